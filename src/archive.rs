@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{absolute, Path};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use crate::args::ShowArgs;
+use crate::formatted_error::FormattedError;
+use crate::generic_utils::strip_colors;
+use crate::keep_temp::KeptFiles;
+use crate::output::{print_output, OutputConfig, SortOrder};
+use crate::temp_files::write_next_to;
+use crate::test_errors::{ExecutionMetrics, TestError};
+use crate::test_outcome::TestOutcome;
+use crate::test_summary::TestSummary;
+
+/// The run as saved in results.json, from which "toster show" prints the results again
+#[derive(Serialize, Deserialize)]
+struct ArchivedRun {
+    generate_mode: bool,
+    stopped_early: bool,
+    /// How long testing took in seconds
+    duration: f64,
+    tests: Vec<TestOutcome>,
+}
+
+/// Saves the results of every test, the errors of the failed ones and the configuration next to the kept files
+fn save_run(kept_files: &KeptFiles, test_summary: &mut TestSummary, stopped_early: bool, duration: Duration, configuration: &str) -> Result<(), String> {
+    let mut errors: HashMap<String, TestError> = test_summary.get_errors().iter()
+        .map(|failed_test| (failed_test.test_name.clone(), failed_test.error().into_owned()))
+        .collect();
+    let mut tests: Vec<TestOutcome> = test_summary.test_results.iter()
+        .map(|result| {
+            let metrics = ExecutionMetrics { memory_kibibytes: result.memory_kibibytes, time: result.time, cpu_time: result.cpu_time };
+            TestOutcome::new(&result.test_name, result.verdict, &metrics, errors.remove(&result.test_name))
+        })
+        .collect();
+    tests.sort_by(|a, b| human_sort::compare(&a.test, &b.test));
+    let run = ArchivedRun {
+        generate_mode: test_summary.generate_mode,
+        stopped_early,
+        duration: duration.as_secs_f64(),
+        tests,
+    };
+    let results = serde_json::to_string_pretty(&run).expect("Failed to serialize the results");
+    kept_files.save_text("results.json", &results)?;
+
+    let errors: Vec<String> = test_summary.get_errors().iter()
+        .map(|failed_test| strip_colors(&failed_test.error().to_string(&failed_test.test_name, &failed_test.metrics)))
+        .collect();
+    kept_files.save_text("errors.txt", &errors.join("\n\n"))?;
+
+    kept_files.save_text("configuration.txt", configuration)
+}
+
+/// Adds every file in `directory` (recursively) for which `include` returns true to the archive,
+/// named by its path relative to `directory`
+fn add_directory(zip: &mut ZipWriter<File>, root: &Path, directory: &Path, include: &impl Fn(&Path) -> bool) -> Result<(), String> {
+    let mut paths = directory.read_dir()
+        .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>())
+        .map_err(|error| format!("Failed to read {}: {}", directory.display(), error))?;
+    paths.sort();
+    for path in paths {
+        let relative = path.strip_prefix(root).expect("The path should be in the archived directory");
+        if !include(relative) {
+            continue;
+        }
+        if path.is_dir() {
+            add_directory(zip, root, &path, include)?;
+            continue;
+        }
+        let name = relative.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let add_file = |zip: &mut ZipWriter<File>| -> io::Result<()> {
+            let mut file = File::open(&path)?;
+            let options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(file.metadata()?.len() >= u32::MAX as u64);
+            zip.start_file(name, options)?;
+            io::copy(&mut file, zip)?;
+            Ok(())
+        };
+        add_file(zip).map_err(|error| format!("Failed to add {} to the archive: {}", path.display(), error))?;
+    }
+    Ok(())
+}
+
+/// Bundles the kept files of the run, along with its results, errors and configuration, into the zip file at `path`.
+///
+/// Only the files of the failed tests are included, unless `include_passed` is set
+pub(crate) fn create_archive(path: &Path, kept_files: &KeptFiles, test_summary: &mut TestSummary, stopped_early: bool, duration: Duration, configuration: &str, include_passed: bool) -> Result<(), String> {
+    save_run(kept_files, test_summary, stopped_early, duration, configuration)?;
+
+    let failed_tests: HashSet<&str> = test_summary.get_errors().iter().map(|failed_test| failed_test.test_name.as_str()).collect();
+    // The files of a test are in tests/<test name>
+    let include = |path: &Path| {
+        include_passed || !path.starts_with("tests") || path.iter().nth(1).is_none_or(|test| test.to_str().is_some_and(|test| failed_tests.contains(test)))
+    };
+
+    let path = absolute(path).map_err(|error| error.to_string())?;
+    let error = |error: io::Error| format!("Failed to create the archive {}: {}", path.display(), error);
+    let archive = write_next_to(&path, &mut io::empty()).map_err(error)?;
+    let mut zip = ZipWriter::new(archive.as_file().try_clone().map_err(error)?);
+    add_directory(&mut zip, kept_files.directory(), kept_files.directory(), &include)?;
+    zip.finish().map_err(|zip_error| error(zip_error.into()))?;
+    archive.persist(&path).map_err(|persist_error| error(persist_error.error))?;
+    Ok(())
+}
+
+/// Reads results.json from an archive created with --archive, or directly from the file if it isn't a zip file
+fn read_results(path: &Path) -> Result<String, String> {
+    if !path.is_file() {
+        return Err(format!("The file {} does not exist", path.display()));
+    }
+    if path.extension().is_none_or(|extension| extension != "zip") {
+        return fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error));
+    }
+
+    let error = |error: String| format!("Failed to read results.json from {}: {}", path.display(), error);
+    let archive = File::open(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let mut archive = ZipArchive::new(archive).map_err(|zip_error| error(zip_error.to_string()))?;
+    let mut results = String::new();
+    archive.by_name("results.json")
+        .map_err(|zip_error| error(zip_error.to_string()))?
+        .read_to_string(&mut results)
+        .map_err(|io_error| error(io_error.to_string()))?;
+    Ok(results)
+}
+
+/// Prints the results of an archived run like they were printed after it finished, without running anything
+pub(crate) fn run_show(args: ShowArgs) -> Result<(), FormattedError> {
+    let results = read_results(&args.archive).map_err(|error| FormattedError::from_str(&error))?;
+    let run: ArchivedRun = serde_json::from_str(&results)
+        .map_err(|error| FormattedError::from_str(&format!("{} isn't a valid archive of a run: {}", args.archive.display(), error)))?;
+
+    let mut test_summary = TestSummary::new(run.generate_mode, run.tests.len());
+    test_summary.start_time = Instant::now().checked_sub(Duration::from_secs_f64(run.duration)).unwrap_or(test_summary.start_time);
+    for outcome in run.tests {
+        let metrics = outcome.metrics();
+        match outcome.error {
+            Some(error) => test_summary.add_test_error(error, outcome.test, &metrics),
+            None => test_summary.add_success(&metrics, &outcome.test),
+        }
+    }
+
+    print_output(run.stopped_early, &mut Some(test_summary), &OutputConfig {
+        details: args.details.then_some(args.sort.unwrap_or(SortOrder::Name)),
+        max_errors: args.max_errors,
+        use_pager: !args.no_pager,
+    });
+    Ok(())
+}
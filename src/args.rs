@@ -1,11 +1,38 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use glob::Pattern;
 use crate::args::ExecuteMode::{Simple};
+use crate::baseline::Baseline;
+use crate::checker::{CheckerProtocol, CheckerVerdict};
+use crate::compiler::{FileKind, Languages};
+use crate::config::ConfigFile;
+use crate::comparator::{CommandComparator, Comparator, ComparatorKind};
+use crate::diff_tool::DiffTool;
+use crate::executor::{Environment, ExecutionLimits, SEED_VARIABLE};
+#[cfg(target_os = "linux")]
+use crate::executor::sandbox::verify_sandbox_support;
+use crate::limits::TestLimits;
+use crate::manifest::Manifest;
+use crate::prepare_input::TagFilter;
+use crate::problem_config::{ProblemLimits, PROBLEM_CONFIG_PATH};
+use crate::output::{ColorMode, OutputConfig, SortOrder, StreamErrors};
+use crate::testing_utils::{LineEndings, WhitespaceRules};
+use crate::units::parse_duration;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use crate::units::parse_memory;
 
 #[derive(Parser, Debug)]
 #[command(name = "Toster", version, about = "A simple-as-toast tester for C++ solutions to competitive programming exercises\nReport issues on the bugtracker at https://github.com/MikolajKolek/toster/issues", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+	#[command(subcommand)]
+	pub command: Option<ToolCommand>,
+
+	/// When the output is colored
+	#[clap(long, value_enum, global = true, default_value = "auto")]
+	pub color: ColorMode,
+
 	/// Input directory
 	#[clap(short, long, value_parser, default_value = "in")]
 	pub r#in: PathBuf,
@@ -14,6 +41,50 @@ pub struct Args {
 	#[clap(long, value_parser, default_value = ".in")]
 	pub in_ext: String,
 
+	/// A glob pattern selecting the input files, used instead of --in-ext (e.g. "*.txt" or "abc[0-9]*.in"). Test names are the file names without their last extension
+	#[clap(long, value_parser, conflicts_with = "in_ext")]
+	pub in_pattern: Option<String>,
+
+	/// A single file containing the inputs of all tests, separated by lines consisting of the --separator, used instead of the input directory. The tests are named 1, 2, 3 and so on, so their output files are e.g. 1.out
+	#[clap(long, value_parser, conflicts_with_all = ["in_ext", "in_pattern"])]
+	pub multitest_file: Option<PathBuf>,
+
+	/// The line separating the tests in the --multitest-file
+	#[clap(long, value_parser, default_value = "===", requires = "multitest_file")]
+	pub separator: String,
+
+	/// A TOML file listing the tests explicitly, used instead of the input directory. Paths are relative to the manifest and the output, time, memory (requires sio2jail) and tags are optional, for example:
+	/// [[tests]]
+	/// name = "sample1"
+	/// input = "samples/1.in"
+	/// output = "samples/1.out"
+	/// time = "2.5s"
+	/// memory = "256M"
+	/// tags = ["samples"]
+	/// Tests without an output are compared with the output files in the output directory, like in the default mode
+	#[clap(long, value_parser, verbatim_doc_comment, conflicts_with_all = ["multitest_file", "in_ext", "in_pattern"])]
+	pub manifest: Option<PathBuf>,
+
+	/// Only runs the tests with any of the given comma-separated tags (e.g. "sample"). The tags of a test are the ones given in the --manifest and the part of its name before the first underscore, e.g. "sample" for the test "sample_1"
+	#[clap(long, value_parser, value_delimiter = ',')]
+	pub only_tags: Vec<String>,
+
+	/// Skips the tests with any of the given comma-separated tags (e.g. "big"), see --only-tags
+	#[clap(long, value_parser, value_delimiter = ',')]
+	pub skip_tags: Vec<String>,
+
+	/// Runs the program once on the input read from stdin instead of running the tests, printing its output, runtime and memory use
+	#[clap(long, action, conflicts_with_all = ["multitest_file", "manifest", "in_pattern", "generate", "model", "checker"])]
+	pub stdin: bool,
+
+	/// A glob pattern for input files that should be skipped (e.g. "*big*"). Can be used multiple times
+	#[clap(long, value_parser)]
+	pub exclude: Vec<String>,
+
+	/// The command line arguments passed to the tested program, split like in a shell, so arguments containing spaces can be quoted. <TEST> gets replaced with the name of the test. The arguments of a single test can be overridden with a file named like the test with the .args extension in the input directory (e.g. test1.args)
+	#[clap(long, value_parser, allow_hyphen_values = true)]
+	pub args: Option<String>,
+
 	/// Output directory
 	#[clap(short, long, value_parser, default_value = "out")]
 	pub out: PathBuf,
@@ -22,94 +93,794 @@ pub struct Args {
 	#[clap(long, value_parser, default_value = ".out")]
 	pub out_ext: String,
 
+	/// Output file extensions that are tried in the given order when a test doesn't have an output file with the --out-ext extension
+	#[clap(long, value_parser, value_delimiter = ',', default_value = ".ans,.a,.expected")]
+	pub fallback_out_ext: Vec<String>,
+
+	/// Removes CRLF line endings and byte order marks from the input files before they're given to the program, and from the output files and the program's output before they're compared, so that test packages made on Windows work the same everywhere. By default only carriage returns at the ends of the compared lines are ignored
+	#[clap(long, action, conflicts_with = "strict_eol")]
+	pub normalize_eol: bool,
+
+	/// Makes carriage returns and byte order marks count as differences when comparing the program's output with the output files
+	#[clap(long, action)]
+	pub strict_eol: bool,
+
+	/// Makes whitespace at the ends of lines and empty lines at the end of the output count as differences when comparing the program's output with the output files using the default lines comparator
+	#[clap(long, action)]
+	pub strict_whitespace: bool,
+
+	/// Marks the program's output as incorrect if it doesn't end with a newline when comparing it with the output files using the default lines comparator
+	#[clap(long, action)]
+	pub require_trailing_newline: bool,
+
+	/// How the program's output is compared with the output files: "exact" requires them to be identical, "lines" (the default) ignores whitespace at the ends of lines and empty lines at the end, "tokens" only compares the whitespace-separated tokens, "float" additionally accepts numbers within --float-epsilon of each other and "unordered" accepts the lines in any order
+	#[clap(long, value_enum)]
+	pub comparator: Option<ComparatorKind>,
+
+	/// The maximum absolute or relative difference between numbers accepted by the float comparator
+	#[clap(long, value_parser, default_value = "1e-6")]
+	pub float_epsilon: f64,
+
+	/// A command used to compare the program's output with the output files instead of the built-in comparators. <EXPECTED> and <ACTUAL> get replaced with the paths of the output files. The output is correct if the command returns 0, otherwise its output is shown as the error
+	#[clap(long, value_parser, conflicts_with = "comparator")]
+	pub comparator_command: Option<String>,
+
+	/// Compares the program's output with the output file while the program is running and kills it as soon as the output is certainly incorrect, which saves time on slow wrong solutions. Only used with the exact, lines and tokens comparators, a single output file and without --diff-tool
+	#[cfg(unix)]
+	#[clap(long, action, conflicts_with = "comparator_command")]
+	pub early_exit: bool,
+
 	/// The input and output directory (sets both -i and -o at once)
 	#[clap(long, value_parser)]
 	pub io: Option<PathBuf>,
 
-	/// The C++ source code or executable of a checker program that verifies if the tested program's output is correct instead of comparing it with given output files
+	/// The C++ source code, executable or script (.py, .sh or starting with a #! line, run through its interpreter) of a checker program that verifies if the tested program's output is correct instead of comparing it with given output files
 	/// The checker must use the following protocol:
-	/// - The checker receives the contents of the input file and the output of the tested program on stdin, separated by a single "\n" character
+	/// - The checker receives the contents of the input file and the output of the tested program on stdin, separated by a single "\n" character. With "--checker-protocol files", it instead receives the paths of the input file, the output of the tested program and the expected output file from the output directory (if it exists) as arguments
 	/// - The checker outputs "C" if the output is correct, or "I <OPTIONAL_DATA>" if the output is incorrect. The optional data can include any information useful for understanding why the output is wrong and will be shown when errors are displayed
+	/// - The name of the test and the paths of its input and expected output files (if it exists) are available to the checker in the TOSTER_TEST_NAME, TOSTER_INPUT_PATH and TOSTER_EXPECTED_OUTPUT_PATH environment variables, along with TOSTER_SEED if --seed is used
+	/// - In generate mode (without --model), the checker verifies every generated output file instead, and the tests whose outputs it rejects are marked as failed and their output files aren't saved. It doesn't get an expected output file then
 	#[clap(short, long, value_parser, verbatim_doc_comment)]
 	pub checker: Option<PathBuf>,
 
-	/// The number of seconds after which a test or generation times out if the program does not return
+	/// How the checker receives the data of a test, see --checker
+	#[clap(long, value_enum, default_value = "stdin", requires = "checker")]
+	pub checker_protocol: CheckerProtocol,
+
+	/// Where the checker reports its verdict: "stdout" (the default) and "stderr" expect "C" or "I <OPTIONAL_DATA>" like described in --checker, while with "exit-code" the output is correct if the checker returns 0 and incorrect if it returns 1 or 2, with its output shown as the error, like with testlib checkers. Any other exit code is a checker error
+	#[clap(long, value_enum, default_value = "stdout", requires = "checker")]
+	pub checker_verdict: CheckerVerdict,
+
+	/// The time after which a test or generation times out if the program does not return. Accepts units (e.g. 1.5s, 750ms, 2m), numbers without a unit are treated as seconds
 	#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
-	#[clap(short, long, value_parser, default_value = "5")]
-	pub timeout: u64,
+	#[clap(short, long, value_parser = parse_duration, default_value = "5")]
+	pub timeout: Duration,
 
-	/// The number of seconds after which a test or generation (or checker if you're using the --checker flag) times out if the program does not return. WARNING: if you're using the sio2jail flag, this timeout will still work based on time measured directly by toster, not time measured by sio2jail
+	/// The time after which a test or generation (or checker if you're using the --checker flag) times out if the program does not return. Accepts units (e.g. 1.5s, 750ms, 2m), numbers without a unit are treated as seconds. WARNING: if you're using the sio2jail flag, this timeout will still work based on time measured directly by toster, not time measured by sio2jail
 	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-	#[clap(short, long, value_parser, default_value = "5")]
-	pub timeout: u64,
+	#[clap(short, long, value_parser = parse_duration, default_value = "5")]
+	pub timeout: Duration,
+
+	/// Makes the timeout apply to the CPU time used by the program instead of the wall time, which is less affected by the load caused by running many tests in parallel. The program is still killed after twice the timeout passes in wall time, so that programs which are waiting don't run forever
+	#[cfg(unix)]
+	#[clap(long, action)]
+	pub cpu_timeout: bool,
+
+	/// How long a program that timed out has after getting SIGTERM to exit (for example flushing its output and running its atexit handlers) before it's killed with SIGKILL, in seconds or with a unit like the --timeout flag. With 0 it's killed right away. Doesn't apply to sio2jail, which kills the program itself
+	#[cfg(unix)]
+	#[clap(long, value_parser = parse_duration, default_value = "0.1", value_name = "DURATION")]
+	pub kill_grace: Duration,
+
+	/// A TOML file overriding the time (in seconds or with a unit, like the --timeout flag) and memory (in KiB or with a unit, like the --memory-limit flag, requires sio2jail) limits of tests whose names match the given patterns, for example:
+	/// [[tests]]
+	/// pattern = "abc1*"
+	/// time = "2.5s"
+	/// memory = "256M"
+	#[clap(long, value_parser, verbatim_doc_comment)]
+	pub limits: Option<PathBuf>,
+
+	/// Don't read the time and memory limits from the config.yml of a sinol-make problem package in the current directory. The limits from it are only used when --limits isn't provided, and --timeout and --memory-limit take precedence over them
+	#[clap(long, action)]
+	pub no_problem_config: bool,
 
-	/// The number of seconds after which compilation times out if it doesn't finish
-	#[clap(long, value_parser, default_value = "10")]
-	pub compile_timeout: u64,
+	/// Whether --timeout was given on the command line, in which case it takes precedence over the time limits from config.yml
+	#[clap(skip)]
+	pub timeout_given: bool,
 
-	/// The command used to compile the file. <IN> gets replaced with the path to the source code file, <OUT> is the executable output location.
-	#[clap(long, value_parser, default_value = "g++ -std=c++20 -O3 -static <IN> -o <OUT>")]
-	pub compile_command: String,
+	/// The time after which toster stops starting new tests and prints the results of the finished ones, like after pressing Ctrl+C. Useful in CI, where jobs must not hang. Accepts the same units as the --timeout flag
+	#[clap(long, value_parser = parse_duration)]
+	pub total_timeout: Option<Duration>,
+
+	#[clap(flatten)]
+	pub compile: CompileOptions,
+
+	/// Compiles and runs the programs (including the checker) inside of the default WSL distribution, so that they behave exactly like on Linux while toster itself runs on Windows. The compile and interpreter commands are run inside of WSL, and environment variables are only passed to the programs if they're listed in WSLENV. The runtime and memory use of the program are measured inside of WSL by a small wrapper compiled there with cc, so that they don't include starting WSL. Can't be used with --precompile-header
+	#[cfg(windows)]
+	#[clap(long, action, conflicts_with_all = ["remote", "precompile_header"])]
+	pub wsl: bool,
 
 	/// Makes toster use sio2jail for measuring program runtime and memory use more accurately. By default limits memory use to 1 GiB. WARNING: enabling this flag can significantly slow down testing
 	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
 	#[clap(short, long, action)]
 	pub sio2jail: bool,
 
-	/// Sets a memory limit (in KiB) for the executed program and enables the sio2jail flag. WARNING: enabling this flag can significantly slow down testing
+	/// Sets a memory limit for the executed program and enables the sio2jail flag. Accepts units (e.g. 256M, 1G), numbers without a unit are treated as KiB. WARNING: enabling this flag can significantly slow down testing
 	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-	#[clap(short, long, value_parser)]
+	#[clap(short, long, value_parser = parse_memory)]
 	pub memory_limit: Option<u64>,
 
+	/// The path to the sio2jail binary used with the --sio2jail and --memory-limit flags. By default sio2jail is looked for at ~/.local/bin/sio2jail, where "toster install-sio2jail" installs it
+	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+	#[clap(long, value_parser)]
+	pub sio2jail_path: Option<PathBuf>,
+
+	/// Sets an environment variable for the tested program, in the KEY=VALUE format. Can be used multiple times
+	#[clap(long, value_parser = parse_environment_variable)]
+	pub env: Vec<(String, String)>,
+
+	/// Sets the TOSTER_SEED environment variable of the tested program and the checker to this number, so that randomized solutions and checkers can use the same seed in every run. Changing it makes the cached verdicts unusable
+	#[clap(long, value_parser)]
+	pub seed: Option<u64>,
+
+	/// Runs the tested program with all of toster's environment variables. By default only PATH (and SYSTEMROOT on Windows) is kept, so that the results don't depend on your environment
+	#[clap(long, action)]
+	pub inherit_env: bool,
+
+	/// Runs the tests one at a time in natural order, printing the verdict of each test as soon as it finishes. Useful for debugging and when running tests in parallel distorts their runtimes
+	#[clap(long, action)]
+	pub sequential: bool,
+
+	/// Pins every testing thread, along with the programs it runs, to its own CPU core. The threads running the checkers get their own cores too. This reduces timing jitter when many tests are run in parallel and makes the measurements more reproducible
+	#[cfg(target_os = "linux")]
+	#[clap(long, action)]
+	pub pin_cpus: bool,
+
+	/// Lets the tested program dump its core when it crashes, and saves the core dump to .toster/cores/<test name> so that it can be loaded into gdb. Only works if the system's core pattern saves core dumps in the working directory of the program (sudo sysctl kernel.core_pattern=core). Tests aren't taken from the cache when it's set
+	#[cfg(target_os = "linux")]
+	#[clap(long, action)]
+	pub core_dumps: bool,
+
+	/// Reruns the failed tests (at most 5 of them) under rr record after testing, so that the failing executions can be replayed in a debugger with "rr replay". The traces are saved in .toster/rr/<test name>
+	#[cfg(target_os = "linux")]
+	#[clap(long, action)]
+	pub record_failures: bool,
+
+	/// Limits the number of processes the tested program can create, protecting against fork bombs. The limit is enforced with RLIMIT_NPROC, which counts all of the processes running as the current user, so it has to be higher than the number of processes you already have running
+	#[cfg(unix)]
+	#[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+	pub max_processes: Option<u64>,
+
+	/// Runs the tested program in a sandbox which only lets it write files inside of the temporary working directory of the test, protecting your files from buggy solutions. Requires a kernel with Landlock enabled
+	#[cfg(target_os = "linux")]
+	#[clap(long, action)]
+	pub sandbox: bool,
+
+	/// What the runtime of the tested program is measured with. "instructions" counts the instructions the program executes using perf_event and treats 2 billion of them as one second, like sio2jail does, which makes the measurements deterministic without having sio2jail installed
+	#[cfg(target_os = "linux")]
+	#[clap(long, value_enum, default_value = "time")]
+	pub measure: Measure,
+
+	/// Runs the tests on another machine over ssh, for example a server that's closer to the one the solutions are judged on. The program is copied to a temporary directory there, every test is run in its own directory inside of it, and the input and output of every test are streamed through a single ssh connection. The runtimes are measured on the remote machine if its date command supports nanoseconds (like GNU date), and otherwise include the latency of the connection. The program has to be able to run on the remote machine, and ssh has to be able to log in there without asking for a password
+	#[clap(long, value_parser, value_name = "USER@HOST")]
+	pub remote: Option<String>,
+
 	/// Makes toster generate output files in the output directory instead of comparing the program's output with the files in the output directory
 	#[clap(short, long, action)]
 	pub generate: bool,
 
+	/// In generate mode, generates the output files using the given model solution (e.g. a slow, but correct brute force) and then tests the main program against them
+	#[clap(long, value_parser, requires = "generate")]
+	pub model: Option<PathBuf>,
+
+	/// Stops before testing if some tests don't have an output file or some output files don't have a matching input file, instead of only printing a warning
+	#[clap(long, action)]
+	pub fail_on_unmatched: bool,
+
+	/// Prints a table with the verdict, runtime and memory use of every test after the summary
+	#[clap(long, action)]
+	pub details: bool,
+
+	/// The order of the tests in the table printed with --details
+	#[clap(long, value_enum, requires = "details")]
+	pub sort: Option<SortOrder>,
+
+	/// An external program used to show the differences between the expected and actual output of failed tests instead of the built-in table (e.g. "diff -u" or "delta"). <EXPECTED> and <ACTUAL> get replaced with the paths of the output files, if they're not used the paths are appended to the command
+	#[clap(long, value_parser)]
+	pub diff_tool: Option<String>,
+
+	/// Opens the diff tool for every failed test one after another after testing, so that interactive tools (e.g. meld or vimdiff) can be used
+	#[clap(long, action, requires = "diff_tool")]
+	pub interactive_diff: bool,
+
+	/// Describes how the outputs of failed tests differ without showing the contents of the output files: the diff table only has the numbers of the differing lines and the positions of the differing characters in them, so the report can be shared without revealing the answers. The messages of checkers are still shown as they are
+	#[clap(long, action, conflicts_with_all = ["diff_tool", "comparator_command"])]
+	pub redact_diff: bool,
+
+	/// Copies the input of the first failed test (by name) to the system clipboard after testing
+	#[clap(long, action)]
+	pub copy_failed: bool,
+
+	/// After testing, shows a menu of the failed tests and opens the input and the error report of the chosen one in $VISUAL or $EDITOR
+	#[clap(long, action)]
+	pub open_failed: bool,
+
+	/// The maximum number of errors that are printed after testing
+	#[clap(long, value_parser)]
+	pub max_errors: Option<usize>,
+
+	/// Prints every failed test above the progress bar as soon as it fails instead of only after testing. By default only the name and verdict of the test are printed, --stream-errors=full prints the whole error with the diff
+	#[clap(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "short")]
+	pub stream_errors: Option<StreamErrors>,
+
+	/// Prints GitHub Actions workflow annotations for compilation errors and failed tests, so that they're shown in pull request checks
+	#[clap(long, action)]
+	pub gha: bool,
+
+	/// Prints the resolved configuration, the compile commands and the tests that would be run with their input and output files and limits, without compiling or running anything
+	#[clap(long, action, conflicts_with = "stdin")]
+	pub dry_run: bool,
+
+	/// Writes the progress of testing to this file descriptor (3 or higher) as JSON lines (with the "started", "test_finished" and "finished" events), for programs wrapping toster
+	#[cfg(unix)]
+	#[clap(long, value_parser, value_name = "FD")]
+	pub progress_fd: Option<i32>,
+
+	/// Prints the error report directly instead of showing it in a pager ($PAGER or less) when it doesn't fit on the screen
+	#[clap(long, action)]
+	pub no_pager: bool,
+
+	/// Saves the runtimes of the successful tests under the given name, so that later runs can be compared with them using --compare-baseline
+	#[clap(long, value_parser)]
+	pub save_baseline: Option<String>,
+
+	/// Compares the runtimes of the tests with the ones saved using --save-baseline and lists the tests that got slower
+	#[clap(long, value_parser)]
+	pub compare_baseline: Option<String>,
+
+	/// The percentage by which a test has to get slower than in the baseline to be reported by --compare-baseline
+	#[clap(long, value_parser, default_value = "20")]
+	pub regression_threshold: f64,
+
+	/// In generate mode, only generates the output files that don't exist yet
+	#[clap(long, action)]
+	pub skip_existing: bool,
+
+	/// In generate mode, overwrites output files that already exist
+	#[clap(long, action)]
+	pub force: bool,
+
+	/// Generates the outputs without first checking if they will fit on the drive, which takes running the program on one of the tests an additional time
+	#[cfg(unix)]
+	#[clap(long, action)]
+	pub no_space_check: bool,
+
+	/// Runs all of the tests instead of reusing the saved verdicts of tests whose program, input, expected output and limits didn't change since they were last run, and compiles the program and the checker even if they didn't change. The verdicts are saved in the .toster/cache directory, where the ones not used for 30 days are deleted. The compiled checkers, and the programs compiled with "toster compile", are saved in .toster/compiled (the files they include aren't taken into account). The verdict cache is never used in generate mode or with the --interactive-diff, --save-baseline and --compare-baseline flags
+	#[clap(long, action)]
+	pub no_cache: bool,
+
+	/// Saves the compiled program and checker with the diagnostics of their compilation, and the stdout, stderr and checker input of every test in this directory, so that failing tests can be rerun manually. Tests aren't taken from the cache when it's set
+	#[clap(long, value_parser, value_name = "DIR")]
+	pub keep_temp: Option<PathBuf>,
+
+	/// Saves the stderr of every test to <DIR>/<test name>.err, cut off after the first MiB. Tests aren't taken from the cache when it's set
+	#[clap(long, value_parser, value_name = "DIR")]
+	pub stderr_dir: Option<PathBuf>,
+
+	/// Bundles everything needed to reproduce the run into this zip file, e.g. to attach it to an issue: the files saved with --keep-temp (only the ones of the failed tests, unless --archive-passed is set), the results of every test as JSON, the errors of the failed tests and the arguments toster was run with. Tests aren't taken from the cache when it's set
+	#[clap(long, value_parser, value_name = "ZIP", conflicts_with_all = ["keep_temp", "stdin", "dry_run"])]
+	pub archive: Option<PathBuf>,
+
+	/// Also includes the outputs and the stderr of the tests that passed in the archive created with --archive
+	#[clap(long, action, requires = "archive")]
+	pub archive_passed: bool,
+
+	/// The directory the temporary files (the compiled program, the outputs of the tests and their working directories) are created in instead of the system's temporary directory, for example a RAM disk or a big scratch drive. On Linux, the outputs are kept in this directory instead of in memory
+	#[clap(long, value_parser, value_name = "DIR")]
+	pub temp_dir: Option<PathBuf>,
+
+	/// Stores the outputs of the tests in files in the temporary directory instead of in memory, which helps when they don't fit in RAM
+	#[cfg(target_os = "linux")]
+	#[clap(long, action)]
+	pub no_memfd: bool,
+
+	/// A TOML file with toster's settings, by default toster.toml in the current directory is used if it exists. The commands compiling or running files with a given extension can be set in it, for example:
+	/// [languages.rs]
+	/// compile = "rustc -O <IN> -o <OUT>"
+	/// [languages.py]
+	/// run = "pypy3"
+	#[clap(long, value_parser, verbatim_doc_comment)]
+	pub config: Option<PathBuf>,
+
+	/// Compiles the tested file even if it doesn't have a C++ extension, instead of detecting whether it's source code or an executable
+	#[clap(long, action, conflicts_with = "treat_as_exe")]
+	pub treat_as_source: bool,
+
+	/// Runs the tested file directly as an executable, instead of detecting whether it's source code or an executable
+	#[clap(long, action)]
+	pub treat_as_exe: bool,
+
+	/// Prints what toster is doing: the compile commands, the resolved paths and how long each stage took. Use -vv to also print the command running every test
+	#[clap(short, long, action = clap::ArgAction::Count)]
+	pub verbose: u8,
+
 	/// The name of the file containing the source code or the executable you want to test
+	#[clap(value_parser, required = true)]
+	pub filename: Option<PathBuf>
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ToolCommand {
+	/// Runs two programs on the same inputs and reports the tests where their outputs differ, without needing any output files
+	Diff(DiffArgs),
+	/// Tests every problem in a contest directory and prints a summary of the results. Every problem is a subdirectory with an "in" directory and a solution named after the problem (e.g. abc/abc.cpp) or being its only source file
+	Contest(ContestArgs),
+	/// Tests every source file in a directory of submissions on the same tests and prints the score of each of them
+	Grade(GradeArgs),
+	/// Reruns a single test (by default the slowest one of the last run) under perf or gprof and prints the functions the program spends the most time in, or under valgrind's massif or DHAT and prints where the program allocates the most memory
+	Profile(ProfileArgs),
+	/// Deletes the output files (including their alternative, hash and compressed versions) that don't have a matching input file anymore, e.g. after some tests were removed
+	CleanOutputs(CleanOutputsArgs),
+	/// Prints the results of a run saved with --archive again, including the errors of the failed tests, without running anything. Accepts the zip file or the results.json file from it
+	Show(ShowArgs),
+	/// Compiles the program (and the checker) without running any tests and saves them in .toster/compiled, so that the next runs don't have to compile them again, e.g. in a pre-commit hook or before a contest
+	Compile(CompileArgs),
+	/// Installs the sio2jail binary bundled with toster, by default at ~/.local/bin/sio2jail
+	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+	InstallSio2jail {
+		/// Where to install sio2jail instead of the default location
+		#[clap(long, value_parser)]
+		path: Option<PathBuf>,
+	},
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ContestArgs {
+	/// The contest directory
+	#[clap(value_parser, default_value = ".")]
+	pub directory: PathBuf,
+
+	/// The arguments toster is run with for every problem, given after "--" (e.g. "toster contest -- --timeout 2")
+	#[clap(last = true)]
+	pub toster_args: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GradeArgs {
+	/// The directory with the submissions
+	#[clap(value_parser)]
+	pub submissions: PathBuf,
+
+	/// Saves the scores to a CSV file
+	#[clap(long, value_parser)]
+	pub csv: Option<PathBuf>,
+
+	/// Saves the scores to a JSON file
+	#[clap(long, value_parser)]
+	pub json: Option<PathBuf>,
+
+	/// Saves a CSV matrix with the verdict and runtime of every submission (rows) on every test (columns), e.g. to check that the solutions meant to time out do so on the right tests
+	#[clap(long, value_parser)]
+	pub matrix: Option<PathBuf>,
+
+	/// The arguments toster is run with for every submission, given after "--" (e.g. "toster grade submissions -- --timeout 2")
+	#[clap(last = true)]
+	pub toster_args: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CleanOutputsArgs {
+	/// Input directory
+	#[clap(short, long, value_parser, default_value = "in")]
+	pub r#in: PathBuf,
+
+	/// Output directory
+	#[clap(short, long, value_parser, default_value = "out")]
+	pub out: PathBuf,
+
+	/// Output file extension
+	#[clap(long, value_parser, default_value = ".out")]
+	pub out_ext: String,
+
+	/// Output file extensions of the outputs that are also deleted, like in "toster -h"
+	#[clap(long, value_parser, value_delimiter = ',', default_value = ".ans,.a,.expected")]
+	pub fallback_out_ext: Vec<String>,
+
+	/// Only lists the output files that would be deleted
+	#[clap(long, action)]
+	pub dry_run: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ShowArgs {
+	/// The zip file created with --archive, or the results.json file from it
+	#[clap(value_parser)]
+	pub archive: PathBuf,
+
+	/// Prints a table with the verdict, runtime and memory use of every test after the summary
+	#[clap(long, action)]
+	pub details: bool,
+
+	/// The order of the tests in the table printed with --details
+	#[clap(long, value_enum, requires = "details")]
+	pub sort: Option<SortOrder>,
+
+	/// The maximum number of errors that are printed
+	#[clap(long, value_parser)]
+	pub max_errors: Option<usize>,
+
+	/// Prints the error report directly instead of showing it in a pager ($PAGER or less) when it doesn't fit on the screen
+	#[clap(long, action)]
+	pub no_pager: bool,
+}
+
+/// How the programs are compiled, shared by the normal runs and "toster compile",
+/// which has to compile them the same way for the runs to reuse them
+#[derive(clap::Args, Debug)]
+pub struct CompileOptions {
+	/// The time after which compilation times out if it doesn't finish. Accepts units (e.g. 1.5s, 750ms, 2m), numbers without a unit are treated as seconds
+	#[clap(long, value_parser = parse_duration, default_value = "10")]
+	pub compile_timeout: Duration,
+
+	/// The command used to compile the file, regardless of its language. <IN> gets replaced with the path to the source code file, <OUT> is the executable output location. Arguments containing spaces can be quoted like in a shell. By default, the command set for the file's extension in the config file is used, or "g++ -std=c++20 -O3 -static <IN> -o <OUT>" for C++
+	#[clap(long, value_parser)]
+	pub compile_command: Option<String>,
+
+	/// The preset used to compile C++ files: "release" (the default, with -O3), "debug" (with -g and the debug mode of the standard library) or "sanitize" (with -g and the address and undefined behavior sanitizers). Custom profiles can be defined in the config file, for example:
+	/// [profiles.fast]
+	/// compile = "g++ -std=c++20 -O2 -march=native <IN> -o <OUT>"
+	#[clap(long, value_parser, conflicts_with = "compile_command", verbatim_doc_comment)]
+	pub profile: Option<String>,
+
+	/// Runs the compile command through the system shell (sh -c, or cmd /C on Windows), allowing pipes, variables and other shell features
+	#[clap(long, action)]
+	pub compile_shell: bool,
+
+	/// Prints the warnings and other messages of the compiler after successful compilation. By default only the number of warnings is shown
+	#[clap(long, action)]
+	pub show_warnings: bool,
+
+	/// Compiles C++ files with a precompiled bits/stdc++.h, which makes compiling solutions including it several times faster. The header is built once for every compile command and kept in the .toster/pch directory
+	#[clap(long, action, conflicts_with = "compile_shell")]
+	pub precompile_header: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CompileArgs {
+	/// The source code of the program
+	#[clap(value_parser)]
+	pub filename: PathBuf,
+
+	/// The source code of the checker, which is compiled too
+	#[clap(short, long, value_parser)]
+	pub checker: Option<PathBuf>,
+
+	#[clap(flatten)]
+	pub compile: CompileOptions,
+
+	/// Compiles the programs inside of the default WSL distribution, for runs using --wsl
+	#[cfg(windows)]
+	#[clap(long, action, conflicts_with = "precompile_header")]
+	pub wsl: bool,
+
+	/// Prints GitHub Actions workflow annotations for compilation errors
+	#[clap(long, action)]
+	pub gha: bool,
+
+	/// A TOML file with toster's settings, see "toster -h"
+	#[clap(long, value_parser)]
+	pub config: Option<PathBuf>,
+
+	/// Prints what toster is doing, e.g. the compile commands
+	#[clap(short, long, action = clap::ArgAction::Count)]
+	pub verbose: u8,
+}
+
+/// The profiler used by "toster profile"
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ProfileTool {
+	Perf,
+	Gprof,
+	/// Valgrind's massif, showing heap snapshots over time
+	Massif,
+	/// Valgrind's DHAT, showing allocation lifetimes and access counts
+	Dhat,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ProfileArgs {
+	/// The source code or executable of the program
 	#[clap(value_parser)]
-	pub filename: PathBuf
+	pub filename: PathBuf,
+
+	/// The name of the test to profile. By default the slowest test of the last run of the program is profiled
+	#[clap(value_parser)]
+	pub test: Option<String>,
+
+	/// The profiler the program is run under
+	#[clap(long, value_enum, default_value = "perf")]
+	pub tool: ProfileTool,
+
+	/// Saves a flamegraph of the program to this SVG file. Requires perf, and inferno or the FlameGraph scripts
+	#[clap(long, value_parser, value_name = "SVG")]
+	pub flamegraph: Option<PathBuf>,
+
+	/// The number of functions or allocation sites printed in the report
+	#[clap(long, value_parser, default_value = "20")]
+	pub top: usize,
+
+	/// Input directory
+	#[clap(short, long, value_parser, default_value = "in")]
+	pub r#in: PathBuf,
+
+	/// Input file extension
+	#[clap(long, value_parser, default_value = ".in")]
+	pub in_ext: String,
+
+	/// The time after which the program times out if it does not return. Accepts units (e.g. 1.5s, 750ms, 2m), numbers without a unit are treated as seconds
+	#[clap(short, long, value_parser = parse_duration, default_value = "60")]
+	pub timeout: Duration,
+
+	/// The time after which compilation times out if it doesn't finish. Accepts the same units as the --timeout flag
+	#[clap(long, value_parser = parse_duration, default_value = "10")]
+	pub compile_timeout: Duration,
+
+	/// The command used to compile the program, with <IN> and <OUT> like in "toster -h". By default "g++ -std=c++20 -O3 -pg <IN> -o <OUT>" is used with gprof and "g++ -std=c++20 -O3 -g -fno-omit-frame-pointer <IN> -o <OUT>" with the other tools
+	#[clap(long, value_parser)]
+	pub compile_command: Option<String>,
+
+	/// A TOML file with toster's settings, see "toster -h"
+	#[clap(long, value_parser)]
+	pub config: Option<PathBuf>,
+
+	/// Prints what toster is doing: the compile commands and the commands running the profiler
+	#[clap(short, long, action = clap::ArgAction::Count)]
+	pub verbose: u8,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+	/// The source code or executable of the first program
+	#[clap(value_parser)]
+	pub first: PathBuf,
+
+	/// The source code or executable of the second program
+	#[clap(value_parser)]
+	pub second: PathBuf,
+
+	/// Input directory
+	#[clap(short, long, value_parser, default_value = "in")]
+	pub r#in: PathBuf,
+
+	/// Input file extension
+	#[clap(long, value_parser, default_value = ".in")]
+	pub in_ext: String,
+
+	/// The time after which a program times out if it does not return. Accepts units (e.g. 1.5s, 750ms, 2m), numbers without a unit are treated as seconds
+	#[clap(short, long, value_parser = parse_duration, default_value = "5")]
+	pub timeout: Duration,
+
+	/// The time after which compilation times out if it doesn't finish. Accepts the same units as the --timeout flag
+	#[clap(long, value_parser = parse_duration, default_value = "10")]
+	pub compile_timeout: Duration,
+
+	/// The command used to compile the files, regardless of their language. <IN> gets replaced with the path to the source code file, <OUT> is the executable output location. Arguments containing spaces can be quoted like in a shell. By default, the command set for the file's extension in the config file is used, or "g++ -std=c++20 -O3 -static <IN> -o <OUT>" for C++
+	#[clap(long, value_parser)]
+	pub compile_command: Option<String>,
+
+	/// The preset used to compile C++ files: "release" (the default, with -O3), "debug" (with -g and the debug mode of the standard library) or "sanitize" (with -g and the address and undefined behavior sanitizers). Custom profiles can be defined in the config file, for example:
+	/// [profiles.fast]
+	/// compile = "g++ -std=c++20 -O2 -march=native <IN> -o <OUT>"
+	#[clap(long, value_parser, conflicts_with = "compile_command", verbatim_doc_comment)]
+	pub profile: Option<String>,
+
+	/// Runs the compile command through the system shell (sh -c, or cmd /C on Windows)
+	#[clap(long, action)]
+	pub compile_shell: bool,
+
+	/// Prints the warnings and other messages of the compiler after successful compilation
+	#[clap(long, action)]
+	pub show_warnings: bool,
+
+	/// Compiles C++ files with a precompiled bits/stdc++.h, which makes compiling solutions including it several times faster. The header is built once for every compile command and kept in the .toster/pch directory
+	#[clap(long, action, conflicts_with = "compile_shell")]
+	pub precompile_header: bool,
+
+	/// The maximum number of differing tests that are printed
+	#[clap(long, value_parser, default_value = "5")]
+	pub max_errors: usize,
+
+	/// A TOML file with toster's settings, see "toster -h"
+	#[clap(long, value_parser)]
+	pub config: Option<PathBuf>,
+
+	/// Prints what toster is doing: the compile commands and the resolved paths. Use -vv to also print the commands running the programs
+	#[clap(short, long, action = clap::ArgAction::Count)]
+	pub verbose: u8,
 }
 
 pub(crate) enum InputConfig {
 	Directory {
 		directory: PathBuf,
-		ext: String,
-	}
+		pattern: Pattern,
+		exclude: Vec<Pattern>,
+		arguments: Option<String>,
+	},
+	/// All of the inputs are in a single file, separated by lines consisting of `separator`
+	MultitestFile {
+		path: PathBuf,
+		separator: String,
+		arguments: Option<String>,
+	},
+	/// The tests are listed in a manifest file
+	Manifest {
+		manifest: Manifest,
+		arguments: Option<String>,
+	},
+	/// The program is run once on the input read from toster's stdin
+	Stdin {
+		arguments: Option<String>,
+	},
+}
+
+/// What the runtime of the tested program is measured with
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum Measure {
+	Time,
+	Instructions,
 }
 
+#[derive(Debug)]
 pub(crate) enum ExecuteMode {
 	Simple,
+	#[cfg(target_os = "linux")]
+	Instructions,
 	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
 	Sio2jail {
 		memory_limit: u64,
-	}
+		path: Option<PathBuf>,
+	},
+	Remote {
+		host: String,
+	},
+	Wsl,
+}
+
+pub(crate) struct ModelConfig {
+	pub(crate) path: PathBuf,
+	pub(crate) output_directory: PathBuf,
+	pub(crate) output_ext: String,
+	pub(crate) existing_outputs: ExistingOutputs,
+}
+
+/// What to do with output files that already exist in generate mode
+#[derive(Clone, Copy)]
+pub(crate) enum ExistingOutputs {
+	Error,
+	Skip,
+	Overwrite,
+}
+
+/// The checker verifying the output files in generate mode
+pub(crate) struct OutputChecker {
+	pub(crate) path: PathBuf,
+	pub(crate) protocol: CheckerProtocol,
+	pub(crate) verdict: CheckerVerdict,
 }
 
 pub(crate) enum ActionType {
 	Generate {
 		output_directory: PathBuf,
 		output_ext: String,
+		existing_outputs: ExistingOutputs,
+		checker: Option<OutputChecker>,
 	},
 	SimpleCompare {
 		output_directory: PathBuf,
 		output_ext: String,
+		fallback_output_exts: Vec<String>,
+		fail_on_unmatched: bool,
+		diff_tool: Option<DiffTool>,
+		comparator: Box<dyn Comparator>,
 	},
 	Checker {
 		path: PathBuf,
+		protocol: CheckerProtocol,
+		verdict: CheckerVerdict,
+		/// Where the expected output files given to the checker with the files protocol are looked for
+		output_directory: PathBuf,
+		output_ext: String,
+		fallback_output_exts: Vec<String>,
 	}
 }
 
 pub(crate) struct ParsedConfig {
 	pub(crate) source_path: PathBuf,
-	pub(crate) compile_command: String,
+	pub(crate) source_kind: FileKind,
+	pub(crate) languages: Languages,
+	pub(crate) compile_shell: bool,
+	pub(crate) show_warnings: bool,
+	pub(crate) precompile_header: bool,
+	/// Whether the programs are compiled and run inside of WSL
+	pub(crate) wsl: bool,
 	pub(crate) compile_timeout: Duration,
+	pub(crate) total_timeout: Option<Duration>,
 	pub(crate) execute_timeout: Duration,
+	pub(crate) test_limits: TestLimits,
+	/// Whether any of the limits were read from config.yml
+	pub(crate) problem_config_used: bool,
 	pub(crate) input: InputConfig,
+	pub(crate) tag_filter: TagFilter,
+	pub(crate) line_endings: LineEndings,
 	pub(crate) execute_mode: ExecuteMode,
+	pub(crate) environment: Environment,
+	/// The seed passed to the tested program and the checker in TOSTER_SEED
+	pub(crate) seed: Option<u64>,
+	pub(crate) sequential: bool,
+	/// What is printed about the failed tests while testing
+	pub(crate) stream_errors: Option<StreamErrors>,
+	#[cfg(target_os = "linux")]
+	pub(crate) pin_cpus: bool,
+	/// Whether the outputs are only generated if they will probably fit on the drive
+	#[cfg(unix)]
+	pub(crate) space_check: bool,
+	/// Whether the core dumps of crashed programs are saved
+	#[cfg(target_os = "linux")]
+	pub(crate) core_dumps: bool,
+	/// Whether the failed tests are rerun under rr record after testing
+	#[cfg(target_os = "linux")]
+	pub(crate) record_failures: bool,
+	/// Whether the program's output is compared while it's running, killing it on the first difference
+	#[cfg(unix)]
+	pub(crate) early_exit: bool,
 	pub(crate) action_type: ActionType,
+	/// The model solution generating the output files before testing
+	pub(crate) model: Option<ModelConfig>,
+	pub(crate) output: OutputConfig,
+	pub(crate) save_baseline: Option<String>,
+	/// The name of the baseline the runtimes are compared with, along with its loaded contents
+	pub(crate) compare_baseline: Option<(String, Baseline)>,
+	pub(crate) regression_threshold: f64,
+	pub(crate) use_cache: bool,
+	/// Whether the program compiled with "toster compile" and the compiled checker are reused from .toster/compiled if they didn't change
+	pub(crate) reuse_compiled: bool,
+	/// The directory the files created while testing are saved in
+	pub(crate) keep_temp: Option<PathBuf>,
+	/// The zip file the run is archived in
+	pub(crate) archive: Option<PathBuf>,
+	/// Whether the files of the tests that passed are archived too
+	pub(crate) archive_passed: bool,
+	/// The directory the stderr of every test is saved in
+	pub(crate) stderr_dir: Option<PathBuf>,
+	/// The directory temporary files are created in, if it isn't the system's temporary directory
+	pub(crate) temp_dir: Option<PathBuf>,
+	/// Whether the outputs are stored in temporary files instead of memfiles
+	#[cfg(target_os = "linux")]
+	pub(crate) no_memfd: bool,
+	/// Whether the diffs hide the contents of the output files
+	pub(crate) redact_diff: bool,
+	pub(crate) copy_failed: bool,
+	pub(crate) open_failed: bool,
+	/// Whether GitHub Actions annotations are printed
+	pub(crate) gha: bool,
+	pub(crate) dry_run: bool,
+}
+
+pub(crate) fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+	Pattern::new(pattern).map_err(|error| format!("The pattern \"{}\" is invalid: {}", pattern, error))
 }
 
-fn verify_compile_command(command: &str) -> Result<(), String> {
+fn parse_environment_variable(variable: &str) -> Result<(String, String), String> {
+	match variable.split_once('=') {
+		Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+		_ => Err(format!("\"{}\" is not in the KEY=VALUE format", variable)),
+	}
+}
+
+pub(crate) fn verify_compile_command(command: &str, shell: bool) -> Result<(), String> {
+	if !shell && shlex::split(command).is_none_or(|split| split.is_empty()) {
+		return Err(format!("The compile command is invalid:\n\"{}\" has unmatched quotes or is empty\nRead \"toster -h\" for more info", command));
+	}
 	let message = format!(
 		"The compile command is invalid:\n{}\nRead \"toster -h\" for more info",
 		match (command.contains("<IN>"), command.contains("<OUT>")) {
@@ -126,7 +897,8 @@ impl TryFrom<Args> for ParsedConfig {
 	type Error = String;
 
 	fn try_from(args: Args) -> Result<Self, String> {
-		if !args.filename.is_file() {
+		let filename = args.filename.expect("The filename is required when there is no subcommand");
+		if !filename.is_file() {
 			return Err("The provided file does not exist".to_string());
 		}
 
@@ -138,45 +910,309 @@ impl TryFrom<Args> for ParsedConfig {
 				(io.clone(), io)
 			},
 			None => {
-				if !args.r#in.is_dir() {
+				if args.multitest_file.is_none() && args.manifest.is_none() && !args.stdin && !args.r#in.is_dir() {
 					return Err("The input directory does not exist".to_string());
 				}
 				(args.r#in, args.out)
 			}
 		};
+		if args.multitest_file.as_ref().is_some_and(|path| !path.is_file()) {
+			return Err("The provided multitest file does not exist".to_string());
+		}
+		let manifest = match &args.manifest {
+			Some(path) if !path.is_file() => return Err("The provided manifest file does not exist".to_string()),
+			Some(path) => Some(Manifest::load(path)?),
+			None => None,
+		};
+		if args.multitest_file.is_some() && args.separator.trim().is_empty() {
+			return Err("The multitest file separator can't be empty".to_string());
+		}
+
+		let config_file = ConfigFile::load(args.config.as_deref())?;
+		let languages = Languages::new(&config_file, args.compile.compile_command, args.compile.profile.as_deref(), args.compile.compile_shell)?;
+
+		for name in args.save_baseline.iter().chain(args.compare_baseline.iter()) {
+			Baseline::verify_name(name)?;
+		}
+		// The baseline is loaded before testing, so that a missing one is reported right away
+		let compare_baseline = args.compare_baseline.clone()
+			.map(|name| Baseline::load(&name).map(|baseline| (name, baseline)))
+			.transpose()?;
+		if args.regression_threshold < 0.0 {
+			return Err("The regression threshold can't be negative".to_string());
+		}
+
+		// With a model solution the program is tested against the generated outputs, like without --generate
+		let generate_only = args.generate && args.model.is_none();
+		if args.fail_on_unmatched && (generate_only || args.checker.is_some()) {
+			return Err("The --fail-on-unmatched flag can't be used with the --generate or --checker flags".to_string());
+		}
+		if args.temp_dir.as_ref().is_some_and(|directory| !directory.is_dir()) {
+			return Err("The temporary directory doesn't exist".to_string());
+		}
+		if args.diff_tool.as_ref().is_some_and(|command| shlex::split(command).is_none_or(|split| split.is_empty())) {
+			return Err("The diff tool command has unmatched quotes or is empty".to_string());
+		}
+		if (args.comparator.is_some() || args.comparator_command.is_some()) && (generate_only || args.checker.is_some()) {
+			return Err("The --comparator and --comparator-command flags can't be used with the --generate or --checker flags".to_string());
+		}
+		if args.comparator_command.as_ref().is_some_and(|command| shlex::split(command).is_none_or(|split| split.is_empty())) {
+			return Err("The comparator command has unmatched quotes or is empty".to_string());
+		}
+		if args.float_epsilon < 0.0 {
+			return Err("The float epsilon can't be negative".to_string());
+		}
+		#[cfg(unix)]
+		if args.early_exit && (generate_only || args.checker.is_some()) {
+			return Err("The --early-exit flag can't be used with the --generate or --checker flags".to_string());
+		}
+		if args.diff_tool.is_some() && (generate_only || args.checker.is_some()) {
+			return Err("The --diff-tool flag can't be used with the --generate or --checker flags".to_string());
+		}
+		if !args.generate && (args.skip_existing || args.force) {
+			return Err("The --skip-existing and --force flags can only be used with the --generate flag".to_string());
+		}
+
+		#[cfg(target_os = "linux")]
+		if args.sandbox {
+			verify_sandbox_support()?;
+		}
+		#[cfg(target_os = "linux")]
+		if args.record_failures && which::which("rr").is_err() {
+			return Err("rr was not found, install it to record the failed tests with --record-failures".to_string());
+		}
+		#[cfg(windows)]
+		if args.wsl && which::which("wsl").is_err() {
+			return Err("WSL was not found, install it with \"wsl --install\"".to_string());
+		}
+
+		let execute_mode = {
+			#[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+				if let Some(memory_limit) = args.memory_limit {
+					ExecuteMode::Sio2jail { memory_limit, path: args.sio2jail_path }
+				} else if args.sio2jail {
+					ExecuteMode::Sio2jail { memory_limit: 1024 * 1024, path: args.sio2jail_path }
+				} else if args.sio2jail_path.is_some() {
+					return Err("The --sio2jail-path flag can only be used with the --sio2jail or --memory-limit flags".to_string());
+				} else {
+					Simple
+				}
+			}
+			#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+			Simple
+		};
+		#[cfg(target_os = "linux")]
+		let execute_mode = match (args.measure, execute_mode) {
+			(Measure::Time, execute_mode) => execute_mode,
+			(Measure::Instructions, Simple) => ExecuteMode::Instructions,
+			(Measure::Instructions, _) => {
+				return Err("Instructions can't be counted with sio2jail, which measures the runtime itself".to_string());
+			}
+		};
+
+		let execute_mode = match (args.remote, execute_mode) {
+			(Some(host), Simple) => ExecuteMode::Remote { host },
+			(Some(_), _) => {
+				return Err("The --remote flag can't be used with sio2jail or --measure instructions".to_string());
+			}
+			(None, execute_mode) => execute_mode,
+		};
+		#[cfg(windows)]
+		let wsl = args.wsl;
+		#[cfg(not(windows))]
+		let wsl = false;
+		// --wsl conflicts with --remote, so the programs would otherwise be run with the simple executor
+		let execute_mode = if wsl { ExecuteMode::Wsl } else { execute_mode };
+		#[cfg(unix)]
+		if args.cpu_timeout && !matches!(execute_mode, Simple) {
+			return Err("The --cpu-timeout flag can't be used with sio2jail, --measure instructions or --remote, which measure the runtime themselves".to_string());
+		}
+		if matches!(execute_mode, ExecuteMode::Remote { .. }) {
+			#[cfg(unix)]
+			if args.max_processes.is_some() {
+				return Err("The --max-processes flag can't be used with --remote".to_string());
+			}
+			#[cfg(target_os = "linux")]
+			if args.sandbox {
+				return Err("The --sandbox flag can't be used with --remote".to_string());
+			}
+		}
+
+		let global_limits = ExecutionLimits {
+			timeout: args.timeout,
+			memory_limit: match execute_mode {
+				Simple => None,
+				#[cfg(target_os = "linux")]
+				ExecuteMode::Instructions => None,
+				#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+				ExecuteMode::Sio2jail { memory_limit, .. } => Some(memory_limit),
+				ExecuteMode::Remote { .. } => None,
+				ExecuteMode::Wsl => None,
+			},
+			#[cfg(unix)]
+			max_processes: args.max_processes,
+			#[cfg(unix)]
+			cpu_timeout: args.cpu_timeout,
+			#[cfg(unix)]
+			kill_grace: args.kill_grace,
+			#[cfg(target_os = "linux")]
+			sandbox: args.sandbox,
+		};
+		let problem_config = Path::new(PROBLEM_CONFIG_PATH);
+		let mut problem_config_used = false;
+		let test_limits = match args.limits {
+			Some(path) => {
+				if !path.is_file() {
+					return Err("The provided limits file does not exist".to_string());
+				}
+				TestLimits::load(&path, global_limits)?
+			}
+			None if !args.no_problem_config && problem_config.is_file() => {
+				let extension = filename.extension().and_then(|extension| extension.to_str());
+				let mut problem_limits = ProblemLimits::load(problem_config, extension)?;
+				// The limits given on the command line take precedence over the ones from the problem package
+				if args.timeout_given {
+					problem_limits.time_limit = None;
+					problem_limits.group_time_limits.clear();
+				}
+				#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+				if args.memory_limit.is_some() {
+					problem_limits.memory_limit = None;
+					problem_limits.group_memory_limits.clear();
+				}
+				let test_limits = TestLimits::from_problem(problem_limits, global_limits, global_limits.memory_limit.is_some())?;
+				problem_config_used = test_limits.has_overrides();
+				test_limits
+			}
+			None => TestLimits::new(global_limits),
+		};
+		if test_limits.has_memory_limits() && global_limits.memory_limit.is_none() {
+			return Err("The limits file sets memory limits, which can only be enforced with the --sio2jail flag".to_string());
+		}
+		if manifest.as_ref().is_some_and(Manifest::has_memory_limits) && global_limits.memory_limit.is_none() {
+			return Err("The manifest sets memory limits, which can only be enforced with the --sio2jail flag".to_string());
+		}
 
-		verify_compile_command(&args.compile_command)?;
+		// These need the actual output and runtime of every test, which aren't cached
+		let use_cache = !args.no_cache && !generate_only && !args.interactive_diff
+			&& args.save_baseline.is_none() && args.compare_baseline.is_none() && args.keep_temp.is_none() && args.stderr_dir.is_none()
+			&& args.archive.is_none();
+		#[cfg(target_os = "linux")]
+		let use_cache = use_cache && !args.core_dumps;
+
+		if args.generate && output_directory.exists() && !output_directory.is_dir() {
+			return Err("The output path is not a directory".to_string())
+		}
+		let existing_outputs = match (args.skip_existing, args.force) {
+			(true, true) => {
+				return Err("You can't have the --skip-existing and --force flags on at the same time".to_string())
+			},
+			(true, false) => ExistingOutputs::Skip,
+			(false, true) => ExistingOutputs::Overwrite,
+			(false, false) => ExistingOutputs::Error,
+		};
+
+		let line_endings = match (args.normalize_eol, args.strict_eol) {
+			(true, _) => LineEndings::Normalize,
+			(false, true) => LineEndings::Strict,
+			(false, false) => LineEndings::Lenient,
+		};
 
 		Ok(ParsedConfig {
-			source_path: args.filename,
-			compile_timeout: Duration::from_secs(args.compile_timeout),
-			execute_timeout: Duration::from_secs(args.timeout),
-			compile_command: args.compile_command,
-			input: InputConfig::Directory {
-				directory: input_directory,
-				ext: args.in_ext,
+			source_path: filename,
+			source_kind: match (args.treat_as_source, args.treat_as_exe) {
+				(true, _) => FileKind::Source,
+				(false, true) => FileKind::Executable,
+				(false, false) => FileKind::Detect,
+			},
+			compile_timeout: args.compile.compile_timeout,
+			execute_timeout: args.timeout,
+			test_limits,
+			problem_config_used,
+			languages,
+			compile_shell: args.compile.compile_shell,
+			show_warnings: args.compile.show_warnings,
+			precompile_header: args.compile.precompile_header,
+			wsl,
+			tag_filter: TagFilter {
+				only: args.only_tags,
+				skip: args.skip_tags,
+			},
+			input: match args.multitest_file {
+				_ if args.stdin => InputConfig::Stdin { arguments: args.args },
+				_ if manifest.is_some() => InputConfig::Manifest {
+					manifest: manifest.expect("The manifest was checked to be loaded"),
+					arguments: args.args,
+				},
+				Some(path) => InputConfig::MultitestFile {
+					path,
+					separator: args.separator,
+					arguments: args.args,
+				},
+				None => InputConfig::Directory {
+					directory: input_directory,
+					pattern: match args.in_pattern {
+						Some(pattern) => parse_pattern(&pattern)?,
+						None => parse_pattern(&format!("*{}", Pattern::escape(&args.in_ext)))?,
+					},
+					exclude: args.exclude.iter()
+						.map(|pattern| parse_pattern(pattern))
+						.collect::<Result<Vec<Pattern>, String>>()?,
+					arguments: args.args,
+				},
 			},
 
-			action_type: match (args.generate, args.checker) {
-				(true, Some(_)) => {
-					return Err("You can't have the --generate and --checker flags on at the same time".to_string())
+			model: match &args.model {
+				Some(path) => {
+					if !path.is_file() {
+						return Err("The provided model solution file does not exist".to_string());
+					}
+					Some(ModelConfig {
+						path: path.clone(),
+						output_directory: output_directory.clone(),
+						output_ext: args.out_ext.clone(),
+						existing_outputs,
+					})
 				},
-				(true, None) => {
-					if output_directory.exists() && !output_directory.is_dir() {
-						return Err("The output path is not a directory".to_string())
+				None => None,
+			},
+
+			action_type: match (generate_only, args.checker) {
+				(true, checker_path) => {
+					if checker_path.as_ref().is_some_and(|path| !path.is_file()) {
+						return Err("The provided checker file does not exist".to_string());
 					}
 					ActionType::Generate {
 						output_directory,
 						output_ext: args.out_ext,
+						existing_outputs,
+						checker: checker_path.map(|path| OutputChecker {
+							path,
+							protocol: args.checker_protocol,
+							verdict: args.checker_verdict,
+						}),
 					}
 				},
 				(false, None) => {
-					if !output_directory.is_dir() {
+					if !args.generate && !args.stdin && !output_directory.is_dir() {
 						return Err("The output directory does not exist".to_string())
 					}
 					ActionType::SimpleCompare {
 						output_directory,
+						// The model solution only generates files with the main extension
+						fallback_output_exts: args.fallback_out_ext.into_iter()
+							.filter(|ext| !ext.is_empty() && *ext != args.out_ext && !args.generate)
+							.collect(),
 						output_ext: args.out_ext,
+						fail_on_unmatched: args.fail_on_unmatched,
+						diff_tool: args.diff_tool.map(|command| DiffTool::new(command, args.interactive_diff)),
+						comparator: match args.comparator_command {
+							Some(command) => Box::new(CommandComparator::new(&command)),
+							None => args.comparator.unwrap_or(ComparatorKind::Lines).create(line_endings, WhitespaceRules {
+								strict: args.strict_whitespace,
+								require_trailing_newline: args.require_trailing_newline,
+							}, args.float_epsilon),
+						},
 					}
 				},
 				(false, Some(checker_path)) => {
@@ -185,23 +1221,61 @@ impl TryFrom<Args> for ParsedConfig {
 					}
 					ActionType::Checker {
 						path: checker_path,
+						protocol: args.checker_protocol,
+						verdict: args.checker_verdict,
+						output_directory,
+						fallback_output_exts: args.fallback_out_ext.into_iter()
+							.filter(|ext| !ext.is_empty() && *ext != args.out_ext)
+							.collect(),
+						output_ext: args.out_ext,
 					}
 				}
 			},
 
-			execute_mode: {
-				#[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
-					if let Some(memory_limit) = args.memory_limit {
-						ExecuteMode::Sio2jail { memory_limit }
-					} else if args.sio2jail {
-						ExecuteMode::Sio2jail { memory_limit: 1024 * 1204 }
-					} else {
-						Simple
-					}
-				}
-				#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
-				Simple
-			}
+			execute_mode,
+			environment: Environment {
+				inherit: args.inherit_env,
+				variables: args.env.into_iter()
+					.chain(args.seed.map(|seed| (SEED_VARIABLE.to_string(), seed.to_string())))
+					.collect(),
+			},
+			seed: args.seed,
+			total_timeout: args.total_timeout,
+			line_endings,
+			sequential: args.sequential,
+			stream_errors: args.stream_errors,
+			#[cfg(target_os = "linux")]
+			pin_cpus: args.pin_cpus,
+			#[cfg(unix)]
+			space_check: !args.no_space_check,
+			#[cfg(target_os = "linux")]
+			core_dumps: args.core_dumps,
+			#[cfg(target_os = "linux")]
+			record_failures: args.record_failures,
+			#[cfg(unix)]
+			early_exit: args.early_exit,
+			output: OutputConfig {
+				details: args.details.then_some(args.sort.unwrap_or(SortOrder::Name)),
+				max_errors: args.max_errors,
+				use_pager: !args.no_pager,
+			},
+			save_baseline: args.save_baseline,
+			compare_baseline,
+			regression_threshold: args.regression_threshold,
+			use_cache,
+			reuse_compiled: !args.no_cache,
+			keep_temp: args.keep_temp,
+			archive: args.archive,
+			archive_passed: args.archive_passed,
+			stderr_dir: args.stderr_dir,
+			temp_dir: args.temp_dir,
+			#[cfg(target_os = "linux")]
+			no_memfd: args.no_memfd,
+			redact_diff: args.redact_diff,
+			copy_failed: args.copy_failed,
+			open_failed: args.open_failed,
+			gha: args.gha,
+			dry_run: args.dry_run,
 		})
 	}
 }
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::test_summary::TestSummary;
+
+/// The runtimes of the successful tests of a run, saved with --save-baseline
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Baseline {
+    /// Test runtimes in seconds
+    tests: BTreeMap<String, f64>,
+}
+
+impl Baseline {
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from(DATA_DIRECTORY).join("baselines").join(format!("{}.json", name))
+    }
+
+    pub(crate) fn verify_name(name: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(format!("\"{}\" is not a valid baseline name", name));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_summary(test_summary: &TestSummary) -> Self {
+        Baseline {
+            tests: test_summary.successful_test_times()
+                .map(|(test_name, time)| (test_name.to_string(), time.as_secs_f64()))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn save(&self, name: &str) -> Result<(), String> {
+        let path = Self::path(name);
+        fs::create_dir_all(path.parent().expect("The baseline path should have a parent"))
+            .map_err(|error| format!("Failed to create the baseline directory: {}", error))?;
+        let contents = serde_json::to_string_pretty(self).expect("Failed to serialize the baseline");
+        fs::write(&path, contents)
+            .map_err(|error| format!("Failed to save the baseline to {}: {}", path.display(), error))
+    }
+
+    pub(crate) fn load(name: &str) -> Result<Self, String> {
+        let path = Self::path(name);
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| format!("The baseline \"{}\" does not exist, save it first using --save-baseline", name))?;
+        serde_json::from_str(&contents)
+            .map_err(|error| format!("The baseline file {} is invalid: {}", path.display(), error))
+    }
+
+    /// Formats a comparison of this (older) baseline with `current`,
+    /// listing the tests that got slower by more than `threshold_percent`
+    pub(crate) fn compare(&self, name: &str, current: &Baseline, threshold_percent: f64) -> String {
+        let common: Vec<(&String, f64, f64)> = current.tests.iter()
+            .filter_map(|(test_name, new_time)| {
+                self.tests.get(test_name).map(|old_time| (test_name, *old_time, *new_time))
+            })
+            .collect();
+        if common.is_empty() {
+            return format!("Baseline \"{}\" has no successful tests in common with this run", name).yellow().to_string();
+        }
+
+        let old_total: f64 = common.iter().map(|(_, old_time, _)| old_time).sum();
+        let new_total: f64 = common.iter().map(|(_, _, new_time)| new_time).sum();
+        let mut lines = vec![format!(
+            "Compared to baseline \"{}\" ({} common tests): total runtime {:.3}s -> {:.3}s ({})",
+            name, common.len(), old_total, new_total, format_change(old_total, new_total),
+        )];
+
+        let mut regressions: Vec<&(&String, f64, f64)> = common.iter()
+            .filter(|(_, old_time, new_time)| *new_time > old_time * (1.0 + threshold_percent / 100.0))
+            .collect();
+        regressions.sort_by(|a, b| human_sort::compare(a.0, b.0));
+        if regressions.is_empty() {
+            lines.push(format!("No tests got more than {}% slower", threshold_percent).green().to_string());
+        } else {
+            lines.push(format!("Tests that got more than {}% slower:", threshold_percent).red().to_string());
+            for (test_name, old_time, new_time) in regressions {
+                lines.push(format!(
+                    "{}: {:.3}s -> {:.3}s ({})",
+                    test_name, old_time, new_time, format_change(*old_time, *new_time),
+                ).red().to_string());
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+fn format_change(old_time: f64, new_time: f64) -> String {
+    if old_time == 0.0 {
+        return format!("{:+.3}s", new_time - old_time);
+    }
+    format!("{:+.1}%", (new_time - old_time) / old_time * 100.0)
+}
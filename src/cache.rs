@@ -0,0 +1,147 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::prepare_input::Test;
+use crate::test_errors::{ExecutionMetrics, TestError};
+
+/// Cache entries which weren't used for this long are deleted
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The verdicts of previously run tests, saved in `.toster/cache` so that
+/// tests whose program, input, expected output and limits didn't change aren't run again
+pub(crate) struct ResultCache {
+    directory: PathBuf,
+    /// The hash of everything shared by all of the tests, like the executables and the execute mode
+    run_hash: Vec<u8>,
+    expected_outputs: Option<ExpectedOutputs>,
+    hits: AtomicUsize,
+}
+
+/// Where the expected output files of the tests are looked for
+pub(crate) struct ExpectedOutputs {
+    pub(crate) directory: PathBuf,
+    pub(crate) ext: String,
+    pub(crate) fallback_exts: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedResult {
+    metrics: ExecutionMetrics,
+    error: Option<TestError>,
+}
+
+impl ResultCache {
+    /// Creates a cache for the given executables, `settings` should describe
+    /// all of the other configuration that influences the verdicts
+    pub(crate) fn new(executables: &[&Path], settings: &str, expected_outputs: Option<ExpectedOutputs>) -> Result<Self, String> {
+        let mut hasher = Sha256::new();
+        for executable in executables {
+            let contents = fs::read(executable)
+                .map_err(|error| format!("Failed to read {} for caching the results: {}", executable.display(), error))?;
+            hash_part(&mut hasher, &contents);
+        }
+        hash_part(&mut hasher, settings.as_bytes());
+
+        let directory = PathBuf::from(DATA_DIRECTORY).join("cache");
+        evict_unused(&directory);
+        Ok(ResultCache {
+            directory,
+            run_hash: hasher.finalize().to_vec(),
+            expected_outputs,
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns the name of the cache entry of the test
+    pub(crate) fn key(&self, test: &Test) -> String {
+        let input_hash = hash_file(&mut test.input_source.get_file()).expect("Failed to read input file");
+
+        let mut hasher = Sha256::new();
+        hash_part(&mut hasher, &self.run_hash);
+        hash_part(&mut hasher, &input_hash);
+        hash_part(&mut hasher, test.arguments.join("\0").as_bytes());
+        hash_part(&mut hasher, format!("{:?}", test.limits).as_bytes());
+        if let Some(expected) = &self.expected_outputs {
+            let paths = test.output_files(&expected.directory, &expected.ext, &expected.fallback_exts);
+            for path in paths {
+                // The test fails if the output file is missing, which is a verdict worth caching too
+                let output_hash = match File::open(path) {
+                    Ok(mut file) => hash_file(&mut file).expect("Failed to read output file"),
+                    Err(_) => Sha256::new().finalize().to_vec(),
+                };
+                hash_part(&mut hasher, &output_hash);
+            }
+        }
+
+        hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<(ExecutionMetrics, Result<(), TestError>)> {
+        let path = self.directory.join(key);
+        let contents = fs::read_to_string(&path).ok()?;
+        // Invalid entries, e.g. ones saved by an older version of toster, are treated as missing
+        let cached: CachedResult = serde_json::from_str(&contents).ok()?;
+        // The modification time marks when the entry was last used, failing to update it only makes it evicted sooner
+        let _ = File::options().write(true).open(&path).and_then(|file| file.set_modified(SystemTime::now()));
+        self.hits.fetch_add(1, Relaxed);
+        Some((cached.metrics, cached.error.map_or(Ok(()), Err)))
+    }
+
+    pub(crate) fn save(&self, key: &str, metrics: &ExecutionMetrics, result: Result<(), TestError>) -> Result<(), TestError> {
+        let cached = CachedResult { metrics: *metrics, error: result.err() };
+        // Failing to save the result only means that the test will be run again next time
+        if fs::create_dir_all(&self.directory).is_ok() {
+            let contents = serde_json::to_string(&cached).expect("Failed to serialize the test result");
+            let _ = fs::write(self.directory.join(key), contents);
+        }
+        cached.error.map_or(Ok(()), Err)
+    }
+
+    /// The number of tests whose results were taken from the cache
+    pub(crate) fn hits(&self) -> usize {
+        self.hits.load(Relaxed)
+    }
+}
+
+/// Deletes the cache entries which weren't used for `MAX_ENTRY_AGE`, so that the cache doesn't grow forever
+fn evict_unused(directory: &Path) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let unused_for = entry.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        if unused_for.is_some_and(|unused_for| unused_for > MAX_ENTRY_AGE) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Hashes the file without loading it into memory, since the inputs and outputs can be huge
+fn hash_file(file: &mut File) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize().to_vec());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Hashes the data prefixed with its length, so that the boundaries between parts are unambiguous
+fn hash_part(hasher: &mut Sha256, data: &[u8]) {
+    hasher.update((data.len() as u64).to_le_bytes());
+    hasher.update(data);
+}
@@ -1,28 +1,90 @@
+use std::fs;
 use std::fs::File;
 use std::io::{read_to_string, Seek, Write};
-use std::path::PathBuf;
+use std::path::Path;
 use std::io;
 use std::time::Duration;
-use colored::Colorize;
+use clap::ValueEnum;
+use crate::compiler::Executable;
 use crate::executor::simple::SimpleExecutor;
-use crate::executor::test_to_temp;
+use crate::executor::{Environment, ExecutionLimits, SEED_VARIABLE};
 use crate::prepare_input::TestInputSource;
 use crate::temp_files::create_temp_file;
-use crate::test_errors::TestError;
+use crate::test_errors::{OutputDifference, TestError};
 use crate::test_errors::ExecutionError::IncorrectCheckerFormat;
 use crate::test_errors::TestError::CheckerError;
+use crate::verbose::log;
+
+/// How the checker receives the data of a test
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum CheckerProtocol {
+    /// The input and the program's output are given on stdin, separated by a single "\n" character
+    Stdin,
+    /// The paths of the input file, the program's output and the expected output file (if it exists) are given as arguments
+    Files,
+}
+
+/// How the checker reports its verdict
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum CheckerVerdict {
+    /// "C" or "I <OPTIONAL_DATA>" on stdout
+    Stdout,
+    /// "C" or "I <OPTIONAL_DATA>" on stderr
+    Stderr,
+    /// The exit code, 0 if the output is correct and 1 or 2 if it isn't, with the checker's output shown as the error.
+    /// Any other exit code is a checker error
+    ExitCode,
+}
 
 pub(crate) struct Checker {
-    executor: SimpleExecutor
+    executable: Executable,
+    limits: ExecutionLimits,
+    verdict: CheckerVerdict,
+    seed: Option<u64>,
+}
+
+/// The test the checker verifies, whose details are passed to the checker in environment variables
+pub(crate) struct CheckedTest<'a> {
+    pub(crate) test_name: &'a str,
+    pub(crate) input_path: &'a Path,
+    pub(crate) expected_path: Option<&'a Path>,
+    /// The checker is run in the test's working directory, so it can inspect the files created by the program
+    pub(crate) working_directory: &'a Path,
+}
+
+impl CheckedTest<'_> {
+    /// The variables the checker is run with, TOSTER_TEST_NAME, TOSTER_INPUT_PATH and TOSTER_EXPECTED_OUTPUT_PATH (if there is an output file)
+    fn environment_variables(&self) -> Vec<(String, String)> {
+        let absolute = |path: &Path| fs::canonicalize(path).unwrap_or(path.to_path_buf()).to_string_lossy().to_string();
+        let mut variables = vec![
+            ("TOSTER_TEST_NAME".to_string(), self.test_name.to_string()),
+            ("TOSTER_INPUT_PATH".to_string(), absolute(self.input_path)),
+        ];
+        if let Some(expected_path) = self.expected_path {
+            variables.push(("TOSTER_EXPECTED_OUTPUT_PATH".to_string(), absolute(expected_path)));
+        }
+        variables
+    }
 }
 
 impl Checker {
-    pub(crate) fn new(checker_executable: PathBuf, timeout: Duration) -> Self {
+    pub(crate) fn new(checker_executable: Executable, timeout: Duration, verdict: CheckerVerdict, seed: Option<u64>) -> Self {
         Checker {
-            executor: SimpleExecutor {
-                executable_path: checker_executable,
+            executable: checker_executable,
+            limits: ExecutionLimits {
                 timeout,
-            }
+                memory_limit: None,
+                #[cfg(unix)]
+                max_processes: None,
+                #[cfg(unix)]
+                cpu_timeout: false,
+                #[cfg(unix)]
+                kill_grace: Duration::ZERO,
+                #[cfg(target_os = "linux")]
+                sandbox: false,
+            },
+            verdict,
+            seed,
         }
     }
 
@@ -31,10 +93,9 @@ impl Checker {
             None => Err(CheckerError { error: IncorrectCheckerFormat("the checker returned an empty file".to_string()) }),
             Some('C') => Ok(()),
             Some('I') => {
-                let checker_error = if output.len() > 1 { output.split_at(2).1.to_string() } else { String::new() };
-                let error_message = format!("Incorrect output{}{}", if checker_error.trim().is_empty() { "" } else { ": " }, checker_error.trim()).red();
+                let checker_comment = if output.len() > 1 { output.split_at(2).1.trim().to_string() } else { String::new() };
                 Err(TestError::Incorrect {
-                    error: error_message.to_string(),
+                    error: OutputDifference::CheckerComment(checker_comment),
                 })
             }
             Some(_) => Err(CheckerError { error: IncorrectCheckerFormat("the first character of the checker's output wasn't C or I".to_string()) })
@@ -48,6 +109,7 @@ impl Checker {
     /// which can be done by passing the file as stdin to the tested program.
     pub(crate) fn prepare_checker_input(input_source: &TestInputSource) -> File {
         let mut input_memfile = create_temp_file().unwrap();
+        // Copying between two files uses copy_file_range or sendfile on Linux, so the input isn't copied through userspace
         io::copy(&mut input_source.get_file(), &mut input_memfile).unwrap();
         input_memfile.write_all("\n".as_bytes()).unwrap();
         input_memfile
@@ -56,17 +118,53 @@ impl Checker {
     /// Run checker on input file created using `prepare_checker_input()`.
     /// The program output should be appended to that file.
     /// `check()` will rewind `checker_input` before running checker.
-    pub(crate) fn check(&self, mut checker_input: File) -> Result<(), TestError> {
+    ///
+    /// The checker is run in the test's working directory, so it can inspect the files created by the program.
+    pub(crate) fn check(&self, mut checker_input: File, test: &CheckedTest) -> Result<(), TestError> {
         checker_input.rewind().unwrap();
+        self.run(&checker_input, &[], test)
+    }
 
-        let (_, result) = test_to_temp(&self.executor, &checker_input);
-        let output = match result {
-            Ok(output) => output,
-            Err(error) => {
-                return Err(CheckerError { error });
-            }
+    /// Runs the checker with the paths of the input file, the program's output and the expected output as arguments,
+    /// in the test's working directory
+    pub(crate) fn check_files(&self, output_path: &Path, test: &CheckedTest) -> Result<(), TestError> {
+        let arguments: Vec<String> = [Some(test.input_path), Some(output_path), test.expected_path].into_iter()
+            .flatten()
+            .map(|path| fs::canonicalize(path).unwrap_or(path.to_path_buf()).to_string_lossy().to_string())
+            .collect();
+        let empty_input = create_temp_file().expect("Failed to create memfile");
+        self.run(&empty_input, &arguments, test)
+    }
+
+    fn run(&self, checker_input: &File, arguments: &[String], test: &CheckedTest) -> Result<(), TestError> {
+        let mut variables = test.environment_variables();
+        variables.extend(self.seed.map(|seed| (SEED_VARIABLE.to_string(), seed.to_string())));
+        let executor = SimpleExecutor {
+            executable: self.executable.clone(),
+            environment: Environment::inherit_with(variables),
         };
-        let output = read_to_string(output).expect("Failed to read checker output");
-        Self::parse_checker_output(&output)
+        let mut stdout = create_temp_file().expect("Failed to create memfile");
+        let mut stderr = create_temp_file().expect("Failed to create memfile");
+        let mut command = executor.command(checker_input, &stdout, &stderr, arguments, test.working_directory, &self.limits);
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn().expect("Failed to spawn child");
+        let (_, status) = executor.wait_for_status(child, &self.limits);
+        stdout.rewind().expect("Failed to rewind memfile");
+        stderr.rewind().expect("Failed to rewind memfile");
+        let stdout = read_to_string(stdout).expect("Failed to read checker output");
+        let stderr = read_to_string(stderr).expect("Failed to read checker stderr");
+        let status = status.map_err(|error| CheckerError { error })?;
+        // Like in testlib, 1 means a wrong answer and 2 a presentation error, while anything else means the checker itself failed
+        if let (CheckerVerdict::ExitCode, Some(1 | 2)) = (self.verdict, status.code()) {
+            let output = format!("{}{}", stdout, stderr);
+            let comment = if output.trim().is_empty() { format!("the checker returned {}", status) } else { output.trim().to_string() };
+            return Err(TestError::Incorrect { error: OutputDifference::CheckerComment(comment) });
+        }
+        SimpleExecutor::map_status_code(&status).map_err(|error| CheckerError { error })?;
+        match self.verdict {
+            CheckerVerdict::ExitCode => Ok(()),
+            CheckerVerdict::Stdout => Self::parse_checker_output(&stdout),
+            CheckerVerdict::Stderr => Self::parse_checker_output(&stderr),
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::process::Child;
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::{io, mem};
+use std::thread;
+use std::thread::ThreadId;
+use std::time::Duration;
+#[cfg(unix)]
+use std::time::Instant;
+use crate::executor::ExecutionLimits;
+
+struct RunningChild {
+    id: u32,
+    /// The thread that spawned the child
+    #[cfg_attr(not(unix), allow(dead_code))]
+    thread: ThreadId,
+    /// How long the child has to exit after getting SIGTERM before it's killed with SIGKILL
+    #[cfg_attr(not(unix), allow(dead_code))]
+    kill_grace: Duration,
+}
+
+/// The children that are currently running, so that they can all be killed when testing is aborted
+static RUNNING_CHILDREN: Mutex<Vec<RunningChild>> = Mutex::new(vec![]);
+
+/// Keeps a child registered as running until it's dropped
+pub(crate) struct ChildHandle {
+    id: u32,
+}
+
+impl ChildHandle {
+    /// Registers the child, which is given the kill grace period from `limits` when it's killed
+    pub(crate) fn register(child: &Child, limits: &ExecutionLimits) -> Self {
+        let id = child.id();
+        #[cfg(unix)]
+        let kill_grace = limits.kill_grace;
+        #[cfg(not(unix))]
+        let (kill_grace, _) = (Duration::ZERO, limits);
+        RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex").push(RunningChild {
+            id,
+            thread: thread::current().id(),
+            kill_grace,
+        });
+        ChildHandle { id }
+    }
+}
+
+impl Drop for ChildHandle {
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
+
+fn unregister(id: u32) {
+    let mut running_children = RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex");
+    if let Some(index) = running_children.iter().position(|child| child.id == id) {
+        running_children.swap_remove(index);
+    }
+}
+
+/// Blocks until the child exits and unregisters it, but doesn't reap it, which is left to the caller.
+///
+/// The id of a child can only be reused after it's reaped, so a registered id always belongs
+/// to the child, and killing the running children can never hit an unrelated process
+#[cfg(unix)]
+pub(crate) fn wait_for_exit(id: u32) -> io::Result<()> {
+    // SAFETY: siginfo_t is a plain struct, for which all zeroes is a valid value
+    let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+    while unsafe { libc::waitid(libc::P_PID, id as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) } == -1 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    unregister(id);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_registered(running_children: &[RunningChild], id: u32) -> bool {
+    running_children.iter().any(|child| child.id == id)
+}
+
+/// Sends `signal` to the child if it's still registered, so an exited child is never signalled
+#[cfg(unix)]
+fn signal_child(id: u32, signal: libc::c_int) {
+    let running_children = RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex");
+    if is_registered(&running_children, id) {
+        // SAFETY: kill only sends a signal, and the child can't be reaped while the mutex is locked.
+        // The result is ignored, the child might have exited without being reaped yet
+        unsafe { libc::kill(id as libc::pid_t, signal); }
+    }
+}
+
+/// Sends SIGTERM to the child, giving it `kill_grace` to exit before it's killed with SIGKILL,
+/// or kills it right away if `kill_grace` is zero. `wait` waits for the child to exit for at most the given time
+/// and returns whether it did.
+///
+/// Only registered children are signalled, so a child that was already reaped is never signalled
+#[cfg(unix)]
+pub(crate) fn terminate_child(id: u32, kill_grace: Duration, wait: impl FnOnce(Duration) -> bool) {
+    if !kill_grace.is_zero() {
+        signal_child(id, libc::SIGTERM);
+        if wait(kill_grace) {
+            return;
+        }
+    }
+    signal_child(id, libc::SIGKILL);
+}
+
+/// Waits for at most `timeout` for the child to be unregistered, which happens once it exits
+#[cfg(unix)]
+fn wait_until_unregistered(id: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if !is_registered(&RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex"), id) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Kills all of the children that are currently running, giving each of them its kill grace period like on a timeout
+#[cfg(unix)]
+pub(crate) fn kill_running_children() {
+    let running_children: Vec<(u32, Duration)> = RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex")
+        .iter()
+        .map(|child| (child.id, child.kill_grace))
+        .collect();
+    thread::scope(|scope| {
+        for (id, kill_grace) in running_children {
+            scope.spawn(move || terminate_child(id, kill_grace, |timeout| wait_until_unregistered(id, timeout)));
+        }
+    });
+}
+
+/// Kills all of the children that are currently running
+#[cfg(windows)]
+pub(crate) fn kill_running_children() {
+    let running_children = RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex");
+    for child in running_children.iter() {
+        let _ = std::process::Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &child.id.to_string()])
+            .output();
+    }
+}
+
+/// Kills the children that are currently running and were spawned by `thread`, returning whether there were any
+#[cfg(unix)]
+pub(crate) fn kill_children_of_thread(thread: ThreadId) -> bool {
+    let running_children = RUNNING_CHILDREN.lock().expect("Failed to lock running children mutex");
+    let mut killed = false;
+    for child in running_children.iter().filter(|child| child.thread == thread) {
+        // SAFETY: kill only sends a signal, and the child can't be reaped while the mutex is locked
+        unsafe { libc::kill(child.id as libc::pid_t, libc::SIGKILL); }
+        killed = true;
+    }
+    killed
+}
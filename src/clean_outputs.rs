@@ -0,0 +1,43 @@
+use std::fs;
+use colored::Colorize;
+use crate::args::CleanOutputsArgs;
+use crate::formatted_error::FormattedError;
+use crate::prepare_input::find_orphaned_outputs;
+
+pub(crate) fn run_clean_outputs(args: CleanOutputsArgs) -> Result<(), FormattedError> {
+    // Without the inputs every output file would count as orphaned
+    if !args.r#in.is_dir() {
+        return Err(FormattedError::from_str("The input directory does not exist"));
+    }
+    if !args.out.is_dir() {
+        return Err(FormattedError::from_str("The output directory does not exist"));
+    }
+
+    let out_exts: Vec<&str> = [args.out_ext.as_str()].into_iter()
+        .chain(args.fallback_out_ext.iter().map(|ext| ext.as_str()))
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    let orphaned_outputs: Vec<String> = find_orphaned_outputs(&args.r#in, &args.out, &out_exts).into_iter()
+        .filter(|file_name| args.out.join(file_name).is_file())
+        .collect();
+    if orphaned_outputs.is_empty() {
+        println!("{}", "All of the output files have a matching input file".green());
+        return Ok(());
+    }
+
+    for file_name in &orphaned_outputs {
+        let path = args.out.join(file_name);
+        if !args.dry_run {
+            fs::remove_file(&path)
+                .map_err(|error| FormattedError::from_str(&format!("Failed to delete {}: {}", path.display(), error)))?;
+        }
+        println!("{}", path.display());
+    }
+    let files = if orphaned_outputs.len() == 1 { "output file" } else { "output files" };
+    if args.dry_run {
+        println!("{}", format!("{} {} would be deleted", orphaned_outputs.len(), files).yellow());
+    } else {
+        println!("{}", format!("Deleted {} {}", orphaned_outputs.len(), files).green());
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use which::which;
+
+/// The commands copying their stdin to the system clipboard, in the order they're tried
+fn clipboard_commands() -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbcopy"]]
+    } else if cfg!(windows) {
+        vec![&["clip"]]
+    } else {
+        let mut commands: Vec<&'static [&'static str]> = vec![&["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]];
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            commands.insert(0, &["wl-copy"]);
+        }
+        commands
+    }
+}
+
+/// Copies `contents` to the system clipboard using the first available clipboard command
+pub(crate) fn copy_to_clipboard(contents: &[u8]) -> Result<(), String> {
+    let Some(command) = clipboard_commands().into_iter().find(|command| which(command[0]).is_ok()) else {
+        return Err(format!(
+            "No clipboard command was found, install one of: {}",
+            clipboard_commands().iter().map(|command| command[0]).collect::<Vec<_>>().join(", "),
+        ));
+    };
+
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| format!("Failed to run {}: {}", command[0], error))?;
+    child.stdin.take().expect("The stdin of the clipboard command should be piped")
+        .write_all(contents)
+        .map_err(|error| format!("Failed to write to {}: {}", command[0], error))?;
+    let status = child.wait().map_err(|error| format!("Failed to run {}: {}", command[0], error))?;
+    if !status.success() {
+        return Err(format!("{} failed with {}", command[0], status));
+    }
+    Ok(())
+}
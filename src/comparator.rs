@@ -0,0 +1,353 @@
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use clap::ValueEnum;
+use colored::Colorize;
+use crate::temp_files::create_named_temp_file;
+use crate::test_errors::OutputDifference;
+use crate::testing_utils::{compare_lines, diffs_redacted, LineEndings, OutputHashing, WhitespaceRules, BYTE_ORDER_MARK};
+
+/// Decides if the program's output is correct, given the contents of an expected output file
+pub(crate) trait Comparator: Debug + Send + Sync {
+    /// Returns a description of how the output differs if it isn't correct
+    fn compare(&self, expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference>;
+
+    /// Whether every output accepted by this comparator consists of the same whitespace-separated tokens as the output file
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn requires_same_tokens(&self) -> bool {
+        false
+    }
+
+    /// How the outputs can be hashed so that this comparator accepts exactly the outputs with the same hash
+    /// as the output file, which is used for output files too large to be loaded into memory
+    fn hashing(&self) -> Option<OutputHashing> {
+        None
+    }
+}
+
+/// The built-in comparators that can be selected with --comparator
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum ComparatorKind {
+    Exact,
+    Lines,
+    Tokens,
+    Float,
+    Unordered,
+}
+
+impl ComparatorKind {
+    pub(crate) fn create(self, line_endings: LineEndings, whitespace: WhitespaceRules, float_epsilon: f64) -> Box<dyn Comparator> {
+        match self {
+            ComparatorKind::Exact => Box::new(ExactComparator),
+            ComparatorKind::Lines => Box::new(LinesComparator { line_endings, whitespace }),
+            ComparatorKind::Tokens => Box::new(TokensComparator),
+            ComparatorKind::Float => Box::new(FloatComparator { epsilon: float_epsilon }),
+            ComparatorKind::Unordered => Box::new(UnorderedComparator),
+        }
+    }
+}
+
+const HEADERS: (&str, &str) = ("Output file", "Your program's output");
+
+/// Requires the output to be identical to the output file, byte for byte
+#[derive(Debug)]
+pub(crate) struct ExactComparator;
+
+impl Comparator for ExactComparator {
+    fn compare(&self, _expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        if expected_output == actual_output {
+            return Ok(());
+        }
+        compare_lines(expected_output, actual_output, LineEndings::Strict, WhitespaceRules::default(), HEADERS)?;
+        Err("The output only differs from the output file in whitespace at the ends of lines or empty lines at the end".red().to_string().into())
+    }
+
+    fn requires_same_tokens(&self) -> bool {
+        true
+    }
+
+    fn hashing(&self) -> Option<OutputHashing> {
+        Some(OutputHashing::Exact)
+    }
+}
+
+/// Compares the outputs line by line, ignoring whitespace at the ends of lines and empty lines at the end
+/// unless the whitespace rules are strict
+#[derive(Debug)]
+pub(crate) struct LinesComparator {
+    pub(crate) line_endings: LineEndings,
+    pub(crate) whitespace: WhitespaceRules,
+}
+
+impl Comparator for LinesComparator {
+    fn compare(&self, _expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        compare_lines(expected_output, actual_output, self.line_endings, self.whitespace, HEADERS)
+    }
+
+    fn requires_same_tokens(&self) -> bool {
+        true
+    }
+
+    fn hashing(&self) -> Option<OutputHashing> {
+        let WhitespaceRules { strict, require_trailing_newline } = self.whitespace;
+        (matches!(self.line_endings, LineEndings::Lenient) && !strict && !require_trailing_newline).then_some(OutputHashing::Lines)
+    }
+}
+
+/// Splits the output into whitespace-separated tokens, along with the numbers of the lines they're on
+fn tokens(output: &str) -> Vec<(usize, &str)> {
+    output.strip_prefix(BYTE_ORDER_MARK).unwrap_or(output)
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| line.split_whitespace().map(move |token| (index + 1, token)))
+        .collect()
+}
+
+/// Checks if the outputs consist of the same whitespace-separated tokens, ignoring how they're spaced out
+pub(crate) fn same_tokens(expected_output: &str, actual_output: &str) -> bool {
+    tokens(expected_output).into_iter().map(|(_, token)| token)
+        .eq(tokens(actual_output).into_iter().map(|(_, token)| token))
+}
+
+/// Compares the tokens of the outputs one by one using `tokens_equal`
+fn compare_tokens(expected_output: &str, actual_output: &str, tokens_equal: impl Fn(&str, &str) -> bool) -> Result<(), OutputDifference> {
+    let expected_tokens = tokens(expected_output);
+    let actual_tokens = tokens(actual_output);
+
+    let first_difference = expected_tokens.iter().zip(&actual_tokens)
+        .enumerate()
+        .find(|(_, ((_, expected), (_, actual)))| !tokens_equal(expected, actual));
+    if let Some((index, ((expected_line, expected), (actual_line, actual)))) = first_difference {
+        if diffs_redacted() {
+            return Err(format!(
+                "Token {} differs: it's on line {} of the output file and on line {} of your program's output",
+                index + 1, expected_line, actual_line,
+            ).red().to_string().into());
+        }
+        return Err(format!(
+            "Token {} differs: the output file has \"{}\" (line {}), but your program's output has \"{}\" (line {})",
+            index + 1, expected, expected_line, actual, actual_line,
+        ).red().to_string().into());
+    }
+    if expected_tokens.len() != actual_tokens.len() {
+        return Err(format!(
+            "The output file has {} tokens, but your program's output has {}",
+            expected_tokens.len(), actual_tokens.len(),
+        ).red().to_string().into());
+    }
+    Ok(())
+}
+
+/// Compares the whitespace-separated tokens of the outputs, ignoring how they're split into lines
+#[derive(Debug)]
+pub(crate) struct TokensComparator;
+
+impl Comparator for TokensComparator {
+    fn compare(&self, _expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        compare_tokens(expected_output, actual_output, |expected, actual| expected == actual)
+    }
+
+    fn requires_same_tokens(&self) -> bool {
+        true
+    }
+}
+
+/// Compares the tokens like [`TokensComparator`], but accepts numbers that differ
+/// by at most `epsilon`, either absolutely or relatively
+#[derive(Debug)]
+pub(crate) struct FloatComparator {
+    pub(crate) epsilon: f64,
+}
+
+impl Comparator for FloatComparator {
+    fn compare(&self, _expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        compare_tokens(expected_output, actual_output, |expected, actual| {
+            // The tokens are compared textually first, and only finite numbers are compared with the epsilon,
+            // since nan is never equal to itself and any number is within the epsilon of inf
+            if expected == actual {
+                return true;
+            }
+            match (expected.parse::<f64>(), actual.parse::<f64>()) {
+                (Ok(expected), Ok(actual)) if expected.is_finite() && actual.is_finite() => (expected - actual).abs() <= self.epsilon * expected.abs().max(1.0),
+                _ => false,
+            }
+        })
+    }
+}
+
+/// Accepts the output if it has the same lines as the output file in any order
+#[derive(Debug)]
+pub(crate) struct UnorderedComparator;
+
+impl Comparator for UnorderedComparator {
+    fn compare(&self, _expected_path: &Path, expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        let sorted_lines = |output: &str| {
+            let mut lines: Vec<String> = output.strip_prefix(BYTE_ORDER_MARK).unwrap_or(output)
+                .lines()
+                .map(|line| line.trim_end().to_string())
+                .filter(|line| !line.is_empty())
+                .collect();
+            lines.sort();
+            lines
+        };
+        let expected_lines = sorted_lines(expected_output);
+        let actual_lines = sorted_lines(actual_output);
+        if expected_lines == actual_lines {
+            return Ok(());
+        }
+
+        let (missing, extra) = multiset_difference(&expected_lines, &actual_lines);
+        let mut description = vec![format!(
+            "The lines of the output don't match the output file in any order (the output file has {} lines, your program's output has {})",
+            expected_lines.len(), actual_lines.len(),
+        ).red().to_string()];
+        if diffs_redacted() {
+            return Err(description.join("\n").into());
+        }
+        if !missing.is_empty() {
+            description.push(format!("Missing lines: {}", missing.iter().map(|line| format!("\"{}\"", line)).collect::<Vec<String>>().join(", ")));
+        }
+        if !extra.is_empty() {
+            description.push(format!("Unexpected lines: {}", extra.iter().map(|line| format!("\"{}\"", line)).collect::<Vec<String>>().join(", ")));
+        }
+        Err(description.join("\n").into())
+    }
+}
+
+/// Returns at most 10 of the lines that are only in `expected` and at most 10 that are only in `actual`,
+/// counting repeated lines as many times as they appear. Both have to be sorted
+fn multiset_difference<'a>(expected: &'a [String], actual: &'a [String]) -> (Vec<&'a String>, Vec<&'a String>) {
+    let (mut missing, mut extra) = (vec![], vec![]);
+    let (mut expected, mut actual) = (expected.iter().peekable(), actual.iter().peekable());
+    loop {
+        match (expected.peek(), actual.peek()) {
+            (Some(expected_line), Some(actual_line)) if expected_line == actual_line => {
+                expected.next();
+                actual.next();
+            },
+            (Some(expected_line), Some(actual_line)) if expected_line < actual_line => missing.push(expected.next().unwrap()),
+            (Some(_), None) => missing.push(expected.next().unwrap()),
+            (_, Some(_)) => extra.push(actual.next().unwrap()),
+            (None, None) => break,
+        }
+    }
+    missing.truncate(10);
+    extra.truncate(10);
+    (missing, extra)
+}
+
+/// Replaces `<EXPECTED>` and `<ACTUAL>` in the arguments of a command, which were split
+/// before substituting so that paths with spaces stay a single argument
+pub(crate) fn substitute_paths(template: &[String], expected_path: &Path, actual_path: &Path) -> Vec<String> {
+    let expected_path = expected_path.to_str().expect("The output file path is invalid");
+    let actual_path = actual_path.to_str().expect("The temporary file path is invalid");
+    template.iter()
+        .map(|arg| arg.replace("<EXPECTED>", expected_path).replace("<ACTUAL>", actual_path))
+        .collect()
+}
+
+/// Runs a command to compare the outputs. `<EXPECTED>` and `<ACTUAL>` get replaced with the paths
+/// of the output files. The output is correct if the command returns 0, otherwise its output is shown
+#[derive(Debug)]
+pub(crate) struct CommandComparator {
+    /// The command split into arguments, which must not be empty
+    template: Vec<String>,
+}
+
+impl CommandComparator {
+    /// The command must have been validated with `shlex::split` already
+    pub(crate) fn new(command: &str) -> Self {
+        CommandComparator { template: shlex::split(command).expect("The comparator command is invalid") }
+    }
+}
+
+impl Comparator for CommandComparator {
+    fn compare(&self, expected_path: &Path, _expected_output: &str, actual_output: &str) -> Result<(), OutputDifference> {
+        let actual_file = create_named_temp_file().expect("Failed to create temporary file");
+        fs::write(actual_file.path(), actual_output).expect("Failed to save the program's output");
+
+        let mut args = substitute_paths(&self.template, expected_path, actual_file.path());
+        let output = Command::new(args.remove(0))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|error| OutputDifference::from(format!("Failed to run the comparator command: {}", error).blue().to_string()))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        Err(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        ).trim_end().to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(comparator: &dyn Comparator, expected_output: &str, actual_output: &str) -> bool {
+        comparator.compare(Path::new("test.out"), expected_output, actual_output).is_ok()
+    }
+
+    #[test]
+    fn compares_exactly() {
+        assert!(compare(&ExactComparator, "1 2\n3\n", "1 2\n3\n"));
+        assert!(!compare(&ExactComparator, "1 2\n3\n", "1 2 \n3\n"));
+        assert!(!compare(&ExactComparator, "1 2\n3\n", "1 2\n3"));
+    }
+
+    #[test]
+    fn compares_lines() {
+        let comparator = LinesComparator { line_endings: LineEndings::Lenient, whitespace: WhitespaceRules::default() };
+        assert!(compare(&comparator, "1 2\n3\n", "1 2  \r\n3\n\n"));
+        assert!(!compare(&comparator, "1 2\n3\n", "1  2\n3\n"));
+        assert!(!compare(&comparator, "1 2\n3\n", "1 2 3\n"));
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(compare(&TokensComparator, "1 2\n3\n", "1\n2   3"));
+        assert!(!compare(&TokensComparator, "1 2\n3\n", "1 2"));
+        assert!(!compare(&TokensComparator, "1 2\n3\n", "1 2 4"));
+    }
+
+    #[test]
+    fn compares_floats() {
+        let comparator = FloatComparator { epsilon: 1e-6 };
+        assert!(compare(&comparator, "1.0 1000000", "1.0000001 1000000.5"));
+        assert!(!compare(&comparator, "1.0", "1.001"));
+        assert!(compare(&comparator, "nan inf -inf", "nan inf -inf"));
+        assert!(!compare(&comparator, "nan", "1.0"));
+        assert!(!compare(&comparator, "inf", "-inf"));
+        assert!(!compare(&comparator, "inf", "1.0"));
+        assert!(!compare(&comparator, "YES", "NO"));
+    }
+
+    #[test]
+    fn compares_unordered_lines() {
+        assert!(compare(&UnorderedComparator, "a\nb\nb\n", "b\na\nb\n"));
+        assert!(!compare(&UnorderedComparator, "a\na\n", "a\nb\n"));
+        assert!(!compare(&UnorderedComparator, "a\nb\n", "a\nb\nb\n"));
+    }
+
+    #[test]
+    fn finds_repeated_missing_and_extra_lines() {
+        let lines = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect::<Vec<String>>();
+        let (expected, actual) = (lines(&["a", "a", "b", "c"]), lines(&["a", "b", "b", "d"]));
+        let (missing, extra) = multiset_difference(&expected, &actual);
+        assert_eq!(missing, ["a", "c"]);
+        assert_eq!(extra, ["b", "d"]);
+    }
+
+    #[test]
+    fn keeps_paths_with_spaces_as_one_argument() {
+        let template = shlex::split("cmp --silent '<EXPECTED>' <ACTUAL>").unwrap();
+        assert_eq!(
+            substitute_paths(&template, Path::new("my tests/a.out"), Path::new("/tmp/a b.actual")),
+            ["cmp", "--silent", "my tests/a.out", "/tmp/a b.actual"],
+        );
+    }
+}
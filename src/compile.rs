@@ -0,0 +1,62 @@
+use std::path::Path;
+use colored::Colorize;
+use crate::args::CompileArgs;
+use crate::compiler::{CompiledCache, Compiler, FileKind, Languages};
+use crate::config::ConfigFile;
+use crate::formatted_error::FormattedError;
+use crate::gha::annotate_compiler_error;
+use crate::temp_files::create_temp_dir;
+use crate::verbose::set_verbosity;
+
+/// Compiles the file, saving the executable in .toster/compiled, or reuses it if it's already there
+fn compile(compiler: &Compiler, path: &Path, name: &'static str, is_checker: bool, args: &CompileArgs) -> Result<(), FormattedError> {
+    if compiler.compiler_name(path, FileKind::Detect).is_none() {
+        println!("{}", format!("{} isn't compiled, so there's nothing to do", path.display()).bright_black());
+        return Ok(());
+    }
+    let (_, compilation) = compiler.prepare_executable(path, name, FileKind::Detect, CompiledCache::ReuseAndSave)
+        .map_err(|error| {
+            if args.gha {
+                annotate_compiler_error(path, &error);
+            }
+            error.to_formatted(is_checker)
+        })?;
+    match compilation {
+        Some(compilation) => {
+            compilation.print(&format!("Compilation of {}", path.display()), args.compile.show_warnings);
+        },
+        None => println!("{}", format!("{} didn't change since it was last compiled", path.display()).green()),
+    }
+    Ok(())
+}
+
+/// Compiles the program and the checker like a normal run would, so that it can reuse them
+pub(crate) fn run_compile(args: CompileArgs) -> Result<(), FormattedError> {
+    set_verbosity(args.verbose);
+    for path in [Some(&args.filename), args.checker.as_ref()].into_iter().flatten() {
+        if !path.is_file() {
+            return Err(FormattedError::from_str(&format!("The file {} does not exist", path.display())));
+        }
+    }
+    let languages = ConfigFile::load(args.config.as_deref())
+        .and_then(|config_file| Languages::new(&config_file, args.compile.compile_command.clone(), args.compile.profile.as_deref(), args.compile.compile_shell))
+        .map_err(|error| FormattedError::from_str(&error))?;
+
+    let tempdir = create_temp_dir().expect("Failed to create temporary directory");
+    let compiler = Compiler {
+        tempdir: &tempdir,
+        compile_timeout: args.compile.compile_timeout,
+        languages: &languages,
+        compile_shell: args.compile.compile_shell,
+        precompile_header: args.compile.precompile_header,
+        #[cfg(windows)]
+        wsl: args.wsl,
+        #[cfg(not(windows))]
+        wsl: false,
+    };
+    compile(&compiler, &args.filename, "program", false, &args)?;
+    if let Some(checker) = &args.checker {
+        compile(&compiler, checker, "checker", true, &args)?;
+    }
+    Ok(())
+}
@@ -1,16 +1,27 @@
+use std::collections::HashMap;
 use std::{fs, io};
 use std::io::ErrorKind::NotFound;
-use std::io::{read_to_string, Seek};
+use std::fs::File;
+use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::{Duration, Instant};
 use colored::Colorize;
-use is_executable::is_executable;
+use sha2::{Digest, Sha256};
 use tempfile::TempDir;
 use wait_timeout::ChildExt;
+use which::which;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use crate::args::verify_compile_command;
 use crate::compiler::CompilerError::{CompilationError, InvalidExecutable};
+use crate::config::ConfigFile;
 use crate::formatted_error::FormattedError;
-use crate::temp_files::{create_temp_file, make_cloned_stdio};
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::precompiled_header::prepare_precompiled_header;
+use crate::verbose::log;
+use crate::wsl::{bridge_executable, to_wsl_path, wrap_command};
+use crate::temp_files::{create_temp_file, make_cloned_stdio, write_atomically};
 
 pub(crate) enum CompilerError {
     InvalidExecutable(io::Error),
@@ -44,25 +55,346 @@ impl CompilerError {
     }
 }
 
+/// The details of a successful compilation
+pub(crate) struct Compilation {
+    pub(crate) time: Duration,
+    /// What the compiler printed to stderr, usually warnings
+    pub(crate) diagnostics: String,
+}
+
+impl Compilation {
+    fn warning_count(&self) -> usize {
+        self.diagnostics.lines().filter(|line| line.contains("warning:")).count()
+    }
+
+    /// Prints that the compilation described by `header` (e.g. "Program compilation") finished,
+    /// followed by the compiler's diagnostics if `show_warnings` is set, or otherwise the number of warnings
+    pub(crate) fn print(&self, header: &str, show_warnings: bool) {
+        println!("{}", format!("{} completed in {:.2}", header, self.time.as_secs_f32()).green());
+        if show_warnings {
+            if !self.diagnostics.trim().is_empty() {
+                println!("{}", self.diagnostics.trim_end());
+            }
+        } else {
+            match self.warning_count() {
+                0 => {}
+                1 => println!("{}", "The compiler reported 1 warning, use --show-warnings to see it".yellow()),
+                count => println!("{}", format!("The compiler reported {} warnings, use --show-warnings to see them", count).yellow()),
+            }
+        }
+    }
+}
+
+/// A program prepared for running, either directly or through an interpreter
+#[derive(Clone, Debug)]
+pub(crate) struct Executable {
+    pub(crate) path: PathBuf,
+    /// The interpreter command (with its arguments) that scripts are run with
+    pub(crate) interpreter: Option<Vec<String>>,
+}
+
+impl Executable {
+    pub(crate) fn native(path: PathBuf) -> Self {
+        Executable { path, interpreter: None }
+    }
+
+    /// Creates a command running the program
+    pub(crate) fn command(&self) -> Command {
+        match &self.interpreter {
+            Some(interpreter) => {
+                let mut command = Command::new(&interpreter[0]);
+                command.args(&interpreter[1..]).arg(&self.path);
+                command
+            }
+            None => Command::new(&self.path),
+        }
+    }
+}
+
+#[cfg(windows)]
+const PYTHON: &str = "python";
+#[cfg(not(windows))]
+const PYTHON: &str = "python3";
+
+#[cfg(windows)]
+const SHELL: [&str; 2] = ["cmd", "/C"];
+#[cfg(not(windows))]
+const SHELL: [&str; 2] = ["sh", "-c"];
+
+/// The magic numbers at the start of ELF, PE and Mach-O (32 and 64-bit, both byte orders, universal) executables
+const EXECUTABLE_MAGIC: &[&[u8]] = &[
+    b"\x7fELF",
+    b"MZ",
+    b"\xfe\xed\xfa\xce",
+    b"\xfe\xed\xfa\xcf",
+    b"\xce\xfa\xed\xfe",
+    b"\xcf\xfa\xed\xfe",
+    b"\xca\xfe\xba\xbe",
+];
+
+/// The command C++ files are compiled with when neither --compile-command nor the config file set one
+pub(crate) const DEFAULT_COMPILE_COMMAND: &str = "g++ -std=c++20 -O3 -static <IN> -o <OUT>";
+
+/// The built-in compile profiles for C++ that can be selected with --profile
+const PROFILES: &[(&str, &str)] = &[
+    ("release", DEFAULT_COMPILE_COMMAND),
+    ("debug", "g++ -std=c++20 -g -O0 -D_GLIBCXX_DEBUG -D_GLIBCXX_DEBUG_PEDANTIC <IN> -o <OUT>"),
+    ("sanitize", "g++ -std=c++20 -g -O1 -fno-omit-frame-pointer -fsanitize=address,undefined <IN> -o <OUT>"),
+];
+
+/// How files with a given extension are turned into a program
+#[derive(Clone, Debug)]
+pub(crate) enum Language {
+    /// Compiled with the given command template
+    Compiled(String),
+    /// Run through the given interpreter command (with its arguments)
+    Interpreted(Vec<String>),
+}
+
+/// The languages toster can compile or run, chosen based on the extension of the file
+pub(crate) struct Languages {
+    /// The command set with --compile-command, which is used for all compiled languages
+    compile_command: Option<String>,
+    by_extension: HashMap<String, Language>,
+}
+
+impl Languages {
+    /// Returns the C++ compile command of the profile, looking for it in the config file first
+    fn profile_command(config: &ConfigFile, profile: &str) -> Result<String, String> {
+        if let Some(entry) = config.profiles.get(profile) {
+            return Ok(entry.compile.clone());
+        }
+        if let Some((_, command)) = PROFILES.iter().find(|(name, _)| *name == profile) {
+            return Ok(command.to_string());
+        }
+
+        let mut names: Vec<&str> = PROFILES.iter().map(|(name, _)| *name)
+            .chain(config.profiles.keys().map(|name| name.as_str()))
+            .collect();
+        names.sort();
+        names.dedup();
+        Err(format!("The profile \"{}\" does not exist, use one of: {}", profile, names.join(", ")))
+    }
+
+    /// Creates the built-in languages (C++, Python and shell scripts), overridden by the ones from the config file.
+    ///
+    /// C++ is compiled with the command of `profile` if one is given, otherwise with the release profile
+    pub(crate) fn new(config: &ConfigFile, compile_command: Option<String>, profile: Option<&str>, compile_shell: bool) -> Result<Self, String> {
+        if let Some(command) = &compile_command {
+            verify_compile_command(command, compile_shell)?;
+        }
+        let cpp_command = match profile {
+            Some(profile) => {
+                let command = Self::profile_command(config, profile)?;
+                verify_compile_command(&command, compile_shell)
+                    .map_err(|error| format!("The compile command of the \"{}\" profile is invalid:\n{}", profile, error))?;
+                command
+            }
+            None => DEFAULT_COMPILE_COMMAND.to_string(),
+        };
+
+        let mut by_extension: HashMap<String, Language> = HashMap::new();
+        for extension in ["cpp", "cc", "cxx", "c"] {
+            by_extension.insert(extension.to_string(), Language::Compiled(cpp_command.clone()));
+        }
+        by_extension.insert("py".to_string(), Language::Interpreted(vec![PYTHON.to_string()]));
+        by_extension.insert("sh".to_string(), Language::Interpreted(vec!["sh".to_string()]));
+
+        for (extension, entry) in &config.languages {
+            let extension = extension.strip_prefix('.').unwrap_or(extension);
+            let language = match (&entry.compile, &entry.run) {
+                (Some(command), None) => {
+                    verify_compile_command(command, compile_shell)
+                        .map_err(|error| format!("The compile command for .{} files in the config file is invalid:\n{}", extension, error))?;
+                    Language::Compiled(command.clone())
+                }
+                (None, Some(command)) => match shlex::split(command) {
+                    Some(split) if !split.is_empty() => Language::Interpreted(split),
+                    _ => return Err(format!("The run command for .{} files in the config file has unmatched quotes or is empty", extension)),
+                },
+                _ => return Err(format!("The .{} language in the config file needs exactly one of the compile and run commands", extension)),
+            };
+            by_extension.insert(extension.to_string(), language);
+        }
+
+        Ok(Languages { compile_command, by_extension })
+    }
+
+    fn get(&self, path: &Path) -> Option<&Language> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.by_extension.get(extension))
+    }
+
+    /// Whether the file has the extension of one of the languages
+    pub(crate) fn is_known(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Returns the command compiling files with the given language
+    fn compile_command<'b>(&'b self, command: &'b str) -> &'b str {
+        self.compile_command.as_deref().unwrap_or(command)
+    }
+
+    /// Returns the command compiling source files that don't have the extension of a compiled language,
+    /// which are compiled as C++
+    fn fallback_compile_command(&self) -> &str {
+        match self.by_extension.get("cpp") {
+            Some(Language::Compiled(command)) => self.compile_command(command),
+            _ => self.compile_command(DEFAULT_COMPILE_COMMAND),
+        }
+    }
+}
+
+/// Whether a file given to toster is compiled or run directly
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FileKind {
+    /// Detected from the extension, the #! line of scripts and the magic numbers of executables
+    Detect,
+    Source,
+    Executable,
+}
+
+/// How the executables saved in .toster/compiled are used when a file is compiled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CompiledCache {
+    /// The file is always compiled
+    Unused,
+    /// An executable saved earlier is reused, but a newly compiled one isn't saved
+    Reuse,
+    /// An executable saved earlier is reused, and a newly compiled one is saved for later runs
+    ReuseAndSave,
+}
+
 pub(crate) struct Compiler<'a> {
     pub(crate) tempdir: &'a TempDir,
     pub(crate) compile_timeout: Duration,
-    pub(crate) compile_command: &'a str,
+    pub(crate) languages: &'a Languages,
+    /// Whether the compile command is run through the system shell instead of being split into arguments
+    pub(crate) compile_shell: bool,
+    /// Whether C++ files are compiled with a precompiled bits/stdc++.h
+    pub(crate) precompile_header: bool,
+    /// Whether programs are compiled and run inside of WSL, which is only possible on Windows
+    pub(crate) wsl: bool,
 }
 
 impl<'a> Compiler<'a> {
-    fn is_source_file(path: &Path) -> bool {
-        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
-            return matches!(extension, "cpp" | "cc" | "cxx" | "c");
+    /// Checks if the file starts like an ELF, PE or Mach-O executable
+    fn has_executable_magic(path: &Path) -> bool {
+        let mut start = [0; 4];
+        let Ok(mut file) = File::open(path) else {
+            return false;
+        };
+        if file.read_exact(&mut start).is_err() {
+            return false;
+        }
+        EXECUTABLE_MAGIC.iter().any(|magic| start.starts_with(magic))
+    }
+
+    fn is_cpp_file(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension, "cpp" | "cc" | "cxx"))
+    }
+
+    /// Returns the interpreter a script should be run with, based on the #! line at its start
+    fn shebang_interpreter(path: &Path) -> Option<Vec<String>> {
+        let contents = fs::read(path).ok()?;
+        let shebang = contents.strip_prefix(b"#!")?.split(|byte| *byte == b'\n').next()?;
+        let interpreter: Vec<String> = String::from_utf8_lossy(shebang).split_whitespace().map(|part| part.to_string()).collect();
+        (!interpreter.is_empty()).then_some(interpreter)
+    }
+
+    /// Returns the interpreter the file is run through, if it's a script
+    fn interpreter(&self, source_path: &Path, kind: FileKind) -> Option<Vec<String>> {
+        match (kind, self.languages.get(source_path)) {
+            (FileKind::Detect, Some(Language::Interpreted(interpreter))) => Some(interpreter.clone()),
+            (FileKind::Detect, _) => Self::shebang_interpreter(source_path),
+            (FileKind::Source | FileKind::Executable, _) => None,
         }
-        !is_executable(path)
     }
 
-    fn compile_cpp(&self, source_path: &Path, executable_path: &Path) -> Result<Duration, String> {
-        let cmd = self.compile_command
-            .replace("<IN>", source_path.to_str().expect("The provided filename is invalid"))
-            .replace("<OUT>", executable_path.to_str().expect("The provided filename is invalid"));
-        let mut split_cmd = cmd.split(' ');
+    fn is_source_file(&self, source_path: &Path, kind: FileKind) -> bool {
+        match kind {
+            FileKind::Detect => matches!(self.languages.get(source_path), Some(Language::Compiled(_))) || !Self::has_executable_magic(source_path),
+            FileKind::Source => true,
+            FileKind::Executable => false,
+        }
+    }
+
+    /// Returns the compile command template for the source file
+    fn compile_command_for(&self, source_path: &Path) -> &str {
+        match self.languages.get(source_path) {
+            Some(Language::Compiled(command)) => self.languages.compile_command(command),
+            _ => self.languages.fallback_compile_command(),
+        }
+    }
+
+    /// Returns the program compiling the file (the first word of its compile command), if it's a source file
+    pub(crate) fn compiler_name(&self, source_path: &Path, kind: FileKind) -> Option<String> {
+        if self.interpreter(source_path, kind).is_some() || !self.is_source_file(source_path, kind) {
+            return None;
+        }
+        self.compile_command_for(source_path).split_whitespace().next().map(|name| name.to_string())
+    }
+
+    /// Describes how [`Compiler::prepare_executable`] would prepare the file, without running anything
+    pub(crate) fn describe_preparation(&self, source_path: &Path, kind: FileKind) -> String {
+        if let Some(interpreter) = self.interpreter(source_path, kind) {
+            return format!("run with the interpreter \"{}\"", interpreter.join(" "));
+        }
+        if !self.is_source_file(source_path, kind) {
+            return "run directly as an executable".to_string();
+        }
+        let description = format!("compiled with \"{}\"", self.compile_command_for(source_path));
+        if self.wsl {
+            return format!("{} inside of WSL", description);
+        }
+        description
+    }
+
+    /// The shell the compile command is run through with --compile-shell
+    fn shell(&self) -> &'static [&'static str] {
+        if self.wsl {
+            return &["sh", "-c"];
+        }
+        &SHELL
+    }
+
+    fn compile(&self, compile_command: &str, source_path: &Path, executable_path: &Path) -> Result<Compilation, String> {
+        let mut source_path = source_path.to_str().expect("The provided filename is invalid").to_string();
+        let mut executable_path = executable_path.to_str().expect("The provided filename is invalid").to_string();
+        if self.wsl {
+            source_path = to_wsl_path(&source_path);
+            executable_path = to_wsl_path(&executable_path);
+        }
+        let (source_path, executable_path) = (source_path.as_str(), executable_path.as_str());
+        let split_cmd: Vec<String> = if self.compile_shell {
+            let quote = |path| shlex::try_quote(path).expect("The provided filename is invalid").to_string();
+            let cmd = compile_command
+                .replace("<IN>", &quote(source_path))
+                .replace("<OUT>", &quote(executable_path));
+            self.shell().iter().map(|part| part.to_string()).chain([cmd]).collect()
+        } else {
+            // The paths are substituted after splitting, so that spaces in them don't need quoting
+            let template = shlex::split(compile_command).expect("The compile command is invalid");
+            let mut split_cmd: Vec<String> = template.iter()
+                .map(|arg| arg.replace("<IN>", source_path).replace("<OUT>", executable_path))
+                .collect();
+            if self.precompile_header && Self::is_cpp_file(Path::new(source_path)) {
+                match prepare_precompiled_header(&template, self.compile_timeout) {
+                    Ok(include_directory) => {
+                        log(1, || format!("Using the precompiled header from {}", include_directory.display()));
+                        split_cmd.splice(1..1, ["-I".to_string(), include_directory.to_string_lossy().to_string()]);
+                    }
+                    Err(error) => println!("{}", format!("Failed to precompile bits/stdc++.h, compiling without it: {}", error).yellow()),
+                }
+            }
+            split_cmd
+        };
+        let split_cmd = if self.wsl { wrap_command(split_cmd) } else { split_cmd };
+        log(1, || format!("Compiling {} with {:?}", source_path, split_cmd));
+        let mut split_cmd = split_cmd.into_iter();
 
         let mut stderr = create_temp_file().expect("Failed to create memfile");
         let time_before_compilation = Instant::now();
@@ -78,21 +410,20 @@ impl<'a> Compiler<'a> {
         };
         let result = child.wait_timeout(self.compile_timeout).unwrap();
 
+        let time = time_before_compilation.elapsed();
         stderr.rewind().unwrap();
 
-        match result {
-            Some(status) => {
-                if status.code().expect("The compiler returned an invalid status code") != 0 {
-                    let compilation_result = read_to_string(stderr).expect("Failed to read compiler output");
-                    return Err(compilation_result);
-                }
-            }
-            None => {
-                child.kill().unwrap();
-                return Err("Compilation timed out".to_string());
-            }
+        let Some(status) = result else {
+            child.kill().unwrap();
+            return Err("Compilation timed out".to_string());
+        };
+        let mut compilation_result = vec![];
+        stderr.read_to_end(&mut compilation_result).expect("Failed to read compiler output");
+        let diagnostics = String::from_utf8_lossy(&compilation_result).to_string();
+        if !status.success() {
+            return Err(diagnostics);
         }
-        Ok(time_before_compilation.elapsed())
+        Ok(Compilation { time, diagnostics })
     }
 
     fn try_spawning_executable(executable_path: &PathBuf) -> io::Result<()> {
@@ -103,25 +434,101 @@ impl<'a> Compiler<'a> {
             })
     }
 
+    /// Prepares the file for running, compiling it if it's source code.
+    ///
+    /// The executable can be taken from and saved in `.toster/compiled` depending on `cache`, so that it's reused by later runs
+    /// if the source code and the compile command don't change, in which case no compilation is returned
     pub(crate) fn prepare_executable(
         &self,
         source_path: &Path,
         name: &'static str,
-    ) -> Result<(PathBuf, Option<Duration>), CompilerError> {
+        kind: FileKind,
+        cache: CompiledCache,
+    ) -> Result<(Executable, Option<Compilation>), CompilerError> {
+        let (executable, compilation) = self.prepare_native_executable(source_path, name, kind, cache)?;
+        if self.wsl {
+            return Ok((bridge_executable(executable), compilation));
+        }
+        Ok((executable, compilation))
+    }
+
+    /// Prepares the file like [`Compiler::prepare_executable`], without making it run inside of WSL
+    fn prepare_native_executable(
+        &self,
+        source_path: &Path,
+        name: &'static str,
+        kind: FileKind,
+        cache: CompiledCache,
+    ) -> Result<(Executable, Option<Compilation>), CompilerError> {
         debug_assert!(PathBuf::from(name).extension().is_none());
         let output_path = self.tempdir.path().join(format!("{}.o", name));
 
-        if !Self::is_source_file(source_path) {
+        // Scripts are run from their original location, so that they can use the files next to them
+        if let Some(interpreter) = self.interpreter(source_path, kind) {
+            // Interpreters inside of WSL can't be looked for from Windows
+            if !self.wsl {
+                if let Err(error) = which(&interpreter[0]) {
+                    return Err(InvalidExecutable(io::Error::new(NotFound, format!("The interpreter \"{}\" was not found: {}", interpreter[0], error))));
+                }
+            }
+            let path = fs::canonicalize(source_path).expect("The provided filename is invalid");
+            log(1, || format!("Running {} through the interpreter {:?}", path.display(), interpreter));
+            return Ok((Executable { path, interpreter: Some(interpreter) }, None));
+        }
+
+        if !self.is_source_file(source_path, kind) {
+            log(1, || format!("Running {} directly as an executable", source_path.display()));
             fs::copy(source_path, &output_path).expect("The provided filename is invalid");
-            if let Err(error) = Self::try_spawning_executable(&output_path) {
-                return Err(InvalidExecutable(error));
+            // The file might not have been marked as executable
+            #[cfg(unix)]
+            fs::set_permissions(&output_path, fs::Permissions::from_mode(0o755)).expect("Failed to make the executable runnable");
+            // Linux executables can't be spawned directly on Windows
+            if !self.wsl {
+                if let Err(error) = Self::try_spawning_executable(&output_path) {
+                    return Err(InvalidExecutable(error));
+                }
             }
-            return Ok((output_path, None));
+            return Ok((Executable::native(output_path), None));
         }
 
-        match self.compile_cpp(source_path, &output_path) {
-            Ok(compilation_time) => Ok((output_path, Some(compilation_time))),
-            Err(error) => Err(CompilationError(error)),
+        // Compilers decide the language based on the extension
+        let source_copy;
+        let compile_command = self.compile_command_for(source_path);
+        let source_path = match self.languages.get(source_path) {
+            Some(Language::Compiled(_)) => source_path,
+            _ => {
+                source_copy = self.tempdir.path().join(format!("{}.cpp", name));
+                fs::copy(source_path, &source_copy).expect("The provided filename is invalid");
+                source_copy.as_path()
+            }
+        };
+        let cached_path = (cache != CompiledCache::Unused).then(|| self.compiled_cache_path(source_path, compile_command));
+        if let Some(cached_path) = cached_path.as_ref().filter(|path| path.is_file()) {
+            log(1, || format!("Reusing the executable compiled from {} in an earlier run, saved at {}", source_path.display(), cached_path.display()));
+            fs::copy(cached_path, &output_path).expect("Failed to copy the cached executable");
+            #[cfg(unix)]
+            fs::set_permissions(&output_path, fs::Permissions::from_mode(0o755)).expect("Failed to make the executable runnable");
+            return Ok((Executable::native(output_path), None));
         }
+        let compilation = self.compile(compile_command, source_path, &output_path).map_err(CompilationError)?;
+        if let Some(cached_path) = cached_path.filter(|_| cache == CompiledCache::ReuseAndSave) {
+            // Failing to save the executable only means that it will be compiled again next time
+            let _ = fs::create_dir_all(cached_path.parent().expect("The cached executable path should have a parent"))
+                .and_then(|_| write_atomically(&cached_path, &mut File::open(&output_path)?));
+        }
+        Ok((Executable::native(output_path), Some(compilation)))
+    }
+
+    /// Returns where the executable compiled from the source file with the command is saved between runs.
+    ///
+    /// The files included by the source aren't taken into account
+    fn compiled_cache_path(&self, source_path: &Path, compile_command: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(fs::read(source_path).expect("The provided filename is invalid"));
+        hasher.update([0]);
+        hasher.update(compile_command);
+        hasher.update([self.compile_shell as u8, self.precompile_header as u8, self.wsl as u8]);
+        let key: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        PathBuf::from(DATA_DIRECTORY).join("compiled").join(key)
     }
 }
\ No newline at end of file
@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use flate2::read::MultiGzDecoder;
+use tempfile::NamedTempFile;
+use crate::temp_files::{create_named_temp_file, create_temp_file};
+
+#[derive(Clone, Copy)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// The extensions of compressed test files
+const COMPRESSED_EXTS: [(&str, Compression); 2] = [
+    (".gz", Compression::Gzip),
+    (".zst", Compression::Zstd),
+];
+
+fn compression(path: &Path) -> Option<Compression> {
+    let file_name = path.file_name()?.to_str()?;
+    COMPRESSED_EXTS.iter()
+        .find(|(ext, _)| file_name.ends_with(ext))
+        .map(|(_, compression)| *compression)
+}
+
+/// Removes the compression extension from a file name, e.g. "test1.in.gz" becomes "test1.in"
+pub(crate) fn strip_compression_ext(file_name: &str) -> &str {
+    COMPRESSED_EXTS.iter()
+        .find_map(|(ext, _)| file_name.strip_suffix(ext))
+        .unwrap_or(file_name)
+}
+
+/// Returns the compressed versions of a file that are looked for if it doesn't exist
+pub(crate) fn compressed_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    COMPRESSED_EXTS.iter().map(|(ext, _)| {
+        let mut compressed = path.as_os_str().to_os_string();
+        compressed.push(ext);
+        compressed.into()
+    })
+}
+
+fn decompress_error(error: io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => "the file is truncated".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+/// Decompresses the whole input, which for gzip can consist of multiple members
+fn decompress(input: impl Read, compression: Compression, output: &mut impl Write) -> Result<(), String> {
+    match compression {
+        Compression::Gzip => io::copy(&mut MultiGzDecoder::new(BufReader::new(input)), output).map(|_| ()),
+        Compression::Zstd => zstd::stream::copy_decode(input, &mut *output),
+    }.and_then(|()| output.flush()).map_err(decompress_error)
+}
+
+fn decompress_into(path: &Path, compression: Compression, output: &File) -> Result<(), String> {
+    let input = File::open(path).map_err(|error| format!("Failed to open {}: {}", path.display(), error))?;
+    decompress(input, compression, &mut BufWriter::new(output))
+        .map_err(|error| format!("Failed to decompress {}: {}", path.display(), error))
+}
+
+/// Opens a test file, decompressing it into a temporary file first if it's compressed
+pub(crate) fn open_decompressed(path: &Path) -> Result<File, String> {
+    let Some(compression) = compression(path) else {
+        return File::open(path).map_err(|error| format!("Failed to open {}: {}", path.display(), error));
+    };
+    let mut output = create_temp_file().map_err(|error| format!("Failed to create a temporary file: {}", error))?;
+    decompress_into(path, compression, &output)?;
+    output.rewind().map_err(|error| error.to_string())?;
+    Ok(output)
+}
+
+/// Decompresses a compressed test file into a named temporary file, for programs like checkers that need a path.
+/// Returns `None` if the file isn't compressed
+pub(crate) fn decompress_to_named_file(path: &Path) -> Result<Option<NamedTempFile>, String> {
+    let Some(compression) = compression(path) else {
+        return Ok(None);
+    };
+    let output = create_named_temp_file().map_err(|error| format!("Failed to create a temporary file: {}", error))?;
+    decompress_into(path, compression, output.as_file())?;
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, Compression};
+
+    /// "1 2\n3\n" compressed by gzip with the original file name saved, followed by "4\n" as a second member
+    const MULTI_MEMBER_GZIP: [u8; 53] = [
+        31, 139, 8, 8, 0, 0, 0, 0, 2, 255, 116, 46, 105, 110, 0, 51, 84, 48, 226, 50, 230, 2, 0, 125, 99, 46, 252, 6, 0, 0, 0,
+        31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 51, 225, 2, 0, 22, 8, 38, 26, 2, 0, 0, 0,
+    ];
+
+    #[test]
+    fn decompresses_gzip() {
+        let mut output = vec![];
+        decompress(&MULTI_MEMBER_GZIP[..], Compression::Gzip, &mut output).unwrap();
+        assert_eq!(output, b"1 2\n3\n4\n");
+    }
+
+    #[test]
+    fn decompresses_zstd() {
+        let compressed = zstd::encode_all(&b"1 2\n3\n"[..], 0).unwrap();
+        let mut output = vec![];
+        decompress(&compressed[..], Compression::Zstd, &mut output).unwrap();
+        assert_eq!(output, b"1 2\n3\n");
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut corrupted = MULTI_MEMBER_GZIP;
+        corrupted[27] ^= 1;
+        assert!(decompress(&corrupted[..], Compression::Gzip, &mut vec![]).is_err());
+        assert_eq!(decompress(&MULTI_MEMBER_GZIP[..40], Compression::Gzip, &mut vec![]), Err("the file is truncated".to_string()));
+        assert!(decompress(&b"1 2\n3\n"[..], Compression::Gzip, &mut vec![]).is_err());
+        assert!(decompress(&b"1 2\n3\n"[..], Compression::Zstd, &mut vec![]).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::verbose::log;
+
+/// The config file used when --config isn't given, if it exists in the current directory
+const DEFAULT_CONFIG_PATH: &str = "toster.toml";
+
+/// Toster's settings read from a TOML config file, for example:
+/// ```toml
+/// [languages.c]
+/// compile = "gcc -O2 <IN> -o <OUT>"
+///
+/// [languages.py]
+/// run = "pypy3"
+///
+/// [profiles.fast]
+/// compile = "g++ -std=c++20 -O2 -march=native <IN> -o <OUT>"
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    /// How files are compiled or run, keyed by their extension
+    #[serde(default)]
+    pub(crate) languages: HashMap<String, LanguageEntry>,
+    /// Custom compile profiles for C++, keyed by their name
+    #[serde(default)]
+    pub(crate) profiles: HashMap<String, ProfileEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LanguageEntry {
+    /// The command compiling the file, with the same placeholders as --compile-command
+    pub(crate) compile: Option<String>,
+    /// The interpreter command the file is run with, its path is appended to the command
+    pub(crate) run: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileEntry {
+    pub(crate) compile: String,
+}
+
+impl ConfigFile {
+    /// Reads the config file at `path`, or toster.toml in the current directory if no path is given.
+    ///
+    /// Returns the default config if no path is given and toster.toml doesn't exist
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => {
+                if !path.is_file() {
+                    return Err("The provided config file does not exist".to_string());
+                }
+                path.to_path_buf()
+            }
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if !path.is_file() {
+                    return Ok(ConfigFile::default());
+                }
+                path
+            }
+        };
+
+        log(1, || format!("Using the config file {}", path.display()));
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| format!("Failed to read the config file {}: {}", path.display(), error))?;
+        toml::from_str(&contents)
+            .map_err(|error| format!("The config file {} is invalid:\n{}", path.display(), error))
+    }
+}
@@ -0,0 +1,125 @@
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use colored::Colorize;
+use comfy_table::{Attribute, Cell, Color};
+use comfy_table::ContentArrangement::Dynamic;
+use crate::args::ContestArgs;
+use crate::compiler::Languages;
+use crate::config::ConfigFile;
+use crate::formatted_error::FormattedError;
+use crate::output::create_table;
+use crate::history::{Run, RUN_FILE_VARIABLE};
+use crate::temp_files::create_named_temp_file;
+
+/// The result of testing a single problem of the contest
+enum ProblemOutcome {
+    NoSolution,
+    /// Toster stopped before finishing the tests, e.g. because the solution didn't compile
+    Failed,
+    Tested { correct: usize, total: usize },
+}
+
+struct Problem {
+    name: String,
+    solution: Option<PathBuf>,
+    outcome: ProblemOutcome,
+}
+
+/// Finds the solution of the problem in `directory`: the source file named after the problem,
+/// or the only source file in the directory
+fn find_solution(directory: &Path, name: &str, languages: &Languages) -> Option<PathBuf> {
+    let sources: Vec<PathBuf> = fs::read_dir(directory).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && languages.is_known(path))
+        .collect();
+    if let Some(named) = sources.iter().find(|path| path.file_stem().is_some_and(|stem| stem == name)) {
+        return Some(named.clone());
+    }
+    match sources.as_slice() {
+        [only] => Some(only.clone()),
+        _ => None,
+    }
+}
+
+/// Runs toster on `solution` (relative to `directory`) in `directory`, printing its output as usual.
+///
+/// Returns the results of the run, or `None` if testing didn't finish
+pub(crate) fn run_toster(directory: &Path, solution: &Path, toster_args: &[String]) -> Result<Option<Run>, FormattedError> {
+    let toster = env::current_exe().map_err(|error| FormattedError::from_str(&format!("Failed to find the toster executable: {}", error)))?;
+    let run_file = create_named_temp_file().map_err(|error| FormattedError::from_str(&format!("Failed to create a temporary file: {}", error)))?;
+    Command::new(toster)
+        .arg(solution)
+        .arg("--no-pager")
+        .args(toster_args)
+        .env(RUN_FILE_VARIABLE, run_file.path())
+        .current_dir(directory)
+        .status()
+        .map_err(|error| FormattedError::from_str(&format!("Failed to run toster: {}", error)))?;
+
+    // The run is only saved when all of the tests were run, otherwise the file stays empty
+    Ok(Run::load_from(run_file.path()))
+}
+
+fn print_summary(problems: &[Problem]) {
+    let mut table = create_table();
+    table.set_content_arrangement(Dynamic).set_header(vec![
+        Cell::new("Problem").add_attribute(Attribute::Bold),
+        Cell::new("Solution").add_attribute(Attribute::Bold),
+        Cell::new("Result").add_attribute(Attribute::Bold),
+    ]);
+    for problem in problems {
+        let solution = problem.solution.as_ref()
+            .and_then(|solution| solution.file_name())
+            .map_or("-".to_string(), |name| name.to_string_lossy().to_string());
+        let result = match problem.outcome {
+            ProblemOutcome::NoSolution => Cell::new("No solution found").fg(Color::Yellow),
+            ProblemOutcome::Failed => Cell::new("Testing failed").fg(Color::Red),
+            ProblemOutcome::Tested { correct, total } => Cell::new(format!("{}/{} correct", correct, total))
+                .fg(if correct == total { Color::Green } else { Color::Red }),
+        };
+        table.add_row(vec![Cell::new(&problem.name), Cell::new(solution), result]);
+    }
+    println!("{}", table.to_string().replace('\r', ""));
+}
+
+pub(crate) fn run_contest(args: ContestArgs) -> Result<(), FormattedError> {
+    let config = ConfigFile::load(None).map_err(|error| FormattedError::from_str(&error))?;
+    let languages = Languages::new(&config, None, None, false).map_err(|error| FormattedError::from_str(&error))?;
+
+    let mut directories: Vec<PathBuf> = fs::read_dir(&args.directory)
+        .map_err(|error| FormattedError::from_str(&format!("Failed to read the contest directory: {}", error)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join("in").is_dir())
+        .collect();
+    if directories.is_empty() {
+        return Err(FormattedError::from_str("The contest directory doesn't contain any problems, which are subdirectories with an \"in\" directory"));
+    }
+    directories.sort_by(|a, b| human_sort::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    let mut problems = vec![];
+    for directory in directories {
+        let name = directory.file_name().expect("The problem directory should have a name").to_string_lossy().to_string();
+        let solution = find_solution(&directory, &name, &languages);
+        let outcome = match &solution {
+            None => ProblemOutcome::NoSolution,
+            Some(solution) => {
+                println!("{}", format!("Testing problem {}", name).bold());
+                let file_name = solution.file_name().expect("The solution should be a file");
+                let outcome = match run_toster(&directory, Path::new(file_name), &args.toster_args)? {
+                    Some(run) => {
+                        let (correct, total) = run.counts();
+                        ProblemOutcome::Tested { correct, total }
+                    }
+                    None => ProblemOutcome::Failed,
+                };
+                println!();
+                outcome
+            }
+        };
+        problems.push(Problem { name, solution, outcome });
+    }
+
+    print_summary(&problems);
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::test_errors::ExecutionError;
+use crate::test_errors::ExecutionError::RuntimeError;
+
+/// The file deciding where the kernel saves core dumps
+const CORE_PATTERN_PATH: &str = "/proc/sys/kernel/core_pattern";
+
+/// The core pattern the names of core dumps are made from, set when --core-dumps is used
+static CORE_PATTERN: OnceLock<String> = OnceLock::new();
+
+/// Makes the tested programs dump their cores when they crash, so that they can be collected after every test.
+///
+/// Only core dumps saved in the working directory of the program can be collected, which depends on the system's core pattern
+pub(crate) fn enable_core_dumps() -> Result<(), String> {
+    let pattern = fs::read_to_string(CORE_PATTERN_PATH)
+        .map_err(|error| format!("Failed to read the core pattern from {}: {}", CORE_PATTERN_PATH, error))?;
+    let pattern = pattern.trim();
+    if let Some(handler) = pattern.strip_prefix('|') {
+        return Err(format!(
+            "Core dumps are passed to \"{}\" instead of being saved as files, so toster can't collect them. Find them with coredumpctl or set the core pattern to \"core\" with \"sudo sysctl kernel.core_pattern=core\"",
+            handler.split_whitespace().next().unwrap_or(handler),
+        ));
+    }
+    let prefix = pattern.split('%').next().unwrap_or_default();
+    if prefix.is_empty() || pattern.contains('/') {
+        return Err(format!(
+            "Core dumps are saved as \"{}\", so toster can't find them. Set the core pattern to \"core\" with \"sudo sysctl kernel.core_pattern=core\"",
+            pattern,
+        ));
+    }
+    let _ = CORE_PATTERN.set(pattern.to_string());
+    Ok(())
+}
+
+pub(crate) fn core_dumps_enabled() -> bool {
+    CORE_PATTERN.get().is_some()
+}
+
+/// Whether the name matches the core pattern, with each %-specifier (like %p or %e) standing for any non-empty text
+fn matches_core_pattern(name: &str, pattern: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => name.is_empty(),
+        Some('%') => match pattern_chars.next() {
+            Some('%') => name.strip_prefix('%').is_some_and(|name| matches_core_pattern(name, pattern_chars.as_str())),
+            Some(_) => (1..=name.len())
+                .filter(|index| name.is_char_boundary(*index))
+                .any(|index| matches_core_pattern(&name[index..], pattern_chars.as_str())),
+            // A trailing % is dropped by the kernel
+            None => name.is_empty(),
+        },
+        Some(char) => name.strip_prefix(char).is_some_and(|name| matches_core_pattern(name, pattern_chars.as_str())),
+    }
+}
+
+/// Whether the file is named like a core dump saved with the core pattern,
+/// which is followed by ".<pid>" if kernel.core_uses_pid is set and the pattern doesn't contain %p
+fn is_core_file_name(name: &str, pattern: &str) -> bool {
+    let is_pid = |pid: &str| !pid.is_empty() && pid.bytes().all(|byte| byte.is_ascii_digit());
+    matches_core_pattern(name, pattern)
+        || name.rsplit_once('.').is_some_and(|(name, pid)| is_pid(pid) && matches_core_pattern(name, pattern))
+}
+
+/// Moves the core dump a crashed program left in its working directory to `.toster/cores/<test_name>`,
+/// and adds its path to the runtime error
+pub(crate) fn collect_core_dump(test_name: &str, working_directory: &Path, error: ExecutionError) -> ExecutionError {
+    let (Some(pattern), RuntimeError(message)) = (CORE_PATTERN.get(), &error) else {
+        return error;
+    };
+    let core_file = fs::read_dir(working_directory).into_iter().flatten().flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.file_name().is_some_and(|name| is_core_file_name(&name.to_string_lossy(), pattern)));
+    let Some(core_file) = core_file else {
+        return error;
+    };
+
+    let destination = PathBuf::from(DATA_DIRECTORY).join("cores").join(test_name);
+    // The working directory is usually on a different filesystem, in which case the file has to be copied
+    let saved = fs::create_dir_all(destination.parent().expect("The core dump path should have a parent"))
+        .and_then(|_| fs::rename(&core_file, &destination).or_else(|_| fs::copy(&core_file, &destination).map(|_| ())));
+    match saved {
+        Ok(()) => RuntimeError(format!("{}\n- the core dump was saved to {}", message, destination.display())),
+        Err(save_error) => RuntimeError(format!("{}\n- failed to save the core dump: {}", message, save_error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_core_file_name;
+
+    #[test]
+    fn matches_core_file_names() {
+        assert!(is_core_file_name("core", "core"));
+        assert!(is_core_file_name("core.1234", "core"));
+        assert!(!is_core_file_name("core.txt", "core"));
+        assert!(!is_core_file_name("core_output", "core"));
+        assert!(!is_core_file_name("cores", "core"));
+
+        assert!(is_core_file_name("core.program.1234", "core.%e.%p"));
+        assert!(!is_core_file_name("core.program", "core.%e.%p"));
+        assert!(!is_core_file_name("core..1234", "core.%e.%p"));
+        assert!(is_core_file_name("dump%1234", "dump%%%p"));
+    }
+}
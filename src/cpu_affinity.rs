@@ -0,0 +1,25 @@
+use std::mem;
+
+/// Returns the CPUs toster is allowed to run on
+pub(crate) fn available_cpus() -> Result<Vec<usize>, String> {
+    // SAFETY: cpu_set_t is a plain bit mask, for which all zeroes is a valid (empty) value
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+        return Err(format!("Failed to read the CPU affinity: {}", std::io::Error::last_os_error()));
+    }
+    let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize).filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) }).collect();
+    if cpus.is_empty() {
+        return Err("Failed to read the CPU affinity: no CPUs are available".to_string());
+    }
+    Ok(cpus)
+}
+
+/// Pins the calling thread (and the processes it spawns from now on) to a single CPU
+pub(crate) fn pin_current_thread(cpu: usize) {
+    // SAFETY: see available_cpus()
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        panic!("Failed to pin a worker thread to CPU {}: {}", cpu, std::io::Error::last_os_error());
+    }
+}
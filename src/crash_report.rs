@@ -0,0 +1,63 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use serde::Serialize;
+
+/// The parsed command line arguments, saved as soon as they're parsed
+static CONFIGURATION: OnceLock<String> = OnceLock::new();
+/// The compiler of the tested program, whose version is added to the report
+static COMPILER: OnceLock<String> = OnceLock::new();
+/// What toster was doing when it crashed
+static STAGE: Mutex<&str> = Mutex::new("parsing the arguments");
+
+pub(crate) fn set_configuration(configuration: String) {
+    let _ = CONFIGURATION.set(configuration);
+}
+
+pub(crate) fn set_compiler(compiler: String) {
+    let _ = COMPILER.set(compiler);
+}
+
+pub(crate) fn set_stage(stage: &'static str) {
+    *STAGE.lock().unwrap_or_else(|error| error.into_inner()) = stage;
+}
+
+/// The command line toster was run with, with the arguments that aren't valid Unicode converted lossily
+pub(crate) fn command_line() -> String {
+    env::args_os().map(|argument| argument.to_string_lossy().into_owned()).collect::<Vec<String>>().join(" ")
+}
+
+/// The information added to the report created by human-panic
+#[derive(Serialize)]
+struct ReportDetails {
+    command_line: String,
+    stage: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compiler_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    configuration: Option<String>,
+}
+
+/// Returns the first line of `compiler --version`
+fn compiler_version(compiler: &str) -> Option<String> {
+    let output = Command::new(compiler).arg("--version").stdin(Stdio::null()).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.to_string())
+}
+
+/// Appends the configuration, the compiler version and the current stage to the crash report at `path`.
+/// The report is a TOML file with only top-level keys, so more of them can be added at its end
+pub(crate) fn extend_report(path: &Path) -> Result<(), String> {
+    let details = ReportDetails {
+        command_line: command_line(),
+        stage: STAGE.lock().unwrap_or_else(|error| error.into_inner()).to_string(),
+        compiler_version: COMPILER.get().and_then(|compiler| compiler_version(compiler)),
+        configuration: CONFIGURATION.get().cloned(),
+    };
+    let details = toml::to_string(&details).map_err(|error| error.to_string())?;
+    let mut report = OpenOptions::new().append(true).open(path).map_err(|error| error.to_string())?;
+    write!(report, "{}", details).map_err(|error| error.to_string())
+}
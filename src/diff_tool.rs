@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use colored::Colorize;
+use tempfile::TempDir;
+use crate::comparator::substitute_paths;
+use crate::temp_files::create_temp_dir;
+use crate::test_errors::OutputDifference;
+
+/// An external program used to show the differences between the expected and the actual output
+pub(crate) struct DiffTool {
+    command: String,
+    interactive: bool,
+    directory: TempDir,
+    /// The expected and actual output files of the failed tests, used in interactive mode
+    saved_diffs: Mutex<Vec<(String, PathBuf, PathBuf)>>,
+}
+
+impl DiffTool {
+    pub(crate) fn new(command: String, interactive: bool) -> Self {
+        DiffTool {
+            command,
+            interactive,
+            directory: create_temp_dir().expect("Failed to create temporary directory"),
+            saved_diffs: Mutex::new(vec![]),
+        }
+    }
+
+    /// Builds the command, replacing `<EXPECTED>` and `<ACTUAL>` with the paths of the files,
+    /// or appending the paths if the placeholders aren't used
+    fn build_command(&self, expected_path: &Path, actual_path: &Path) -> Command {
+        let template = shlex::split(&self.command).expect("The diff tool command is invalid");
+        let mut args = substitute_paths(&template, expected_path, actual_path);
+        if !self.command.contains("<EXPECTED>") && !self.command.contains("<ACTUAL>") {
+            args.extend([expected_path, actual_path].map(|path| path.to_str().expect("The file path is invalid").to_string()));
+        }
+
+        let mut command = Command::new(args.remove(0));
+        command.args(args);
+        command
+    }
+
+    /// Saves the program's output to a file and returns the description of the difference.
+    ///
+    /// In interactive mode, the tool is only run by `run_interactive()` after testing
+    /// and `builtin_diff` is returned. Otherwise, the tool's output is returned
+    pub(crate) fn diff(&self, test_name: &str, expected_path: &Path, actual_output: &str, builtin_diff: impl FnOnce() -> OutputDifference) -> OutputDifference {
+        let actual_path = self.directory.path().join(format!("{}.actual", test_name));
+        fs::write(&actual_path, actual_output).expect("Failed to save the program's output");
+
+        if self.interactive {
+            self.saved_diffs.lock().expect("Failed to lock saved diffs mutex")
+                .push((test_name.to_string(), expected_path.to_path_buf(), actual_path));
+            return builtin_diff();
+        }
+
+        let output = self.build_command(expected_path, &actual_path)
+            .stdin(Stdio::null())
+            .output();
+        let message = match output {
+            Ok(output) => format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr),
+            ).trim_end().to_string(),
+            Err(error) => format!("Failed to run the diff tool: {}", error).red().to_string(),
+        };
+        message.into()
+    }
+
+    /// Runs the diff tool for every failed test one by one, letting the user interact with it
+    pub(crate) fn run_interactive(&self) {
+        let mut saved_diffs = self.saved_diffs.lock().expect("Failed to lock saved diffs mutex");
+        saved_diffs.sort_by(|a, b| human_sort::compare(&a.0, &b.0));
+
+        for (test_name, expected_path, actual_path) in saved_diffs.iter() {
+            println!("{}", format!("Opening the diff tool for test {}", test_name).bold());
+            if let Err(error) = self.build_command(expected_path, actual_path).status() {
+                println!("{}", format!("Failed to run the diff tool: {}", error).red());
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::{fs, io, mem};
+use crate::compression::open_decompressed;
+use crate::executor::{test_to_temp, TestExecutor};
+use crate::formatted_error::FormattedError;
+use crate::prepare_input::Test;
+use crate::temp_files::create_temp_file;
+use crate::verbose::log;
+
+/// Returns the number of bytes that can be written to the filesystem containing `path`
+fn available_space(path: &Path) -> Result<u64, String> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|error| error.to_string())?;
+    // SAFETY: statvfs is a plain struct, for which all zeroes is a valid value
+    let mut stats: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error().to_string());
+    }
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// The size of the input file as stored in the input directory, which is the compressed size for compressed inputs
+fn input_size(test: &Test) -> u64 {
+    fs::metadata(test.input_source.original_path()).map_or(0, |metadata| metadata.len())
+}
+
+/// Estimates how much space the outputs of `tests` will take up by running the program on the test with
+/// the median input size, and scaling the size of its output by the total size of the inputs.
+///
+/// Returns `None` if the program fails on that test or its input can't be read, in which case nothing can be estimated
+fn estimate_output_size(runner: &impl TestExecutor, tests: &[Test]) -> Option<u64> {
+    let mut sizes: Vec<(u64, &Test)> = tests.iter().map(|test| (input_size(test), test)).collect();
+    sizes.sort_by_key(|(size, _)| *size);
+    let (sample_input_size, sample) = *sizes.get(sizes.len() / 2)?;
+
+    let input = open_decompressed(sample.input_source.original_path()).ok()?;
+    fs::create_dir_all(&sample.working_directory).ok()?;
+    let stderr_file = create_temp_file().expect("Failed to create memfile");
+    let (_, result) = test_to_temp(runner, &input, &stderr_file, &sample.arguments, &sample.working_directory, &sample.limits);
+    let _ = fs::remove_dir_all(&sample.working_directory);
+    let sample_output_size = result.ok()?.metadata().ok()?.len();
+
+    if sample_input_size == 0 {
+        return Some(sample_output_size * tests.len() as u64);
+    }
+    let total_input_size: u64 = sizes.iter().map(|(size, _)| size).sum();
+    Some((sample_output_size as u128 * total_input_size as u128 / sample_input_size as u128) as u64)
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// The total size of the outputs of `tests` that already exist, which are freed when they're overwritten
+fn existing_output_size(tests: &[Test], output_directory: &Path, output_ext: &str) -> u64 {
+    tests.iter()
+        .filter_map(|test| fs::metadata(test.generated_output_path(output_directory, output_ext)).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Fails if the outputs of `tests` probably won't fit on the drive with `output_directory`,
+/// so that generation doesn't run out of space halfway through.
+///
+/// The outputs that already exist (and are overwritten with --force) count as free space
+pub(crate) fn check_disk_space(runner: &impl TestExecutor, tests: &[Test], output_directory: &Path, output_ext: &str) -> Result<(), FormattedError> {
+    let available = match available_space(output_directory) {
+        Ok(available) => available + existing_output_size(tests, output_directory, output_ext),
+        Err(error) => {
+            log(1, || format!("Failed to check the free space in {}: {}", output_directory.display(), error));
+            return Ok(());
+        }
+    };
+    let Some(required) = estimate_output_size(runner, tests) else {
+        log(1, || "The program failed on the sample test, so the size of the outputs can't be estimated".to_string());
+        return Ok(());
+    };
+    log(1, || format!("The outputs should take up about {}, {} is free", format_size(required), format_size(available)));
+
+    if required > available {
+        return Err(FormattedError::from_str(&format!(
+            "The output files will take up about {}, but only {} is free in {}. Use --no-space-check to generate them anyway",
+            format_size(required), format_size(available), output_directory.display(),
+        )));
+    }
+    Ok(())
+}
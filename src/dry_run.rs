@@ -0,0 +1,79 @@
+use std::path::Path;
+use colored::Colorize;
+use crate::args::{ActionType, OutputChecker, ParsedConfig};
+use crate::compiler::{Compiler, FileKind};
+use crate::executor::ExecutionLimits;
+use crate::prepare_input::Test;
+
+fn format_limits(limits: &ExecutionLimits) -> String {
+    match limits.memory_limit {
+        Some(memory_limit) => format!("{:.3}s, {}KiB", limits.timeout.as_secs_f64(), memory_limit),
+        None => format!("{:.3}s", limits.timeout.as_secs_f64()),
+    }
+}
+
+/// Formats a pattern matching the output files, e.g. "out/*.out"
+fn output_files(output_directory: &Path, output_ext: &str) -> String {
+    output_directory.join(format!("*{}", output_ext)).display().to_string()
+}
+
+fn format_expected_outputs(test: &Test, output_directory: &Path, output_ext: &str, fallback_exts: &[String]) -> String {
+    let existing: Vec<String> = test.output_files(output_directory, output_ext, fallback_exts).iter()
+        .filter(|path| path.is_file())
+        .map(|path| path.display().to_string())
+        .collect();
+    if existing.is_empty() {
+        "no output file".red().to_string()
+    } else {
+        existing.join(", ")
+    }
+}
+
+/// Prints what toster would do with the configuration, without compiling or running anything
+pub(crate) fn print_dry_run(config: &ParsedConfig, compiler: &Compiler, tests: &[Test]) {
+    println!("{}", "Dry run, nothing will be compiled or run".bold());
+    println!("Program: {} ({})", config.source_path.display(), compiler.describe_preparation(&config.source_path, config.source_kind));
+    if let Some(model) = &config.model {
+        println!("Model solution: {} ({}), generating {}", model.path.display(), compiler.describe_preparation(&model.path, FileKind::Detect), output_files(&model.output_directory, &model.output_ext));
+    }
+    match &config.action_type {
+        ActionType::Generate { output_directory, output_ext, checker, .. } => {
+            println!("Mode: generating the output files {}", output_files(output_directory, output_ext));
+            if let Some(OutputChecker { path, protocol, verdict }) = checker {
+                println!("Checking the outputs with {} ({}) using the {:?} protocol and the {:?} verdict", path.display(), compiler.describe_preparation(path, FileKind::Detect), protocol, verdict);
+            }
+        }
+        ActionType::SimpleCompare { output_directory, output_ext, comparator, .. } => {
+            println!("Mode: comparing with the output files {} using {:?}", output_files(output_directory, output_ext), comparator);
+        }
+        ActionType::Checker { path, protocol, verdict, .. } => {
+            println!("Mode: checking with {} ({}) using the {:?} protocol and the {:?} verdict", path.display(), compiler.describe_preparation(path, FileKind::Detect), protocol, verdict);
+        }
+    }
+    println!("Execute mode: {:?}", config.execute_mode);
+    println!("Default limits: {}", format_limits(config.test_limits.global()));
+
+    println!("{}", format!("Tests ({}):", tests.len()).bold());
+    let mut tests: Vec<&Test> = tests.iter().collect();
+    tests.sort_by(|a, b| human_sort::compare(&a.test_name, &b.test_name));
+    for test in tests {
+        let mut line = format!("{}: {}", test.test_name, test.input_source.path().display());
+        match &config.action_type {
+            ActionType::SimpleCompare { output_directory, output_ext, fallback_output_exts, .. }
+            | ActionType::Checker { output_directory, output_ext, fallback_output_exts, .. } => {
+                line.push_str(&format!(" -> {}", format_expected_outputs(test, output_directory, output_ext, fallback_output_exts)));
+            }
+            ActionType::Generate { output_directory, output_ext, .. } => {
+                line.push_str(&format!(" -> {}", test.generated_output_path(output_directory, output_ext).display()));
+            }
+        }
+        line.push_str(&format!(" ({})", format_limits(&test.limits)));
+        if !test.arguments.is_empty() {
+            line.push_str(&format!(", arguments: {}", test.arguments.join(" ")));
+        }
+        if !test.tags.is_empty() {
+            line.push_str(&format!(", tags: {}", test.tags.join(", ")));
+        }
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::fs::File;
+use std::io::{PipeReader, Read, Seek, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::{io, mem, slice, thread};
+use crate::child_handles::kill_children_of_thread;
+use crate::compression::open_decompressed;
+use crate::executor::TestExecutor;
+use crate::prepare_input::Test;
+use crate::temp_files::create_temp_file;
+use crate::test_errors::{ExecutionError, ExecutionMetrics};
+use crate::test_errors::ExecutionError::ExecutorError;
+use crate::testing_utils::{is_hash_file, BYTE_ORDER_MARK, HASH_COMPARISON_THRESHOLD};
+
+/// How often the reader checks whether the program has exited while it has no output to read,
+/// and how often it tries to kill the program if it isn't registered as running yet
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Returns the contents of the expected output the program's output can be compared with while it's running.
+///
+/// That's only possible if there's exactly one output file, which is small enough to not be compared by its hash
+pub(crate) fn early_exit_expected_output(expected_output_paths: &[PathBuf]) -> Option<String> {
+    let mut existing = expected_output_paths.iter().filter(|path| path.is_file());
+    let path = existing.next()?;
+    if existing.next().is_some() || is_hash_file(path) || fs::metadata(path).ok()?.len() > HASH_COMPARISON_THRESHOLD {
+        return None;
+    }
+    let mut expected_output = String::new();
+    open_decompressed(path).ok()?.read_to_string(&mut expected_output).ok()?;
+    Some(expected_output)
+}
+
+/// Compares the tokens of the output with the expected ones as the output arrives in chunks
+struct TokenMatcher<'a> {
+    expected_tokens: slice::Iter<'a, &'a str>,
+    /// The output after its last whitespace, since its last token might continue in the next chunk
+    pending: Vec<u8>,
+    at_start: bool,
+}
+
+impl<'a> TokenMatcher<'a> {
+    fn new(expected_tokens: &'a [&'a str]) -> Self {
+        TokenMatcher { expected_tokens: expected_tokens.iter(), pending: vec![], at_start: true }
+    }
+
+    /// Adds a chunk of the output, returning whether it certainly differs from the expected one
+    fn push(&mut self, chunk: &[u8]) -> bool {
+        self.pending.extend_from_slice(chunk);
+        let complete = self.pending.iter().rposition(u8::is_ascii_whitespace).map_or(0, |index| index + 1);
+        let complete: Vec<u8> = self.pending.drain(..complete).collect();
+        self.compare(&complete)
+    }
+
+    /// Compares the last token after the output ended, returning whether it differs from the expected one
+    fn finish(&mut self) -> bool {
+        let rest = mem::take(&mut self.pending);
+        self.compare(&rest)
+    }
+
+    fn compare(&mut self, output: &[u8]) -> bool {
+        if output.is_empty() {
+            return false;
+        }
+        let output = String::from_utf8_lossy(output);
+        let output = if mem::replace(&mut self.at_start, false) { output.strip_prefix(BYTE_ORDER_MARK).unwrap_or(&output) } else { &output };
+        output.split_whitespace().any(|token| self.expected_tokens.next() != Some(&token))
+    }
+}
+
+/// Waits until the pipe has data to read or all of its writing ends are closed, for at most `timeout`
+fn wait_readable(pipe: &PipeReader, timeout: Duration) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd { fd: pipe.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    // SAFETY: poll only writes to the single pollfd it's given
+    match unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) } {
+        -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => Ok(false),
+        -1 => Err(io::Error::last_os_error()),
+        ready => Ok(ready > 0),
+    }
+}
+
+/// Copies the output to `captured` until its tokens diverge from `expected_tokens`, returning whether they did.
+///
+/// Reading stops when the output ends, or when there's nothing left to read after the program finished,
+/// even if a process it left running in the background still holds the pipe open
+fn read_until_mismatch(mut output: PipeReader, mut captured: &File, expected_tokens: &[&str], program_finished: &AtomicBool) -> io::Result<bool> {
+    let mut matcher = TokenMatcher::new(expected_tokens);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        // Checked before polling, so that everything the program wrote before it finished is read
+        let finished = program_finished.load(Ordering::Acquire);
+        if !wait_readable(&output, POLL_INTERVAL)? {
+            if finished {
+                return Ok(matcher.finish());
+            }
+            continue;
+        }
+        let read = match output.read(&mut buffer) {
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if read == 0 {
+            return Ok(matcher.finish());
+        }
+        captured.write_all(&buffer[..read])?;
+        if matcher.push(&buffer[..read]) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Runs the program with its stdout connected to a pipe, comparing its tokens with the expected output while it's running.
+///
+/// As soon as the output certainly differs from the expected one, the program is killed and the output up to
+/// the first difference is returned, regardless of how the program ended
+pub(crate) fn test_with_early_exit(runner: &impl TestExecutor, test: &Test, stderr_file: &File, expected_output: &str) -> (ExecutionMetrics, Result<File, ExecutionError>) {
+    let expected_tokens: Vec<&str> = expected_output.strip_prefix(BYTE_ORDER_MARK).unwrap_or(expected_output)
+        .split_whitespace()
+        .collect();
+    let (mut captured, (reader, writer)) = match create_temp_file().and_then(|captured| Ok((captured, io::pipe()?))) {
+        Ok(result) => result,
+        Err(error) => return (ExecutionMetrics::NONE, Err(ExecutorError(format!("failed to create the pipe for the program's output: {}", error)))),
+    };
+    let writer = File::from(OwnedFd::from(writer));
+    let testing_thread = thread::current().id();
+    let program_finished = AtomicBool::new(false);
+
+    let captured_output = &captured;
+    let expected_tokens = &expected_tokens;
+    let program_finished = &program_finished;
+    let (metrics, result, mismatch) = thread::scope(|scope| {
+        let reading = scope.spawn(move || {
+            let mismatch = read_until_mismatch(reader, captured_output, expected_tokens, program_finished)?;
+            if mismatch {
+                // The program might not be registered as running yet when its first output is read,
+                // in which case it's killed once it is, unless it finishes first
+                while !kill_children_of_thread(testing_thread) && !program_finished.load(Ordering::Acquire) {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+            Ok::<_, io::Error>(mismatch)
+        });
+        let (metrics, result) = runner.test_to_file(&test.input_source.get_file(), &writer, stderr_file, &test.arguments, &test.working_directory, &test.limits);
+        program_finished.store(true, Ordering::Release);
+        drop(writer);
+        let mismatch = reading.join().expect("The thread reading the program's output panicked");
+        (metrics, result, mismatch)
+    });
+
+    let mismatch = match mismatch.and_then(|mismatch| captured.rewind().map(|_| mismatch)) {
+        Ok(mismatch) => mismatch,
+        Err(error) => return (metrics, Err(ExecutorError(format!("failed to read the program's output: {}", error)))),
+    };
+    if mismatch {
+        return (metrics, Ok(captured));
+    }
+    (metrics, result.map(|_| captured))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenMatcher;
+
+    #[test]
+    fn matches_tokens_split_between_chunks() {
+        let expected = ["12", "345", "6"];
+        let mut matcher = TokenMatcher::new(&expected);
+        assert!(!matcher.push("\u{feff}1".as_bytes()));
+        assert!(!matcher.push(b"2 3"));
+        assert!(!matcher.push(b"45\n"));
+        assert!(!matcher.push(b"6"));
+        assert!(!matcher.finish());
+
+        let mut matcher = TokenMatcher::new(&expected);
+        assert!(!matcher.push(b"12 34"));
+        assert!(matcher.push(b"6 "));
+
+        let mut matcher = TokenMatcher::new(&expected);
+        assert!(!matcher.push(b"12 345 6"));
+        assert!(!matcher.push(b"7"));
+        assert!(matcher.finish());
+        assert!(TokenMatcher::new(&expected).push(b"12 345 6 7\n"));
+        assert!(!TokenMatcher::new(&expected).push(b"12\n"));
+    }
+}
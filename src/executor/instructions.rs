@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::time::Duration;
+use std::{io, mem, ptr};
+use colored::Colorize;
+use perf_event_open_sys::bindings::{perf_event_attr, PERF_COUNT_HW_INSTRUCTIONS, PERF_FLAG_FD_CLOEXEC, PERF_TYPE_HARDWARE};
+use which::which;
+use crate::compiler::Executable;
+use crate::executor::simple::SimpleExecutor;
+use crate::executor::{ExecutionLimits, TestExecutor};
+use crate::formatted_error::FormattedError;
+use crate::temp_files::create_temp_dir;
+use crate::verbose::log;
+use crate::test_errors::{ExecutionError, ExecutionMetrics};
+use crate::test_errors::ExecutionError::TimedOut;
+
+/// The number of instructions counted as one second of runtime, the same as in sio2jail
+const INSTRUCTIONS_PER_SECOND: u64 = 2_000_000_000;
+
+/// Runs the program like [SimpleExecutor], but measures its runtime by counting the instructions
+/// it executes with perf_event, which unlike the wall time doesn't depend on the load of the machine
+pub(crate) struct InstructionsExecutor {
+    executor: SimpleExecutor,
+}
+
+/// Opens an instruction counter for the calling process and all of its future children and threads,
+/// which starts counting once the process calls exec
+fn open_counter() -> io::Result<RawFd> {
+    // SAFETY: perf_event_attr is a plain struct, for which all zeroes is a valid value
+    let mut attributes: perf_event_attr = unsafe { mem::zeroed() };
+    attributes.type_ = PERF_TYPE_HARDWARE;
+    attributes.size = mem::size_of::<perf_event_attr>() as u32;
+    attributes.config = PERF_COUNT_HW_INSTRUCTIONS as u64;
+    attributes.set_disabled(1);
+    attributes.set_enable_on_exec(1);
+    attributes.set_inherit(1);
+    attributes.set_exclude_kernel(1);
+    attributes.set_exclude_hv(1);
+
+    let fd = unsafe { perf_event_open_sys::perf_event_open(&mut attributes, 0, -1, -1, PERF_FLAG_FD_CLOEXEC as libc::c_ulong) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// A buffer for a control message carrying a single file descriptor
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 64]);
+
+/// Sends a file descriptor through a Unix socket. Doesn't allocate, so it can be used in `pre_exec`
+fn send_fd(socket: RawFd, fd: RawFd) -> io::Result<()> {
+    let mut data = [0u8];
+    let mut iov = libc::iovec { iov_base: data.as_mut_ptr().cast(), iov_len: data.len() };
+    let mut control = ControlBuffer([0; 64]);
+    unsafe {
+        let mut message: libc::msghdr = mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.0.as_mut_ptr().cast();
+        message.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(header).cast::<RawFd>(), fd);
+
+        if libc::sendmsg(socket, &message, 0) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Receives a file descriptor sent with [send_fd]
+fn receive_fd(socket: &UnixStream) -> io::Result<OwnedFd> {
+    let mut data = [0u8];
+    let mut iov = libc::iovec { iov_base: data.as_mut_ptr().cast(), iov_len: data.len() };
+    let mut control = ControlBuffer([0; 64]);
+    unsafe {
+        let mut message: libc::msghdr = mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.0.as_mut_ptr().cast();
+        message.msg_controllen = control.0.len() as _;
+
+        if libc::recvmsg(socket.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let header = libc::CMSG_FIRSTHDR(&message);
+        if header.is_null() || (*header).cmsg_level != libc::SOL_SOCKET || (*header).cmsg_type != libc::SCM_RIGHTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the instruction counter wasn't received"));
+        }
+        Ok(OwnedFd::from_raw_fd(ptr::read_unaligned(libc::CMSG_DATA(header).cast::<RawFd>())))
+    }
+}
+
+impl InstructionsExecutor {
+    fn test(&self) -> Result<(), FormattedError> {
+        if let Err(error) = open_counter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }) {
+            if error.kind() == io::ErrorKind::PermissionDenied {
+                return Err(FormattedError::preformatted(format!(
+                    "{}\n{}",
+                    "You need to run the following command to count instructions with toster.\n\
+                    You may also put this option in your /etc/sysctl.conf.\n\
+                    This will make the setting persist across reboots.".red(),
+                    "sudo sysctl -w kernel.perf_event_paranoid=2".white()
+                )));
+            }
+            if error.raw_os_error() == Some(libc::ENOENT) {
+                return Err(FormattedError::from_str(
+                    "Counting instructions isn't supported on this machine, as its CPU doesn't expose hardware performance counters (which is common in virtual machines)"
+                ));
+            }
+            return Err(FormattedError::from_str(&format!("Failed to open the instruction counter: {}", error)));
+        }
+
+        let Ok(true_command_location) = which("true") else {
+            return Err(FormattedError::from_str("The executable for the \"true\" command could not be found"));
+        };
+        let true_executor = InstructionsExecutor {
+            executor: SimpleExecutor { executable: Executable::native(true_command_location), environment: self.executor.environment.clone() },
+        };
+        let null_file = File::open("/dev/null").expect("Opening /dev/null should not fail");
+        let working_directory = create_temp_dir().expect("Failed to create temporary directory");
+        let limits = ExecutionLimits {
+            timeout: Duration::from_secs(5),
+            memory_limit: None,
+            max_processes: None,
+            cpu_timeout: false,
+            kill_grace: Duration::ZERO,
+            sandbox: false,
+        };
+        let (_, result) = true_executor.run(&null_file, &null_file, &null_file, &[], working_directory.path(), &limits)
+            .map_err(|error| FormattedError::from_str(&format!("Failed to count the instructions of a test program: {}", error)))?;
+        result.map_err(|error| FormattedError::from_str(&format!("Failed to count the instructions of a test program: {}", error)))
+    }
+
+    pub(crate) fn init_and_test(executor: SimpleExecutor) -> Result<InstructionsExecutor, FormattedError> {
+        let executor = InstructionsExecutor { executor };
+        executor.test()?;
+        Ok(executor)
+    }
+
+    fn run(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> io::Result<(ExecutionMetrics, Result<(), ExecutionError>)> {
+        // The program is only killed after twice the timeout in wall time,
+        // as it might be waiting on the load of the machine without executing instructions
+        let wall_limits = ExecutionLimits { timeout: limits.timeout * 2, cpu_timeout: false, ..*limits };
+        let mut command = self.executor.command(input_file, output_file, stderr_file, arguments, working_directory, &wall_limits);
+
+        let (parent_socket, child_socket) = UnixStream::pair()?;
+        let child_socket_fd = child_socket.as_raw_fd();
+        // SAFETY: the counter is opened and sent using system calls only
+        unsafe {
+            command.pre_exec(move || send_fd(child_socket_fd, open_counter()?));
+        }
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn()?;
+        drop(child_socket);
+        let mut counter = File::from(receive_fd(&parent_socket)?);
+
+        let (mut metrics, result) = self.executor.wait_for_child(child, &wall_limits);
+        let result = SimpleExecutor::detect_bad_alloc(result, stderr_file);
+        let mut instructions = [0u8; 8];
+        counter.read_exact(&mut instructions)?;
+        let time = Duration::from_secs_f64(u64::from_ne_bytes(instructions) as f64 / INSTRUCTIONS_PER_SECOND as f64);
+        metrics.time = Some(time);
+
+        let result = match result {
+            Ok(()) if time > limits.timeout => Err(TimedOut),
+            result => result,
+        };
+        Ok((metrics, result))
+    }
+}
+
+impl TestExecutor for InstructionsExecutor {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        self.run(input_file, output_file, stderr_file, arguments, working_directory, limits)
+            .expect("Failed to count the instructions of the program")
+    }
+}
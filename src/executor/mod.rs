@@ -1,52 +1,194 @@
 pub(crate) mod simple;
+pub(crate) mod remote;
+pub(crate) mod wsl;
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
 pub(crate) mod sio2jail;
+#[cfg(target_os = "linux")]
+pub(crate) mod sandbox;
+#[cfg(target_os = "linux")]
+pub(crate) mod instructions;
+#[cfg(windows)]
+mod windows_usage;
 
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+#[cfg(unix)]
+use std::io;
+use std::env;
+use std::process::Command;
+use std::time::Duration;
 use crate::executor::simple::SimpleExecutor;
+use crate::executor::remote::RemoteExecutor;
+use crate::executor::wsl::WslExecutor;
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
 use crate::executor::sio2jail::Sio2jailExecutor;
+#[cfg(target_os = "linux")]
+use crate::executor::instructions::InstructionsExecutor;
 use crate::temp_files::create_temp_file;
+#[cfg(target_os = "linux")]
+use crate::core_dumps::core_dumps_enabled;
 use crate::test_errors::{ExecutionError, ExecutionMetrics};
 
+/// The limits a single execution of the program is subject to
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ExecutionLimits {
+    pub(crate) timeout: Duration,
+    /// The memory limit in KiB. Only enforced by executors that support measuring memory use
+    pub(crate) memory_limit: Option<u64>,
+    /// The maximum number of processes the user running the program can have, enforced with RLIMIT_NPROC
+    #[cfg(unix)]
+    pub(crate) max_processes: Option<u64>,
+    /// Whether the timeout applies to the CPU time used by the program instead of the wall time.
+    /// The program is then killed only after twice the timeout passes in wall time
+    #[cfg(unix)]
+    pub(crate) cpu_timeout: bool,
+    /// How long the program has to exit after getting SIGTERM on timeout before it's killed with SIGKILL
+    #[cfg(unix)]
+    pub(crate) kill_grace: Duration,
+    /// Whether the program is denied writing files outside of its working directory
+    #[cfg(target_os = "linux")]
+    pub(crate) sandbox: bool,
+}
+
+/// Makes the command apply the process limit to the spawned child, and let it dump its core if --core-dumps is used
+#[cfg(unix)]
+pub(crate) fn apply_process_limit(command: &mut Command, limits: &ExecutionLimits) {
+    use std::os::unix::process::CommandExt;
+
+    #[cfg(target_os = "linux")]
+    let core_dumps = core_dumps_enabled();
+    #[cfg(not(target_os = "linux"))]
+    let core_dumps = false;
+    if limits.max_processes.is_none() && !core_dumps {
+        return;
+    }
+    let process_limit = limits.max_processes.map(|max_processes| libc::rlimit {
+        rlim_cur: max_processes as libc::rlim_t,
+        rlim_max: max_processes as libc::rlim_t,
+    });
+    // SAFETY: getrlimit and setrlimit are async-signal-safe and the closure doesn't allocate
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(limit) = process_limit {
+                if libc::setrlimit(libc::RLIMIT_NPROC, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if core_dumps {
+                // The soft limit can be raised up to the hard one without any privileges
+                let mut limit: libc::rlimit = std::mem::zeroed();
+                if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                limit.rlim_cur = limit.rlim_max;
+                if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// The environment variable the seed given with --seed is passed to the tested program and the checker in
+pub(crate) const SEED_VARIABLE: &str = "TOSTER_SEED";
+
+/// The environment variables the program is run with
+#[derive(Clone, Debug)]
+pub(crate) struct Environment {
+    /// Whether all of toster's environment variables are passed to the program,
+    /// otherwise only the ones needed to run programs at all are kept
+    pub(crate) inherit: bool,
+    pub(crate) variables: Vec<(String, String)>,
+}
+
+impl Environment {
+    #[cfg(not(windows))]
+    const KEPT_VARIABLES: &'static [&'static str] = &["PATH"];
+    #[cfg(windows)]
+    const KEPT_VARIABLES: &'static [&'static str] = &["PATH", "SYSTEMROOT"];
+
+    /// All of toster's environment variables, along with the given ones
+    pub(crate) fn inherit_with(variables: Vec<(String, String)>) -> Self {
+        Environment { inherit: true, variables }
+    }
+
+    pub(crate) fn apply(&self, command: &mut Command) {
+        if !self.inherit {
+            command.env_clear();
+            for name in Self::KEPT_VARIABLES {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+        command.envs(self.variables.iter().map(|(name, value)| (name, value)));
+    }
+}
+
 pub(crate) trait TestExecutor: Sync + Send {
     /// Executes the program.
     ///
-    /// Stdin is read from `input_file`, stderr is ignored.
-    /// Stdout is written to `output_file`.
+    /// Stdin is read from `input_file`.
+    /// Stdout is written to `output_file` and stderr to `stderr_file`.
     /// `input_file` might not be read fully. `output_file` **is not** rewound.
-    fn test_to_file(&self, input_file: &File, output_file: &File) -> (ExecutionMetrics, Result<(), ExecutionError>);
+    /// The program is run with `arguments` in `working_directory`, which has to exist.
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>);
 }
 
 /// Creates a tempfile for stdout and executes the program.
 ///
 /// Returns execution metrics and output file (if there are no errors during execution).
 ///
-/// Stdin is read from `input_file`, stderr is ignored.
+/// Stdin is read from `input_file`, stderr is written to `stderr_file`.
 /// `input_file` might not be read fully. Output file **is** rewound before returning.
-pub(crate) fn test_to_temp(executor: &impl TestExecutor, input_file: &File) -> (ExecutionMetrics, Result<impl Read, ExecutionError>) {
+pub(crate) fn test_to_temp(executor: &impl TestExecutor, input_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<File, ExecutionError>) {
     let mut stdout_memfile = create_temp_file().expect("Failed to create memfile");
     let (metrics, result) = executor.test_to_file(
         input_file,
         &stdout_memfile,
+        stderr_file,
+        arguments,
+        working_directory,
+        limits,
     );
     stdout_memfile.rewind().expect("Failed to rewind memfile");
     (metrics, result.map(|_| stdout_memfile))
 }
 
+/// Reads the last bytes of the program's stderr file, returning them along with their position in the file.
+/// Used to find the line a wrapper running the program appended to it
+pub(crate) fn stderr_end(mut stderr_file: &File) -> Option<(u64, String)> {
+    const READ_BYTES: u64 = 256;
+    let length = stderr_file.metadata().ok()?.len();
+    let start = length.saturating_sub(READ_BYTES);
+    stderr_file.seek(SeekFrom::Start(start)).ok()?;
+    let mut end = vec![];
+    stderr_file.read_to_end(&mut end).ok()?;
+    Some((start, String::from_utf8_lossy(&end).to_string()))
+}
+
 pub(crate) enum AnyTestExecutor {
     Simple(SimpleExecutor),
     #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
     Sio2Jail(Sio2jailExecutor),
+    #[cfg(target_os = "linux")]
+    Instructions(InstructionsExecutor),
+    Remote(RemoteExecutor),
+    Wsl(WslExecutor),
 }
 
 impl TestExecutor for AnyTestExecutor {
-    fn test_to_file(&self, input_file: &File, output_file: &File) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
         match self {
-            AnyTestExecutor::Simple(executor) => executor.test_to_file(input_file, output_file),
+            AnyTestExecutor::Simple(executor) => executor.test_to_file(input_file, output_file, stderr_file, arguments, working_directory, limits),
             #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-            AnyTestExecutor::Sio2Jail(executor) => executor.test_to_file(input_file, output_file),
+            AnyTestExecutor::Sio2Jail(executor) => executor.test_to_file(input_file, output_file, stderr_file, arguments, working_directory, limits),
+            #[cfg(target_os = "linux")]
+            AnyTestExecutor::Instructions(executor) => executor.test_to_file(input_file, output_file, stderr_file, arguments, working_directory, limits),
+            AnyTestExecutor::Remote(executor) => executor.test_to_file(input_file, output_file, stderr_file, arguments, working_directory, limits),
+            AnyTestExecutor::Wsl(executor) => executor.test_to_file(input_file, output_file, stderr_file, arguments, working_directory, limits),
         }
     }
 }
@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tempfile::TempDir;
+use crate::compiler::Executable;
+use crate::executor::{stderr_end, Environment, ExecutionLimits, TestExecutor};
+use crate::executor::simple::SimpleExecutor;
+use crate::formatted_error::FormattedError;
+use crate::temp_files::{create_temp_dir, make_cloned_stdio};
+use crate::test_errors::{ExecutionError, ExecutionMetrics};
+use crate::test_errors::ExecutionError::{ExecutorError, RuntimeError, TimedOut};
+use crate::verbose::log;
+
+/// The name the program is copied to in the remote directory
+const REMOTE_PROGRAM: &str = "program";
+
+/// Starts the line the remote command appends to the program's stderr, followed by the exit code of the program
+/// and its runtime in nanoseconds (or nothing if the remote `date` can't measure it)
+const STATUS_MARKER: &str = "toster-remote-status";
+
+/// How much longer than the timeout ssh is waited for locally, since the program is killed on the remote machine
+/// and the connection adds its latency to the local runtime
+const CONNECTION_MARGIN: Duration = Duration::from_secs(5);
+
+/// The exit code of ssh when the connection fails, see ssh(1)
+const SSH_ERROR_CODE: i32 = 255;
+
+/// The remote directory and the ssh connection of the executor, which are cleaned up when toster exits,
+/// even if the executor isn't dropped because toster was stopped with Ctrl+C or panicked
+static REMOTE_SESSION: Mutex<Option<RemoteSession>> = Mutex::new(None);
+
+fn quote(argument: &str) -> String {
+    shlex::try_quote(argument).expect("The argument contains a null byte").to_string()
+}
+
+/// The ssh connection to the remote machine, shared by all of the commands through a ControlMaster socket
+#[derive(Clone)]
+struct Connection {
+    host: String,
+    control_path: PathBuf,
+}
+
+impl Connection {
+    fn ssh_command(&self, remote_command: &str) -> Command {
+        let mut command = Command::new("ssh");
+        command
+            // Makes ssh fail instead of asking for a password, which toster can't pass on
+            .args(["-o", "BatchMode=yes"])
+            // The first command opens the connection in the background, and the next ones reuse it instead of logging in again
+            .args(["-o", "ControlMaster=auto", "-o", "ControlPersist=yes", "-o"])
+            .arg(format!("ControlPath={}", self.control_path.display()))
+            .arg(&self.host)
+            .arg(remote_command);
+        command
+    }
+
+    /// Runs a command on the remote machine, returning its stdout
+    fn run(&self, remote_command: &str, stdin: Stdio) -> Result<String, String> {
+        log(2, || format!("Running {} on {}", remote_command, self.host));
+        let output = self.ssh_command(remote_command)
+            .stdin(stdin)
+            .output()
+            .map_err(|error| format!("Failed to run ssh: {}", error))?;
+        if !output.status.success() {
+            return Err(format!("\"{}\" failed on {}:\n{}", remote_command, self.host, String::from_utf8_lossy(&output.stderr).trim_end()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Closes the connection that was kept open in the background
+    fn close(&self) {
+        let _ = Command::new("ssh")
+            .args(["-O", "exit", "-o"])
+            .arg(format!("ControlPath={}", self.control_path.display()))
+            .arg(&self.host)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+struct RemoteSession {
+    connection: Connection,
+    remote_directory: String,
+    /// The local directory containing the ControlMaster socket
+    _control_directory: TempDir,
+}
+
+impl Drop for RemoteSession {
+    fn drop(&mut self) {
+        // The directory is only cleaned up, so failing to remove it isn't an error
+        let _ = self.connection.run(&format!("rm -rf {}", quote(&self.remote_directory)), Stdio::null());
+        self.connection.close();
+    }
+}
+
+/// Removes the remote directory and closes the connection of the remote executor, if there is one
+pub(crate) fn clean_up_remote() {
+    let session = REMOTE_SESSION.lock().unwrap_or_else(PoisonError::into_inner).take();
+    drop(session);
+}
+
+/// Runs the program on another machine over ssh, streaming the input and output of every test through the connection.
+///
+/// The program is copied to a temporary directory on the remote machine, and every test is run in its own directory inside of it.
+/// The runtime is measured on the remote machine if its `date` supports nanoseconds, and locally (including the latency of the connection) otherwise
+pub(crate) struct RemoteExecutor {
+    connection: Connection,
+    remote_directory: String,
+    interpreter: Option<Vec<String>>,
+    environment: Environment,
+    /// Runs ssh locally, waiting for it with the usual timeout handling
+    ssh: SimpleExecutor,
+}
+
+impl RemoteExecutor {
+    /// Connects to `host`, creates a temporary directory there and copies the program to it
+    pub(crate) fn init(executable: Executable, host: &str, environment: Environment) -> Result<RemoteExecutor, FormattedError> {
+        let error = |error: String| FormattedError::from_str(&format!("Failed to prepare the program on {}: {}", host, error));
+
+        let program = File::open(&executable.path)
+            .map_err(|error| FormattedError::from_str(&format!("Failed to open the program: {}", error)))?;
+        let control_directory = create_temp_dir()
+            .map_err(|error| FormattedError::from_str(&format!("Failed to create a temporary directory: {}", error)))?;
+        let connection = Connection { host: host.to_string(), control_path: control_directory.path().join("ssh") };
+        let remote_directory = match connection.run("mktemp -d", Stdio::null()) {
+            Ok(remote_directory) => remote_directory,
+            Err(message) => {
+                connection.close();
+                return Err(error(message));
+            }
+        };
+        *REMOTE_SESSION.lock().unwrap_or_else(PoisonError::into_inner) = Some(RemoteSession {
+            connection: connection.clone(),
+            remote_directory: remote_directory.clone(),
+            _control_directory: control_directory,
+        });
+
+        let remote_program = quote(&format!("{}/{}", remote_directory, REMOTE_PROGRAM));
+        connection.run(&format!("cat > {} && chmod +x {}", remote_program, remote_program), Stdio::from(program)).map_err(|message| {
+            clean_up_remote();
+            error(message)
+        })?;
+
+        Ok(RemoteExecutor {
+            connection,
+            remote_directory,
+            interpreter: executable.interpreter,
+            environment,
+            ssh: SimpleExecutor {
+                executable: Executable::native("ssh".into()),
+                environment: Environment::inherit_with(vec![]),
+            },
+        })
+    }
+
+    /// The shell command running the program with `arguments` on the remote machine, in the directory `test_directory`
+    /// created inside of the remote directory and removed afterwards.
+    ///
+    /// The program is run with the timeout command, so that it doesn't keep running remotely when the connection is closed.
+    /// Its exit code and runtime are appended to its stderr after a [`STATUS_MARKER`].
+    /// The script is run with sh, since the login shell of the remote user might not be POSIX compatible
+    fn remote_command(&self, test_directory: &str, arguments: &[String], limits: &ExecutionLimits) -> String {
+        let mut command = vec!["env".to_string()];
+        if !self.environment.inherit {
+            command.extend(["-i".to_string(), "PATH=\"$PATH\"".to_string()]);
+        }
+        command.extend(self.environment.variables.iter().map(|(name, value)| quote(&format!("{}={}", name, value))));
+        command.extend(["timeout".to_string(), "-s".to_string(), "KILL".to_string(), format!("{:.3}", limits.timeout.as_secs_f64())]);
+        if let Some(interpreter) = &self.interpreter {
+            command.extend(interpreter.iter().map(|argument| quote(argument)));
+        }
+        command.push(format!("../{}", REMOTE_PROGRAM));
+        command.extend(arguments.iter().map(|argument| quote(argument)));
+
+        let directory = quote(test_directory);
+        let script = [
+            format!("cd {} && mkdir {} && cd {} || exit {}", quote(&self.remote_directory), directory, directory, SSH_ERROR_CODE),
+            "start=$(date +%s%N)".to_string(),
+            command.join(" "),
+            "status=$?".to_string(),
+            "end=$(date +%s%N)".to_string(),
+            format!("cd .. && rm -rf {}", directory),
+            // Without support for %N, date prints it literally
+            "case \"$start$end\" in *N*) elapsed= ;; *) elapsed=$((end - start)) ;; esac".to_string(),
+            format!("printf '\\n{} %s %s\\n' \"$status\" \"$elapsed\" >&2", STATUS_MARKER),
+            "exit \"$status\"".to_string(),
+        ].join("\n");
+        format!("sh -c {}", quote(&script))
+    }
+
+    /// Removes the line with the exit code and the runtime from the end of the program's stderr and returns them
+    fn take_status(stderr_file: &File) -> Option<(i32, Option<Duration>)> {
+        let (start, end) = stderr_end(stderr_file)?;
+        let line_start = end.rfind(&format!("\n{} ", STATUS_MARKER))?;
+        let mut parts = end[line_start..].split_whitespace().skip(1);
+        let status = parts.next()?.parse().ok()?;
+        let elapsed = parts.next().and_then(|elapsed| elapsed.parse().ok()).map(Duration::from_nanos);
+        stderr_file.set_len(start + line_start as u64).ok()?;
+        Some((status, elapsed))
+    }
+
+    /// Turns the exit code of the program into its result, like [`SimpleExecutor::map_status_code`].
+    /// The remote shell reports the programs killed by a signal with an exit code of 128 plus the signal number
+    fn map_remote_status(status: i32, time: Option<Duration>, limits: &ExecutionLimits) -> Result<(), ExecutionError> {
+        const SIGKILL_STATUS: i32 = 128 + 9;
+        match status {
+            0 => Ok(()),
+            SIGKILL_STATUS if time.is_none_or(|time| time >= limits.timeout) => Err(TimedOut),
+            status if status > 128 => Err(RuntimeError(format!("- the process was terminated by signal {}", status - 128))),
+            status => Err(RuntimeError(format!("- the program returned a non-zero return code: {}", status))),
+        }
+    }
+
+    /// The error of ssh failing to run the command, with the error ssh printed last
+    fn connection_error(status: &ExitStatus, stderr_file: &File) -> ExecutionError {
+        let message = stderr_end(stderr_file)
+            .and_then(|(_, end)| end.lines().rev().find(|line| !line.trim().is_empty()).map(|line| line.trim().to_string()));
+        match message {
+            Some(message) => ExecutorError(format!("ssh failed with {}: {}", status, message)),
+            None => ExecutorError(format!("ssh failed with {}", status)),
+        }
+    }
+}
+
+impl TestExecutor for RemoteExecutor {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        let test_directory = working_directory.file_name().map_or("test".into(), |name| name.to_string_lossy());
+        let mut command = self.connection.ssh_command(&self.remote_command(&test_directory, arguments, limits));
+        command
+            .stdin(make_cloned_stdio(input_file))
+            .stdout(make_cloned_stdio(output_file))
+            .stderr(make_cloned_stdio(stderr_file))
+            .current_dir(working_directory);
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn().expect("Failed to spawn ssh");
+
+        let local_limits = ExecutionLimits { timeout: limits.timeout + CONNECTION_MARGIN, ..*limits };
+        let (local_metrics, status) = self.ssh.wait_for_status(child, &local_limits);
+        // The CPU time of ssh says nothing about the program
+        let mut metrics = ExecutionMetrics { cpu_time: None, ..local_metrics };
+        let status = match status {
+            Ok(status) => status,
+            Err(error) => return (metrics, Err(error)),
+        };
+        let result = match Self::take_status(stderr_file) {
+            Some((remote_status, elapsed)) => {
+                metrics.time = elapsed.or(metrics.time);
+                Self::map_remote_status(remote_status, elapsed, limits)
+            }
+            None => Err(Self::connection_error(&status, stderr_file)),
+        };
+        (metrics, SimpleExecutor::detect_bad_alloc(result, stderr_file))
+    }
+}
+
+impl Drop for RemoteExecutor {
+    fn drop(&mut self) {
+        clean_up_remote();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, Write};
+    use std::time::Duration;
+    use super::{RemoteExecutor, STATUS_MARKER};
+    use crate::executor::ExecutionLimits;
+    use crate::test_errors::ExecutionError::{RuntimeError, TimedOut};
+
+    #[test]
+    fn takes_the_status_from_stderr() {
+        let mut stderr = tempfile::tempfile().unwrap();
+        write!(stderr, "error\n\n{} 3 1500000\n", STATUS_MARKER).unwrap();
+        assert_eq!(RemoteExecutor::take_status(&stderr), Some((3, Some(Duration::from_micros(1500)))));
+        let mut contents = String::new();
+        stderr.rewind().unwrap();
+        stderr.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "error\n");
+
+        let mut stderr = tempfile::tempfile().unwrap();
+        write!(stderr, "\n{} 0 \n", STATUS_MARKER).unwrap();
+        assert_eq!(RemoteExecutor::take_status(&stderr), Some((0, None)));
+        assert_eq!(RemoteExecutor::take_status(&tempfile::tempfile().unwrap()), None);
+    }
+
+    #[test]
+    fn maps_remote_statuses() {
+        let limits = ExecutionLimits {
+            timeout: Duration::from_secs(1),
+            memory_limit: None,
+            #[cfg(unix)]
+            max_processes: None,
+            #[cfg(unix)]
+            cpu_timeout: false,
+            #[cfg(unix)]
+            kill_grace: Duration::ZERO,
+            #[cfg(target_os = "linux")]
+            sandbox: false,
+        };
+        let map = |status, time| RemoteExecutor::map_remote_status(status, time, &limits);
+        assert!(map(0, None).is_ok());
+        assert!(matches!(map(137, Some(Duration::from_secs(1))), Err(TimedOut)));
+        assert!(matches!(map(137, Some(Duration::from_millis(10))), Err(RuntimeError(_))));
+        assert!(matches!(map(1, None), Err(RuntimeError(_))));
+    }
+}
@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+use std::os::unix::process::CommandExt;
+use crate::executor::ExecutionLimits;
+use crate::temp_files::create_temp_dir;
+use landlock::{AccessFs, CompatLevel, Compatible, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreated, RulesetCreatedAttr, ABI};
+
+/// The newest Landlock ABI whose write access rights are handled,
+/// older kernels only enforce the rights they support
+const ABI_VERSION: ABI = ABI::V3;
+
+fn create_ruleset(writable_directory: &Path) -> Result<RulesetCreated, Box<dyn Error>> {
+    Ok(Ruleset::default()
+        // Landlock version 1 is required for the sandbox to protect anything at all
+        .set_compatibility(CompatLevel::HardRequirement)
+        .handle_access(AccessFs::from_write(ABI::V1))?
+        .set_compatibility(CompatLevel::BestEffort)
+        .handle_access(AccessFs::from_write(ABI_VERSION))?
+        .create()?
+        .add_rule(PathBeneath::new(PathFd::new(writable_directory)?, AccessFs::from_write(ABI_VERSION)))?
+        .add_rule(PathBeneath::new(PathFd::new("/dev/null")?, AccessFs::WriteFile))?)
+}
+
+/// Checks if the kernel supports Landlock, which is used for sandboxing
+pub(crate) fn verify_sandbox_support() -> Result<(), String> {
+    let directory = create_temp_dir().expect("Failed to create temporary directory");
+    create_ruleset(directory.path())
+        .map(|_| ())
+        .map_err(|error| format!("Sandboxing requires a Linux kernel with Landlock enabled: {}", error))
+}
+
+/// Makes the command deny the spawned child writing to any files outside `writable_directory`
+fn restrict_writes(command: &mut Command, writable_directory: &Path) {
+    let mut ruleset = Some(create_ruleset(writable_directory).expect("Failed to create the sandbox ruleset"));
+    // SAFETY: the ruleset is created before forking, the closure only makes system calls
+    unsafe {
+        command.pre_exec(move || {
+            ruleset.take()
+                .expect("The sandbox should only be applied once")
+                .restrict_self()
+                .map_err(|error| std::io::Error::other(error.to_string()))?;
+            Ok(())
+        });
+    }
+}
+
+/// Makes the command deny the spawned child writing to any files outside `working_directory`
+/// if sandboxing is enabled
+pub(crate) fn apply_sandbox(command: &mut Command, working_directory: &Path, limits: &ExecutionLimits) {
+    if limits.sandbox {
+        restrict_writes(command, working_directory);
+    }
+}
@@ -1,25 +1,45 @@
 use std::fs::File;
-use std::path::PathBuf;
-use std::process::{Child, Command, ExitStatus, Stdio};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
 use std::time::{Duration, Instant};
 use crate::test_errors::{ExecutionError, ExecutionMetrics};
+#[cfg(not(unix))]
 use wait_timeout::ChildExt;
-use crate::executor::TestExecutor;
-use crate::test_errors::ExecutionError::{RuntimeError, TimedOut};
-
+use crate::executor::{Environment, ExecutionLimits, TestExecutor};
+use crate::test_errors::ExecutionError::{MemoryLimitExceeded, RuntimeError, TimedOut};
+#[cfg(unix)]
+use crate::test_errors::ExecutionError::Interrupted;
+use crate::child_handles::ChildHandle;
 #[cfg(unix)]
-use crate::generic_utils::halt;
+use crate::child_handles::{terminate_child, wait_for_exit};
+use crate::compiler::Executable;
+use crate::verbose::log;
+
 #[cfg(unix)]
 use std::os::unix::process::ExitStatusExt;
+#[cfg(unix)]
+use std::{io, mem, thread};
+#[cfg(unix)]
+use std::sync::mpsc;
 use crate::temp_files::make_cloned_stdio;
+#[cfg(unix)]
+use crate::executor::apply_process_limit;
+#[cfg(target_os = "linux")]
+use crate::executor::sandbox::apply_sandbox;
+
+/// The part of the message libstdc++ prints when the program is terminated because an allocation failed
+const BAD_ALLOC_MESSAGE: &str = "std::bad_alloc";
+/// How many bytes at the end of the program's stderr are searched for `BAD_ALLOC_MESSAGE`
+const BAD_ALLOC_SEARCHED_BYTES: u64 = 64 * 1024;
 
 pub(crate) struct SimpleExecutor {
-    pub(crate) timeout: Duration,
-    pub(crate) executable_path: PathBuf,
+    pub(crate) executable: Executable,
+    pub(crate) environment: Environment,
 }
 
 impl SimpleExecutor {
-    fn map_status_code(status: &ExitStatus) -> Result<(), ExecutionError> {
+    pub(crate) fn map_status_code(status: &ExitStatus) -> Result<(), ExecutionError> {
         match status.code() {
             Some(0) => Ok(()),
             Some(exit_code) => {
@@ -28,7 +48,7 @@ impl SimpleExecutor {
             None => {
                 #[cfg(unix)]
                 if status.signal().expect("The program returned an invalid status code") == 2 {
-                    halt();
+                    return Err(Interrupted);
                 }
 
                 Err(RuntimeError(format!("- the process was terminated with the following error:\n{}", status)))
@@ -36,31 +56,139 @@ impl SimpleExecutor {
         }
     }
 
-    fn wait_for_child(&self, mut child: Child) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+    /// Waits for the child to exit for at most `timeout`.
+    ///
+    /// If it times out, it gets SIGTERM and `kill_grace` to exit before it's killed with SIGKILL.
+    ///
+    /// Returns its exit status (or `None` if it timed out), and the wall and CPU time it used
+    #[cfg(unix)]
+    pub(crate) fn wait_with_usage(child: Child, timeout: Duration, kill_grace: Duration) -> (Option<ExitStatus>, ExecutionMetrics) {
+        let pid = child.id() as libc::pid_t;
         let start_time = Instant::now();
-        let status = child.wait_timeout(self.timeout).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        // wait4 blocks until the child exits, so it's called on a separate thread to be able to time out
+        thread::spawn(move || {
+            wait_for_exit(pid as u32).expect("Failed to wait for the child");
+            let mut status = 0;
+            // SAFETY: rusage is a plain struct, for which all zeroes is a valid value
+            let mut usage: libc::rusage = unsafe { mem::zeroed() };
+            while unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } == -1 {
+                if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    panic!("Failed to wait for the child: {}", io::Error::last_os_error());
+                }
+            }
+            let _ = sender.send((start_time.elapsed(), status, usage));
+        });
 
-        match status {
-            Some(status) => (
-                ExecutionMetrics { time: Some(start_time.elapsed()), memory_kibibytes: None },
-                SimpleExecutor::map_status_code(&status)
-            ),
-            None => {
-                child.kill().unwrap();
-                (ExecutionMetrics { time: Some(self.timeout), memory_kibibytes: None }, Err(TimedOut))
+        let (timed_out, (wall_time, status, usage)) = match receiver.recv_timeout(timeout) {
+            Ok(result) => (false, result),
+            Err(_) => {
+                let mut terminated = None;
+                terminate_child(pid as u32, kill_grace, |timeout| {
+                    terminated = receiver.recv_timeout(timeout).ok();
+                    terminated.is_some()
+                });
+                (true, terminated.unwrap_or_else(|| receiver.recv().expect("Failed to wait for the child")))
             }
+        };
+        let cpu_time = [usage.ru_utime, usage.ru_stime].iter()
+            .map(|time| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000))
+            .sum();
+
+        let metrics = ExecutionMetrics { time: Some(wall_time), cpu_time: Some(cpu_time), memory_kibibytes: None };
+        ((!timed_out).then(|| ExitStatus::from_raw(status)), metrics)
+    }
+
+    /// On Windows, wait_timeout waits on the process handle with WaitForSingleObject, so no watchdog thread is needed.
+    /// The CPU time and peak memory use are read from the process handle before it's closed
+    #[cfg(not(unix))]
+    fn wait_with_usage(mut child: Child, timeout: Duration) -> (Option<ExitStatus>, ExecutionMetrics) {
+        let start_time = Instant::now();
+        let status = child.wait_timeout(timeout).unwrap();
+        if status.is_none() {
+            // The child might have exited since the timeout, which isn't an error
+            let _ = child.kill();
+            child.wait().expect("Failed to wait for the child");
+        }
+        let wall_time = start_time.elapsed();
+
+        #[cfg(windows)]
+        let (cpu_time, memory_kibibytes) = crate::executor::windows_usage::process_usage(&child);
+        #[cfg(not(windows))]
+        let (cpu_time, memory_kibibytes) = (None, None);
+        (status, ExecutionMetrics { time: Some(wall_time), cpu_time, memory_kibibytes })
+    }
+
+    pub(crate) fn wait_for_child(&self, child: Child, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        let (metrics, status) = self.wait_for_status(child, limits);
+        (metrics, status.and_then(|status| SimpleExecutor::map_status_code(&status)))
+    }
+
+    /// Like [`SimpleExecutor::wait_for_child`], but returns the exit status of the child instead of turning it into an error
+    pub(crate) fn wait_for_status(&self, child: Child, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<ExitStatus, ExecutionError>) {
+        let _handle = ChildHandle::register(&child, limits);
+        #[cfg(unix)]
+        let wall_timeout = if limits.cpu_timeout { limits.timeout * 2 } else { limits.timeout };
+        #[cfg(not(unix))]
+        let wall_timeout = limits.timeout;
+        #[cfg(unix)]
+        let (status, metrics) = Self::wait_with_usage(child, wall_timeout, limits.kill_grace);
+        #[cfg(not(unix))]
+        let (status, metrics) = Self::wait_with_usage(child, wall_timeout);
+
+        let Some(status) = status else {
+            return (ExecutionMetrics { time: Some(wall_timeout), ..metrics }, Err(TimedOut));
+        };
+        #[cfg(unix)]
+        if limits.cpu_timeout && metrics.cpu_time.is_some_and(|cpu_time| cpu_time > limits.timeout) {
+            return (metrics, Err(TimedOut));
         }
+        (metrics, Ok(status))
     }
-}
 
-impl TestExecutor for SimpleExecutor {
-    fn test_to_file(&self, input_file: &File, output_file: &File) -> (ExecutionMetrics, Result<(), ExecutionError>) {
-        let child = Command::new(&self.executable_path)
+    /// Turns a runtime error caused by a failed allocation into exceeding the memory limit,
+    /// based on the `stderr_file` of the program
+    pub(crate) fn detect_bad_alloc(result: Result<(), ExecutionError>, mut stderr_file: &File) -> Result<(), ExecutionError> {
+        let Err(RuntimeError(_)) = result else {
+            return result;
+        };
+        // The message is printed right before the program is terminated, so only the end of stderr is read
+        let length = stderr_file.metadata().expect("Failed to read the program's stderr").len();
+        stderr_file.seek(SeekFrom::Start(length.saturating_sub(BAD_ALLOC_SEARCHED_BYTES))).expect("Failed to seek memfile");
+        let mut stderr = vec![];
+        stderr_file.read_to_end(&mut stderr).expect("Failed to read the program's stderr");
+        if String::from_utf8_lossy(&stderr).contains(BAD_ALLOC_MESSAGE) {
+            return Err(MemoryLimitExceeded);
+        }
+        result
+    }
+
+    /// Creates the command running the program, with all of the limits applied
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub(crate) fn command(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> Command {
+        let mut command = self.executable.command();
+        command
+            .args(arguments)
             .stdin(make_cloned_stdio(input_file))
             .stdout(make_cloned_stdio(output_file))
-            .stderr(Stdio::null())
-            .spawn().expect("Failed to spawn child");
+            .stderr(make_cloned_stdio(stderr_file))
+            .current_dir(working_directory);
+        self.environment.apply(&mut command);
+        #[cfg(unix)]
+        apply_process_limit(&mut command, limits);
+        #[cfg(target_os = "linux")]
+        apply_sandbox(&mut command, working_directory, limits);
+        command
+    }
+}
+
+impl TestExecutor for SimpleExecutor {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        let mut command = self.command(input_file, output_file, stderr_file, arguments, working_directory, limits);
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn().expect("Failed to spawn child");
 
-        self.wait_for_child(child)
+        let (metrics, result) = self.wait_for_child(child, limits);
+        (metrics, Self::detect_bad_alloc(result, stderr_file))
     }
 }
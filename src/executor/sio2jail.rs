@@ -1,4 +1,6 @@
-use std::fs::File;
+use std::fs;
+use std::fs::{File, Permissions};
+use std::os::unix::fs::PermissionsExt;
 use std::io::{read_to_string, Seek};
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
@@ -7,20 +9,22 @@ use std::time::Duration;
 use colored::Colorize;
 use command_fds::{CommandFdExt, FdMapping};
 use directories::BaseDirs;
-use wait_timeout::ChildExt;
 use which::which;
-use crate::temp_files::{create_temp_file, make_cloned_stdio};
-use crate::executor::TestExecutor;
+use crate::temp_files::{create_temp_dir, create_temp_file, make_cloned_stdio};
+use crate::executor::{apply_process_limit, Environment, ExecutionLimits, TestExecutor};
+use crate::executor::simple::SimpleExecutor;
+use crate::executor::sandbox::apply_sandbox;
 use crate::formatted_error::FormattedError;
-use crate::generic_utils::halt;
+use crate::compiler::Executable;
+use crate::child_handles::ChildHandle;
+use crate::verbose::log;
 use crate::test_errors::{ExecutionError, ExecutionMetrics};
-use crate::test_errors::ExecutionError::{MemoryLimitExceeded, RuntimeError, Sio2jailError, TimedOut};
+use crate::test_errors::ExecutionError::{Interrupted, MemoryLimitExceeded, RuntimeError, Sio2jailError, TimedOut};
 
 pub(crate) struct Sio2jailExecutor {
-    timeout: Duration,
     executable_path: PathBuf,
     sio2jail_path: PathBuf,
-    memory_limit: u64,
+    environment: Environment,
 }
 
 struct Sio2jailOutput {
@@ -29,46 +33,80 @@ struct Sio2jailOutput {
     sio2jail_output: String,
 }
 
+/// The sio2jail binary bundled with toster, installed with `toster install-sio2jail`
+const BUNDLED_SIO2JAIL: &[u8] = include_bytes!("../../sio2jail");
+
+/// The default location of sio2jail, in the user's executable directory
+fn default_sio2jail_path() -> Result<PathBuf, String> {
+    let Some(binding) = BaseDirs::new() else {
+        return Err("No valid home directory path could be retrieved from the operating system".to_string());
+    };
+    let Some(executable_dir) = binding.executable_dir() else {
+        return Err("Couldn't locate the user's executable directory".to_string());
+    };
+    Ok(executable_dir.join("sio2jail"))
+}
+
+/// Installs the bundled sio2jail binary at the given path, or the default location if there is none
+pub(crate) fn install_sio2jail(path: Option<PathBuf>) -> Result<PathBuf, FormattedError> {
+    let path = match path {
+        Some(path) => path,
+        None => default_sio2jail_path().map_err(|error| FormattedError::from_str(&error))?,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| FormattedError::from_str(
+            &format!("Couldn't create the {} directory: {}", parent.display(), error)
+        ))?;
+    }
+    fs::write(&path, BUNDLED_SIO2JAIL).map_err(|error| FormattedError::from_str(
+        &format!("Couldn't write sio2jail to {}: {}", path.display(), error)
+    ))?;
+    fs::set_permissions(&path, Permissions::from_mode(0o755)).map_err(|error| FormattedError::from_str(
+        &format!("Couldn't set execute permissions on sio2jail at {}: {}", path.display(), error)
+    ))?;
+    Ok(path)
+}
+
 impl Sio2jailExecutor {
-    fn get_sio2jail_path() -> Result<PathBuf, FormattedError> {
-        let Some(binding) = BaseDirs::new() else {
-            return Err(FormattedError::from_str(
-                "No valid home directory path could be retrieved from the operating system. Sio2jail could not be found"
-            ));
-        };
-        let Some(executable_dir) = binding.executable_dir() else {
-            return Err(FormattedError::from_str(
-                "Couldn't locate the user's executable directory. Sio2jail could not be found"
-            ));
+    fn get_sio2jail_path(custom_path: Option<&Path>) -> Result<PathBuf, FormattedError> {
+        let result = match custom_path {
+            Some(path) => path.to_path_buf(),
+            None => default_sio2jail_path()
+                .map_err(|error| FormattedError::from_str(&format!("{}. Sio2jail could not be found", error)))?,
         };
-
-        let result = executable_dir.join("sio2jail");
         if !result.exists() {
-            return Err(FormattedError::from_str(
-                &format!("Sio2jail could not be found at {}", result.display())
-            ));
+            return Err(FormattedError::from_str(&format!(
+                "Sio2jail could not be found at {}. Install it using \"toster install-sio2jail\" or point to it using --sio2jail-path",
+                result.display(),
+            )));
         }
         Ok(result)
     }
 
-    fn run_sio2jail(&self, input_file: &File, output_file: &File, executable_path: &Path) -> Result<Sio2jailOutput, ExecutionError> {
+    fn run_sio2jail(&self, input_file: &File, output_file: &File, mut stderr: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> Result<Sio2jailOutput, ExecutionError> {
+        let memory_limit = limits.memory_limit.expect("Sio2jail should always be run with a memory limit");
         let mut sio2jail_output = create_temp_file().unwrap();
-        let mut stderr = create_temp_file().unwrap();
 
-        let mut child = Command::new(&self.sio2jail_path)
-            .args(["-f", "3", "-o", "oiaug", "--mount-namespace", "off", "--pid-namespace", "off", "--uts-namespace", "off", "--ipc-namespace", "off", "--net-namespace", "off", "--capability-drop", "off", "--user-namespace", "off", "-m", &self.memory_limit.to_string(), "--", executable_path.to_str().unwrap() ])
+        let mut command = Command::new(&self.sio2jail_path);
+        command
+            .args(["-f", "3", "-o", "oiaug", "--mount-namespace", "off", "--pid-namespace", "off", "--uts-namespace", "off", "--ipc-namespace", "off", "--net-namespace", "off", "--capability-drop", "off", "--user-namespace", "off", "-m", &memory_limit.to_string(), "--", self.executable_path.to_str().unwrap() ])
+            .args(arguments)
             .fd_mappings(vec![FdMapping {
                 parent_fd: sio2jail_output.try_clone().unwrap().into(),
                 child_fd: 3
             }]).expect("Failed to redirect file descriptor 3")
             .stdout(make_cloned_stdio(output_file))
-            .stderr(make_cloned_stdio(&stderr))
+            .stderr(make_cloned_stdio(stderr))
             .stdin(make_cloned_stdio(input_file))
-            .spawn().expect("Failed to spawn sio2jail");
-
-        let status = child.wait_timeout(self.timeout).unwrap();
-        let Some(status) = status else {
-            child.kill().unwrap();
+            .current_dir(working_directory);
+        self.environment.apply(&mut command);
+        apply_process_limit(&mut command, limits);
+        apply_sandbox(&mut command, working_directory, limits);
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn().expect("Failed to spawn sio2jail");
+
+        let _handle = ChildHandle::register(&child, limits);
+        let (Some(status), _) = SimpleExecutor::wait_with_usage(child, limits.timeout, limits.kill_grace) else {
             return Err(TimedOut);
         };
 
@@ -82,17 +120,24 @@ impl Sio2jailExecutor {
         })
     }
 
-    fn test(&self) -> Result<(), FormattedError> {
+    fn test(&self, limits: &ExecutionLimits) -> Result<(), FormattedError> {
         let Ok(true_command_location) = which("true") else {
             return Err(FormattedError::from_str("The executable for the \"true\" command could not be found"));
         };
 
+        let true_executor = Sio2jailExecutor {
+            executable_path: true_command_location,
+            sio2jail_path: self.sio2jail_path.clone(),
+            environment: self.environment.clone(),
+        };
         let null_file = File::open("/dev/null").expect("Opening /dev/null should not fail");
-        let output = self.run_sio2jail(&null_file, &null_file, &true_command_location);
+        let working_directory = create_temp_dir().expect("Failed to create temporary directory");
+        let stderr = create_temp_file().unwrap();
+        let output = true_executor.run_sio2jail(&null_file, &null_file, &stderr, &[], working_directory.path(), limits);
         let output = match output {
             Ok(output) => output,
             Err(error) => {
-                return Err(FormattedError::from_str(&format!("Sio2jail error: {}", error.to_string())));
+                return Err(FormattedError::from_str(&format!("Sio2jail error: {}", error)));
             }
         };
         if output.stderr == "Exception occurred: System error occured: perf event open failed: Permission denied: error 13: Permission denied\n" {
@@ -110,23 +155,25 @@ impl Sio2jailExecutor {
         Ok(())
     }
 
-    pub(crate) fn init_and_test(timeout: Duration, executable_path: PathBuf, memory_limit: u64) -> Result<Sio2jailExecutor, FormattedError> {
+    pub(crate) fn init_and_test(executable: Executable, sio2jail_path: Option<&Path>, environment: Environment, limits: &ExecutionLimits) -> Result<Sio2jailExecutor, FormattedError> {
+        if executable.interpreter.is_some() {
+            return Err(FormattedError::from_str("Scripts can't be run with sio2jail"));
+        }
         let executor = Sio2jailExecutor {
-            timeout,
-            memory_limit,
-            executable_path,
-            sio2jail_path: Self::get_sio2jail_path()?,
+            executable_path: executable.path,
+            sio2jail_path: Self::get_sio2jail_path(sio2jail_path)?,
+            environment,
         };
-        executor.test()?;
+        executor.test(limits)?;
         Ok(executor)
     }
 }
 
 impl TestExecutor for Sio2jailExecutor {
-    fn test_to_file(&self, input_file: &File, output_file: &File) -> (ExecutionMetrics, Result<(), ExecutionError>) {
-        let output = match self.run_sio2jail(input_file, output_file, &self.executable_path) {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        let output = match self.run_sio2jail(input_file, output_file, stderr_file, arguments, working_directory, limits) {
             Err(TimedOut) => {
-                return (ExecutionMetrics { time: Some(self.timeout), memory_kibibytes: None }, Err(TimedOut));
+                return (ExecutionMetrics { time: Some(limits.timeout), cpu_time: None, memory_kibibytes: None }, Err(TimedOut));
             }
             Err(error) => {
                 return (ExecutionMetrics::NONE, Err(error));
@@ -136,7 +183,7 @@ impl TestExecutor for Sio2jailExecutor {
 
         if !output.stderr.is_empty() {
             return if output.stderr == "terminate called after throwing an instance of 'std::bad_alloc'\n  what():  std::bad_alloc\n" {
-                (ExecutionMetrics { time: None, memory_kibibytes: Some(self.memory_limit) }, Err(MemoryLimitExceeded))
+                (ExecutionMetrics { time: None, cpu_time: None, memory_kibibytes: limits.memory_limit }, Err(MemoryLimitExceeded))
             } else {
                 (ExecutionMetrics::NONE, Err(Sio2jailError(output.stderr)))
             }
@@ -153,14 +200,14 @@ impl TestExecutor for Sio2jailExecutor {
 
         let metrics = ExecutionMetrics {
             time: Some(time),
+            cpu_time: None,
             memory_kibibytes: Some(memory_kibibytes)
         };
 
         match output.status.code() {
             None => {
-                #[cfg(unix)]
-                if cfg!(unix) && output.status.signal().expect("Sio2jail returned an invalid status code") == 2 {
-                    halt();
+                if output.status.signal().expect("Sio2jail returned an invalid status code") == 2 {
+                    return (metrics, Err(Interrupted));
                 }
 
                 return (metrics, Err(RuntimeError(format!("- the process was terminated with the following error:\n{}", output.status))))
@@ -171,7 +218,7 @@ impl TestExecutor for Sio2jailExecutor {
             }
         }
 
-        (ExecutionMetrics { time: Some(time), memory_kibibytes: Some(memory_kibibytes) }, match sio2jail_status {
+        (metrics, match sio2jail_status {
             "OK" => Ok(()),
             "RE" | "RV" => Err(RuntimeError(error_message.map(|message| format!("- {}", message)).unwrap_or(String::new()))),
             "TLE" => Err(TimedOut),
@@ -0,0 +1,62 @@
+use std::ffi::c_void;
+use std::mem;
+use std::os::windows::io::AsRawHandle;
+use std::process::Child;
+use std::time::Duration;
+
+/// A FILETIME, counting time in 100ns intervals
+#[repr(C)]
+#[derive(Default)]
+struct FileTime {
+    low_date_time: u32,
+    high_date_time: u32,
+}
+
+impl FileTime {
+    fn to_duration(&self) -> Duration {
+        let intervals = ((self.high_date_time as u64) << 32) | self.low_date_time as u64;
+        Duration::from_nanos(intervals * 100)
+    }
+}
+
+/// A PROCESS_MEMORY_COUNTERS, with the sizes in bytes
+#[repr(C)]
+#[derive(Default)]
+struct ProcessMemoryCounters {
+    cb: u32,
+    page_fault_count: u32,
+    peak_working_set_size: usize,
+    working_set_size: usize,
+    quota_peak_paged_pool_usage: usize,
+    quota_paged_pool_usage: usize,
+    quota_peak_non_paged_pool_usage: usize,
+    quota_non_paged_pool_usage: usize,
+    pagefile_usage: usize,
+    peak_pagefile_usage: usize,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetProcessTimes(process: *mut c_void, creation_time: *mut FileTime, exit_time: *mut FileTime, kernel_time: *mut FileTime, user_time: *mut FileTime) -> i32;
+    fn K32GetProcessMemoryInfo(process: *mut c_void, counters: *mut ProcessMemoryCounters, size: u32) -> i32;
+}
+
+/// Returns the CPU time and the peak memory use in KiB of a child that has exited
+pub(crate) fn process_usage(child: &Child) -> (Option<Duration>, Option<u64>) {
+    let handle = child.as_raw_handle();
+
+    let (mut creation_time, mut exit_time, mut kernel_time, mut user_time) = Default::default();
+    // SAFETY: the handle stays open while the child isn't dropped, and the FILETIMEs are valid for writes
+    let cpu_time = (unsafe { GetProcessTimes(handle, &mut creation_time, &mut exit_time, &mut kernel_time, &mut user_time) } != 0)
+        .then(|| kernel_time.to_duration() + user_time.to_duration());
+
+    let mut counters = ProcessMemoryCounters {
+        cb: mem::size_of::<ProcessMemoryCounters>() as u32,
+        ..ProcessMemoryCounters::default()
+    };
+    // SAFETY: the handle stays open while the child isn't dropped, and cb is set to the size of the counters
+    let memory_kibibytes = (unsafe { K32GetProcessMemoryInfo(handle, &mut counters, counters.cb) } != 0)
+        .then(|| counters.peak_working_set_size as u64 / 1024);
+
+    (cpu_time, memory_kibibytes)
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+use colored::Colorize;
+use tempfile::TempDir;
+use crate::executor::{stderr_end, AnyTestExecutor, ExecutionLimits, TestExecutor};
+use crate::executor::simple::SimpleExecutor;
+use crate::temp_files::create_temp_dir;
+use crate::test_errors::{ExecutionError, ExecutionMetrics};
+use crate::test_errors::ExecutionError::{ExecutorError, MemoryLimitExceeded, RuntimeError, TimedOut};
+use crate::verbose::log;
+use crate::wsl::{run_through, to_wsl_path, wrap_command};
+
+/// The wrapper measuring the usage of the program inside of WSL, compiled there when the executor is created
+const USAGE_WRAPPER_SOURCE: &str = include_str!("wsl_usage.c");
+
+/// Starts the line the wrapper appends to the program's stderr, see [`USAGE_WRAPPER_SOURCE`]
+const USAGE_MARKER: &str = "toster-wsl-usage";
+
+/// How much longer than the timeout wsl is waited for, since the program is killed by the wrapper
+/// and starting WSL adds to the local runtime
+const STARTUP_MARGIN: Duration = Duration::from_secs(5);
+
+/// The usage of the program reported by the wrapper
+#[derive(Debug, PartialEq)]
+struct WslUsage {
+    time: Duration,
+    cpu_time: Duration,
+    memory_kibibytes: u64,
+    exit_code: i32,
+    /// The signal that terminated the program, or 0 if it exited
+    signal: i32,
+    timed_out: bool,
+}
+
+/// Runs the programs inside of WSL (bridged by the compiler) through a wrapper,
+/// which measures their runtime and memory use inside of WSL, without the overhead of starting it
+pub(crate) struct WslExecutor {
+    simple: SimpleExecutor,
+    /// The path of the wrapper inside of WSL
+    wrapper_path: String,
+    /// Keeps the compiled wrapper until the executor is dropped
+    _wrapper_directory: TempDir,
+}
+
+impl WslExecutor {
+    /// Compiles the wrapper inside of WSL. If it can't be compiled, the programs are run and measured like
+    /// with the simple executor, which includes the time it takes WSL to start
+    pub(crate) fn init(simple: SimpleExecutor) -> AnyTestExecutor {
+        match Self::compile_wrapper() {
+            Ok((wrapper_directory, wrapper_path)) => AnyTestExecutor::Wsl(WslExecutor {
+                simple,
+                wrapper_path,
+                _wrapper_directory: wrapper_directory,
+            }),
+            Err(error) => {
+                println!("{}", format!("{}, the runtime will include starting WSL and memory use won't be measured", error).yellow());
+                AnyTestExecutor::Simple(simple)
+            }
+        }
+    }
+
+    fn compile_wrapper() -> Result<(TempDir, String), String> {
+        let directory = create_temp_dir().map_err(|error| format!("Failed to create a temporary directory: {}", error))?;
+        let source_path = directory.path().join("wsl_usage.c");
+        fs::write(&source_path, USAGE_WRAPPER_SOURCE).map_err(|error| format!("Failed to save the source of the WSL usage wrapper: {}", error))?;
+        let wsl_path = |path: &Path| to_wsl_path(path.to_str().expect("The temporary directory path is invalid"));
+        let wrapper_path = wsl_path(&directory.path().join("wsl_usage"));
+
+        let compile_command = wrap_command(vec!["cc".to_string(), "-O2".to_string(), "-o".to_string(), wrapper_path.clone(), wsl_path(&source_path)]);
+        log(1, || format!("Compiling the WSL usage wrapper with {:?}", compile_command));
+        let output = Command::new(&compile_command[0])
+            .args(&compile_command[1..])
+            .output()
+            .map_err(|error| format!("Failed to run WSL to compile the usage wrapper: {}", error))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to compile the usage wrapper inside of WSL, make sure cc is installed there:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+        Ok((directory, wrapper_path))
+    }
+
+    /// Removes the line with the usage from the end of the program's stderr and returns it
+    fn take_usage(stderr_file: &File) -> Option<WslUsage> {
+        let (start, end) = stderr_end(stderr_file)?;
+        let line_start = end.rfind(&format!("\n{} ", USAGE_MARKER))?;
+        let parts = end[line_start..].split_whitespace().skip(1).collect::<Vec<&str>>();
+        let [time, cpu_time, memory_kibibytes, exit_code, signal, timed_out] = parts[..] else {
+            return None;
+        };
+        let usage = WslUsage {
+            time: Duration::from_nanos(time.parse().ok()?),
+            cpu_time: Duration::from_nanos(cpu_time.parse().ok()?),
+            memory_kibibytes: memory_kibibytes.parse().ok()?,
+            exit_code: exit_code.parse().ok()?,
+            signal: signal.parse().ok()?,
+            timed_out: timed_out == "1",
+        };
+        stderr_file.set_len(start + line_start as u64).ok()?;
+        Some(usage)
+    }
+
+    /// Turns the usage reported by the wrapper into the result of the program
+    fn map_usage(usage: &WslUsage, limits: &ExecutionLimits) -> Result<(), ExecutionError> {
+        if usage.timed_out {
+            return Err(TimedOut);
+        }
+        if limits.memory_limit.is_some_and(|memory_limit| usage.memory_kibibytes > memory_limit) {
+            return Err(MemoryLimitExceeded);
+        }
+        match (usage.exit_code, usage.signal) {
+            (0, 0) => Ok(()),
+            (_, 0) => Err(RuntimeError(format!("- the program returned a non-zero return code: {}", usage.exit_code))),
+            (_, signal) => Err(RuntimeError(format!("- the process was terminated by signal {}", signal))),
+        }
+    }
+
+    /// The error of WSL failing to run the wrapper, with the error printed last
+    fn wsl_error(status: &ExitStatus, stderr_file: &File) -> ExecutionError {
+        let message = stderr_end(stderr_file)
+            .and_then(|(_, end)| end.lines().rev().find(|line| !line.trim().is_empty()).map(|line| line.trim().to_string()));
+        match message {
+            Some(message) => ExecutorError(format!("WSL failed with {}: {}", status, message)),
+            None => ExecutorError(format!("WSL failed with {}", status)),
+        }
+    }
+}
+
+impl TestExecutor for WslExecutor {
+    fn test_to_file(&self, input_file: &File, output_file: &File, stderr_file: &File, arguments: &[String], working_directory: &Path, limits: &ExecutionLimits) -> (ExecutionMetrics, Result<(), ExecutionError>) {
+        let wrapper = vec![self.wrapper_path.clone(), limits.timeout.as_millis().to_string()];
+        let simple = SimpleExecutor {
+            executable: run_through(self.simple.executable.clone(), wrapper),
+            environment: self.simple.environment.clone(),
+        };
+        let mut command = simple.command(input_file, output_file, stderr_file, arguments, working_directory, limits);
+        log(2, || format!("Running {:?}", command));
+        let child = command.spawn().expect("Failed to spawn wsl");
+
+        let local_limits = ExecutionLimits { timeout: limits.timeout + STARTUP_MARGIN, ..*limits };
+        let (local_metrics, status) = simple.wait_for_status(child, &local_limits);
+        let status = match status {
+            Ok(status) => status,
+            Err(error) => return (local_metrics, Err(error)),
+        };
+        let Some(usage) = Self::take_usage(stderr_file) else {
+            return (local_metrics, Err(Self::wsl_error(&status, stderr_file)));
+        };
+        let metrics = ExecutionMetrics {
+            time: Some(usage.time),
+            cpu_time: Some(usage.cpu_time),
+            memory_kibibytes: Some(usage.memory_kibibytes),
+        };
+        (metrics, SimpleExecutor::detect_bad_alloc(Self::map_usage(&usage, limits), stderr_file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, Write};
+    use std::time::Duration;
+    use super::{WslExecutor, WslUsage, USAGE_MARKER, USAGE_WRAPPER_SOURCE};
+    use crate::executor::ExecutionLimits;
+    use crate::test_errors::ExecutionError::{RuntimeError, TimedOut};
+
+    #[test]
+    fn takes_the_usage_from_stderr() {
+        assert!(USAGE_WRAPPER_SOURCE.contains(&format!(r#""\n{} "#, USAGE_MARKER)));
+
+        let mut stderr = tempfile::tempfile().unwrap();
+        write!(stderr, "error\n\n{} 1500000 1000000 2048 0 11 0\n", USAGE_MARKER).unwrap();
+        assert_eq!(WslExecutor::take_usage(&stderr), Some(WslUsage {
+            time: Duration::from_micros(1500),
+            cpu_time: Duration::from_millis(1),
+            memory_kibibytes: 2048,
+            exit_code: 0,
+            signal: 11,
+            timed_out: false,
+        }));
+        let mut contents = String::new();
+        stderr.rewind().unwrap();
+        stderr.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "error\n");
+
+        let mut stderr = tempfile::tempfile().unwrap();
+        write!(stderr, "\n{} 1 2 3\n", USAGE_MARKER).unwrap();
+        assert_eq!(WslExecutor::take_usage(&stderr), None);
+        assert_eq!(WslExecutor::take_usage(&tempfile::tempfile().unwrap()), None);
+    }
+
+    #[test]
+    fn maps_the_usage() {
+        let limits = ExecutionLimits {
+            timeout: Duration::from_secs(1),
+            memory_limit: None,
+            #[cfg(unix)]
+            max_processes: None,
+            #[cfg(unix)]
+            cpu_timeout: false,
+            #[cfg(unix)]
+            kill_grace: Duration::ZERO,
+            #[cfg(target_os = "linux")]
+            sandbox: false,
+        };
+        let usage = |exit_code, signal, timed_out| WslUsage {
+            time: Duration::from_millis(100),
+            cpu_time: Duration::from_millis(100),
+            memory_kibibytes: 1024,
+            exit_code,
+            signal,
+            timed_out,
+        };
+        let map = |exit_code, signal, timed_out| WslExecutor::map_usage(&usage(exit_code, signal, timed_out), &limits);
+        assert!(map(0, 0, false).is_ok());
+        assert!(matches!(map(0, 9, true), Err(TimedOut)));
+        assert!(matches!(map(3, 0, false), Err(RuntimeError(message)) if message.ends_with("code: 3")));
+        assert!(matches!(map(0, 11, false), Err(RuntimeError(message)) if message.ends_with("signal 11")));
+    }
+}
@@ -0,0 +1,83 @@
+// Runs a program inside of WSL and measures its runtime and memory use there,
+// since from Windows only the usage of wsl.exe itself can be measured.
+//
+// Usage: wsl_usage <timeout in milliseconds> <program> [arguments...]
+//
+// The program is killed after the timeout. When it exits, a line with the usage is appended to stderr:
+// toster-wsl-usage <wall time in ns> <CPU time in ns> <peak memory in KiB> <exit code> <signal> <timed out>
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/time.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+static pid_t child;
+static volatile sig_atomic_t timed_out = 0;
+
+static void on_timeout(int signal) {
+    (void) signal;
+    timed_out = 1;
+    kill(child, SIGKILL);
+}
+
+static long long nanoseconds(struct timeval time) {
+    return time.tv_sec * 1000000000LL + time.tv_usec * 1000LL;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 3) {
+        fprintf(stderr, "Usage: %s <timeout in milliseconds> <program> [arguments...]\n", argv[0]);
+        return 127;
+    }
+    long long timeout = atoll(argv[1]);
+    if (timeout < 1) {
+        timeout = 1;
+    }
+
+    struct timespec start, end;
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    child = fork();
+    if (child == -1) {
+        perror("fork");
+        return 127;
+    }
+    if (child == 0) {
+        execvp(argv[2], argv + 2);
+        perror(argv[2]);
+        _exit(127);
+    }
+
+    signal(SIGALRM, on_timeout);
+    struct itimerval timer = {{0, 0}, {timeout / 1000, timeout % 1000 * 1000}};
+    setitimer(ITIMER_REAL, &timer, NULL);
+    // The child is only reaped after the timer is stopped, so that its pid can't be reused before
+    siginfo_t info;
+    while (waitid(P_PID, child, &info, WEXITED | WNOWAIT) == -1) {
+        if (errno != EINTR) {
+            perror("waitid");
+            return 127;
+        }
+    }
+    clock_gettime(CLOCK_MONOTONIC, &end);
+    struct itimerval stopped = {{0, 0}, {0, 0}};
+    setitimer(ITIMER_REAL, &stopped, NULL);
+
+    int status;
+    struct rusage usage;
+    while (wait4(child, &status, 0, &usage) == -1) {
+        if (errno != EINTR) {
+            perror("wait4");
+            return 127;
+        }
+    }
+    long long wall_time = (end.tv_sec - start.tv_sec) * 1000000000LL + (end.tv_nsec - start.tv_nsec);
+    long long cpu_time = nanoseconds(usage.ru_utime) + nanoseconds(usage.ru_stime);
+    fprintf(stderr, "\ntoster-wsl-usage %lld %lld %ld %d %d %d\n", wall_time, cpu_time, usage.ru_maxrss,
+            WIFEXITED(status) ? WEXITSTATUS(status) : 0, WIFSIGNALED(status) ? WTERMSIG(status) : 0, (int) timed_out);
+    return 0;
+}
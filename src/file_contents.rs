@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek};
+use std::ops::Deref;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+#[cfg(unix)]
+use std::{ptr, slice, str};
+
+/// The text contents of a file.
+///
+/// On Unix, regular files are mapped into memory instead of being read, so comparing big outputs
+/// doesn't allocate a copy of them for every test. The file mustn't be truncated while it's mapped
+pub(crate) enum FileContents {
+    #[cfg(unix)]
+    Mapped { pointer: *mut libc::c_void, length: usize },
+    Read(String),
+}
+
+impl FileContents {
+    /// Returns the whole contents of the file, regardless of its current position.
+    /// Fails if they aren't valid UTF-8
+    pub(crate) fn read(mut file: File) -> io::Result<FileContents> {
+        #[cfg(unix)]
+        if let Some(contents) = Self::map(&file)? {
+            return Ok(contents);
+        }
+        file.rewind()?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        Ok(FileContents::Read(text))
+    }
+
+    /// Maps the file into memory, returning `None` if it can't be mapped
+    #[cfg(unix)]
+    fn map(file: &File) -> io::Result<Option<FileContents>> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        // Empty mappings aren't allowed
+        if metadata.len() == 0 {
+            return Ok(Some(FileContents::Read(String::new())));
+        }
+        let Ok(length) = usize::try_from(metadata.len()) else {
+            return Ok(None);
+        };
+
+        // SAFETY: the mapping is read-only and private, it's only accessed through `self` and unmapped when it's dropped
+        let pointer = unsafe { libc::mmap(ptr::null_mut(), length, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if pointer == libc::MAP_FAILED {
+            return Ok(None);
+        }
+        let contents = FileContents::Mapped { pointer, length };
+        // SAFETY: the mapping is `length` bytes long
+        let bytes = unsafe { slice::from_raw_parts(pointer as *const u8, length) };
+        if str::from_utf8(bytes).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"));
+        }
+        Ok(Some(contents))
+    }
+}
+
+impl Deref for FileContents {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            // SAFETY: the mapping is `length` bytes long and was checked to be valid UTF-8 when it was created
+            #[cfg(unix)]
+            FileContents::Mapped { pointer, length } => unsafe {
+                str::from_utf8_unchecked(slice::from_raw_parts(*pointer as *const u8, *length))
+            },
+            FileContents::Read(text) => text,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for FileContents {
+    fn drop(&mut self) {
+        if let FileContents::Mapped { pointer, length } = self {
+            // SAFETY: the mapping was created in `map` and isn't used after this
+            unsafe { libc::munmap(*pointer, *length); }
+        }
+    }
+}
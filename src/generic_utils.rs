@@ -1,8 +1,30 @@
 use std::thread;
 use std::time::Duration;
 
+/// The directory (relative to the current working directory) where toster keeps its data between runs
+pub(crate) const DATA_DIRECTORY: &str = ".toster";
+
 #[deprecated(note = "This is not ideal, there must be a better way to implement it")]
 pub(crate) fn halt() -> ! {
     thread::sleep(Duration::from_secs(u64::MAX));
     unreachable!()
-}
\ No newline at end of file
+}
+
+/// Removes the ANSI color codes from the text
+pub(crate) fn strip_colors(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            // Color codes have the form "\x1b[...m"
+            for char in chars.by_ref() {
+                if char == 'm' {
+                    break;
+                }
+            }
+        } else {
+            result.push(char);
+        }
+    }
+    result
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+use crate::compiler::CompilerError;
+use crate::generic_utils::strip_colors;
+use crate::test_summary::TestSummary;
+
+/// GitHub only shows the first 10 error annotations of every step
+const MAX_ANNOTATIONS: usize = 10;
+
+/// Escapes the message of a workflow command
+/// https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escapes a property (like the file name or the title) of a workflow command
+fn escape_property(property: &str) -> String {
+    escape_data(property).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn print_error(file: &str, line: Option<(&str, &str)>, title: &str, message: &str) {
+    let position = line.map_or(String::new(), |(line, column)| format!(",line={},col={}", line, column));
+    println!(
+        "::error file={}{},title={}::{}",
+        escape_property(file),
+        position,
+        escape_property(title),
+        escape_data(message.trim_end()),
+    );
+}
+
+/// Summarizes the errors that didn't fit in the annotations in the last one
+fn print_omitted(file: &str, count: usize, what: &str) {
+    print_error(file, None, &format!("{} more {}", count, what), "See the output of toster for the details");
+}
+
+/// Finds the errors in GCC-style compiler output, which have the form "file:line:column: error: message".
+/// The position is parsed from the right, since the file name can contain colons (e.g. "C:\a.cpp")
+fn parse_compiler_errors(output: &str) -> Vec<(&str, &str, &str, &str)> {
+    output.lines().filter_map(|line| {
+        let (position, message) = line.split_once(": error:").or_else(|| line.split_once(": fatal error:"))?;
+        let mut parts = position.rsplitn(3, ':');
+        let (column, line, file) = (parts.next()?, parts.next()?, parts.next()?);
+        if file.is_empty() || line.parse::<u32>().is_err() || column.parse::<u32>().is_err() {
+            return None;
+        }
+        Some((file, line, column, message.trim()))
+    }).collect()
+}
+
+/// Prints an annotation for every error of a failed compilation of `source_path`
+pub(crate) fn annotate_compiler_error(source_path: &Path, error: &CompilerError) {
+    let source_path = source_path.to_string_lossy();
+    let output = match error {
+        CompilerError::CompilationError(output) => strip_colors(output),
+        CompilerError::InvalidExecutable(error) => {
+            print_error(&source_path, None, "Invalid executable", &error.to_string());
+            return;
+        }
+    };
+
+    let errors = parse_compiler_errors(&output);
+    if errors.is_empty() {
+        print_error(&source_path, None, "Compilation failed", &output);
+    }
+    for (file, line, column, message) in errors.iter().take(MAX_ANNOTATIONS - 1) {
+        print_error(file, Some((line, column)), "Compilation error", message);
+    }
+    if errors.len() >= MAX_ANNOTATIONS {
+        print_omitted(&source_path, errors.len() - (MAX_ANNOTATIONS - 1), "compilation errors");
+    }
+}
+
+/// Prints an annotation on `source_path` for every failed test
+pub(crate) fn annotate_failed_tests(source_path: &Path, test_summary: &mut TestSummary) {
+    let source_path = source_path.to_string_lossy();
+    let errors = test_summary.get_errors();
+    for failed_test in errors.iter().take(MAX_ANNOTATIONS - 1) {
+        print_error(
+            &source_path,
+            None,
+            &format!("Test {}: {}", failed_test.test_name, failed_test.verdict),
+            &strip_colors(&failed_test.error().description()),
+        );
+    }
+    if errors.len() >= MAX_ANNOTATIONS {
+        print_omitted(&source_path, errors.len() - (MAX_ANNOTATIONS - 1), "failed tests");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compiler_errors() {
+        let output = "a.cpp: In function 'int main()':\n\
+            a.cpp:3:5: error: 'x' was not declared in this scope\n\
+            a.cpp:4:1: warning: unused variable 'y'\n\
+            b.h:1:10: fatal error: c.h: No such file or directory\n";
+        assert_eq!(parse_compiler_errors(output), [
+            ("a.cpp", "3", "5", "'x' was not declared in this scope"),
+            ("b.h", "1", "10", "c.h: No such file or directory"),
+        ]);
+    }
+
+    #[test]
+    fn parses_paths_with_colons() {
+        let output = "C:\\Users\\a.cpp:12:7: error: expected ';' before '}' token";
+        assert_eq!(parse_compiler_errors(output), [("C:\\Users\\a.cpp", "12", "7", "expected ';' before '}' token")]);
+    }
+
+    #[test]
+    fn ignores_lines_without_a_position() {
+        assert!(parse_compiler_errors("collect2: error: ld returned 1 exit status").is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use comfy_table::{Attribute, Cell, Color};
+use comfy_table::ContentArrangement::Dynamic;
+use serde::Serialize;
+use crate::args::GradeArgs;
+use crate::compiler::Languages;
+use crate::config::ConfigFile;
+use crate::contest::run_toster;
+use crate::history::Run;
+use crate::formatted_error::FormattedError;
+use crate::output::create_table;
+
+/// The score of a single submission, `correct` and `total` are `None` if testing didn't finish
+#[derive(Serialize)]
+struct Grade {
+    submission: String,
+    correct: Option<usize>,
+    total: Option<usize>,
+    /// The percentage of correct tests
+    score: f64,
+    #[serde(skip)]
+    run: Option<Run>,
+}
+
+impl Grade {
+    fn score_text(&self) -> String {
+        format!("{:.1}%", self.score)
+    }
+}
+
+fn print_table(grades: &[Grade]) {
+    let mut table = create_table();
+    table.set_content_arrangement(Dynamic).set_header(vec![
+        Cell::new("Submission").add_attribute(Attribute::Bold),
+        Cell::new("Correct").add_attribute(Attribute::Bold),
+        Cell::new("Score").add_attribute(Attribute::Bold),
+    ]);
+    for grade in grades {
+        let (correct, color) = match (grade.correct, grade.total) {
+            (Some(correct), Some(total)) => (format!("{}/{}", correct, total), if correct == total { Color::Green } else { Color::Red }),
+            _ => ("Testing failed".to_string(), Color::Red),
+        };
+        table.add_row(vec![
+            Cell::new(&grade.submission),
+            Cell::new(correct).fg(color),
+            Cell::new(grade.score_text()).fg(color),
+        ]);
+    }
+    println!("{}", table.to_string().replace('\r', ""));
+}
+
+/// Quotes a CSV field, escaping the quotes in it by doubling them
+fn csv_field(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn to_csv(grades: &[Grade]) -> String {
+    let mut csv = "submission,correct,total,score\n".to_string();
+    for grade in grades {
+        let count = |count: Option<usize>| count.map_or(String::new(), |count| count.to_string());
+        csv.push_str(&format!(
+            "{},{},{},{:.1}\n",
+            csv_field(&grade.submission),
+            count(grade.correct),
+            count(grade.total),
+            grade.score,
+        ));
+    }
+    csv
+}
+
+/// Creates a CSV matrix of the verdicts and runtimes with a row for every submission and a column for every test
+fn to_matrix_csv(grades: &[Grade]) -> String {
+    let mut test_names: Vec<&str> = grades.iter()
+        .flat_map(|grade| grade.run.iter().flat_map(|run| run.verdicts().map(|(test_name, _, _)| test_name)))
+        .collect();
+    test_names.sort_by(|a, b| human_sort::compare(a, b));
+    test_names.dedup();
+
+    let mut csv = String::from("submission");
+    for test_name in &test_names {
+        csv.push_str(&format!(",{}", csv_field(test_name)));
+    }
+    csv.push('\n');
+    for grade in grades {
+        csv.push_str(&csv_field(&grade.submission));
+        let verdicts: HashMap<&str, (&str, Option<f64>)> = grade.run.iter()
+            .flat_map(|run| run.verdicts().map(|(test_name, verdict, time)| (test_name, (verdict, time))))
+            .collect();
+        for test_name in &test_names {
+            let cell = match verdicts.get(test_name) {
+                Some((verdict, Some(time))) => format!("{} {:.3}s", verdict, time),
+                Some((verdict, None)) => verdict.to_string(),
+                None => String::new(),
+            };
+            csv.push_str(&format!(",{}", csv_field(&cell)));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+fn export(path: &Path, contents: &str) -> Result<(), FormattedError> {
+    fs::write(path, contents)
+        .map_err(|error| FormattedError::from_str(&format!("Failed to save the scores to {}: {}", path.display(), error)))?;
+    println!("{}", format!("Saved the scores to {}", path.display()).green());
+    Ok(())
+}
+
+pub(crate) fn run_grade(args: GradeArgs) -> Result<(), FormattedError> {
+    let config = ConfigFile::load(None).map_err(|error| FormattedError::from_str(&error))?;
+    let languages = Languages::new(&config, None, None, false).map_err(|error| FormattedError::from_str(&error))?;
+
+    let mut submissions: Vec<PathBuf> = fs::read_dir(&args.submissions)
+        .map_err(|error| FormattedError::from_str(&format!("Failed to read the submissions directory: {}", error)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && languages.is_known(path))
+        .collect();
+    if submissions.is_empty() {
+        return Err(FormattedError::from_str("The submissions directory doesn't contain any source files"));
+    }
+    submissions.sort_by(|a, b| human_sort::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    let mut grades = vec![];
+    for submission in submissions {
+        let name = submission.file_name().expect("The submission should be a file").to_string_lossy().to_string();
+        println!("{}", format!("Testing submission {}", name).bold());
+        let run = run_toster(Path::new("."), &submission, &args.toster_args)?;
+        let counts = run.as_ref().map(|run| run.counts());
+        println!();
+        grades.push(Grade {
+            submission: name,
+            correct: counts.map(|(correct, _)| correct),
+            total: counts.map(|(_, total)| total),
+            // A run without any tests doesn't get any points instead of a score of NaN
+            score: counts.filter(|(_, total)| *total > 0).map_or(0.0, |(correct, total)| 100.0 * correct as f64 / total as f64),
+            run,
+        });
+    }
+
+    print_table(&grades);
+    if let Some(path) = &args.csv {
+        export(path, &to_csv(&grades))?;
+    }
+    if let Some(path) = &args.json {
+        export(path, &serde_json::to_string_pretty(&grades).expect("Failed to serialize the scores"))?;
+    }
+    if let Some(path) = &args.matrix {
+        export(path, &to_matrix_csv(&grades))?;
+    }
+    Ok(())
+}
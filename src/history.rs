@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::test_summary::TestSummary;
+
+/// How many of the most recent runs are kept for every source file
+const KEPT_RUNS: usize = 10;
+
+/// The environment variable with the path where a finished run is saved, used by
+/// the subcommands running toster on many programs to get the results of every run
+pub(crate) const RUN_FILE_VARIABLE: &str = "TOSTER_RUN_FILE";
+
+/// The results of past runs, grouped by the tested source file
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct History {
+    sources: HashMap<String, Vec<Run>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Run {
+    /// The time the run finished at, in seconds since the Unix epoch
+    timestamp: u64,
+    tests: BTreeMap<String, RunTest>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RunTest {
+    success: bool,
+    /// The verdict as shown in the results table, empty in runs saved by older versions
+    #[serde(default)]
+    verdict: String,
+    /// The runtime in seconds
+    time: Option<f64>,
+}
+
+impl History {
+    fn path() -> PathBuf {
+        PathBuf::from(DATA_DIRECTORY).join("history.json")
+    }
+
+    fn source_key(source_path: &Path) -> String {
+        fs::canonicalize(source_path).unwrap_or(source_path.to_path_buf()).to_string_lossy().to_string()
+    }
+
+    /// Loads the history, starting a new one if it doesn't exist or is invalid
+    pub(crate) fn load() -> Self {
+        fs::read_to_string(Self::path()).ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self) -> Result<(), String> {
+        let path = Self::path();
+        fs::create_dir_all(path.parent().expect("The history path should have a parent"))
+            .map_err(|error| format!("Failed to create the history directory: {}", error))?;
+        let contents = serde_json::to_string(self).expect("Failed to serialize the history");
+        fs::write(&path, contents)
+            .map_err(|error| format!("Failed to save the history to {}: {}", path.display(), error))
+    }
+
+    pub(crate) fn last_run(&self, source_path: &Path) -> Option<&Run> {
+        self.sources.get(&Self::source_key(source_path)).and_then(|runs| runs.last())
+    }
+
+    pub(crate) fn add_run(&mut self, source_path: &Path, run: Run) {
+        let runs = self.sources.entry(Self::source_key(source_path)).or_default();
+        runs.push(run);
+        if runs.len() > KEPT_RUNS {
+            runs.drain(..runs.len() - KEPT_RUNS);
+        }
+    }
+}
+
+impl Run {
+    pub(crate) fn from_summary(test_summary: &TestSummary) -> Self {
+        Run {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()),
+            tests: test_summary.test_results.iter()
+                .map(|result| (result.test_name.clone(), RunTest {
+                    success: result.verdict.is_success(),
+                    verdict: result.verdict.to_string(),
+                    time: result.time.map(|time| time.as_secs_f64()),
+                }))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn save_to(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string(self).expect("Failed to serialize the run");
+        fs::write(path, contents).map_err(|error| format!("Failed to save the results to {}: {}", path.display(), error))
+    }
+
+    /// Loads a run saved with `save_to`, returning `None` if nothing was saved
+    pub(crate) fn load_from(path: &Path) -> Option<Self> {
+        fs::read_to_string(path).ok().and_then(|contents| serde_json::from_str(&contents).ok())
+    }
+
+    /// The number of successful tests and the number of all tests
+    pub(crate) fn counts(&self) -> (usize, usize) {
+        (self.tests.values().filter(|test| test.success).count(), self.tests.len())
+    }
+
+    /// The verdict and the runtime in seconds of every test, keyed by the test name
+    pub(crate) fn verdicts(&self) -> impl Iterator<Item = (&str, &str, Option<f64>)> {
+        self.tests.iter().map(|(test_name, test)| (test_name.as_str(), test.verdict.as_str(), test.time))
+    }
+
+    /// The name of the test with the longest runtime
+    pub(crate) fn slowest_test(&self) -> Option<&str> {
+        self.tests.iter()
+            .filter_map(|(test_name, test)| test.time.map(|time| (test_name, time)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(test_name, _)| test_name.as_str())
+    }
+
+    fn slowest_time(&self) -> Option<f64> {
+        self.tests.values().filter_map(|test| test.time).reduce(f64::max)
+    }
+
+    /// Formats a one-line comparison of this (older) run with `current`
+    pub(crate) fn compare(&self, current: &Run) -> String {
+        let common = || current.tests.iter()
+            .filter_map(|(test_name, new)| self.tests.get(test_name).map(|old| (old, new)));
+        let newly_failing = common().filter(|(old, new)| old.success && !new.success).count();
+        let fixed = common().filter(|(old, new)| !old.success && new.success).count();
+
+        let mut parts = vec![];
+        if newly_failing > 0 {
+            parts.push(format!("{} newly failing", newly_failing).red().to_string());
+        }
+        if fixed > 0 {
+            parts.push(format!("{} fixed", fixed).green().to_string());
+        }
+        if parts.is_empty() {
+            parts.push("no verdicts changed".to_string());
+        }
+        if let (Some(old_slowest), Some(new_slowest)) = (self.slowest_time(), current.slowest_time()) {
+            parts.push(format!("slowest test {:+.3}s", new_slowest - old_slowest));
+        }
+
+        format!("Compared to the previous run: {}", parts.join(", "))
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use crate::compiler::Executable;
+
+/// The directory given with --keep-temp, which the files normally deleted after testing are copied to
+pub(crate) struct KeptFiles {
+    directory: PathBuf,
+}
+
+impl KeptFiles {
+    pub(crate) fn new(directory: PathBuf) -> Result<Self, String> {
+        fs::create_dir_all(directory.join("tests"))
+            .map_err(|error| format!("Failed to create the --keep-temp directory: {}", error))?;
+        Ok(KeptFiles { directory })
+    }
+
+    pub(crate) fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Saves `contents` to `name` in the directory
+    pub(crate) fn save_text(&self, name: &str, contents: &str) -> Result<(), String> {
+        let path = self.directory.join(name);
+        fs::write(&path, contents)
+            .map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+    }
+
+    /// Copies the executable (or script) that is being tested to `name` in the directory
+    pub(crate) fn save_executable(&self, executable: &Executable, name: &str) -> Result<(), String> {
+        let path = self.directory.join(name);
+        fs::copy(&executable.path, &path)
+            .map_err(|error| format!("Failed to save the {} in {}: {}", name, path.display(), error))?;
+        Ok(())
+    }
+
+    /// Copies `file` to `tests/<test_name>/<file_name>` in the directory.
+    ///
+    /// `file` is rewound before and after copying
+    pub(crate) fn save_test_file(&self, test_name: &str, file_name: &str, mut file: &File) -> Result<(), String> {
+        let directory = self.directory.join("tests").join(test_name);
+        let path = directory.join(file_name);
+        let error = |error: io::Error| format!("Failed to save {}: {}", path.display(), error);
+        fs::create_dir_all(&directory).map_err(error)?;
+        file.rewind().map_err(error)?;
+        let mut kept_file = File::create(&path).map_err(error)?;
+        io::copy(&mut file, &mut kept_file).map_err(error)?;
+        file.rewind().map_err(error)?;
+        Ok(())
+    }
+}
+
+/// The most bytes of a test's stderr saved with --stderr-dir, the rest is cut off
+const MAX_SAVED_STDERR: u64 = 1024 * 1024;
+
+/// The directory given with --stderr-dir, which the stderr of every test is saved to
+pub(crate) struct StderrDirectory {
+    directory: PathBuf,
+}
+
+impl StderrDirectory {
+    pub(crate) fn new(directory: PathBuf) -> Result<Self, String> {
+        fs::create_dir_all(&directory)
+            .map_err(|error| format!("Failed to create the --stderr-dir directory: {}", error))?;
+        Ok(StderrDirectory { directory })
+    }
+
+    /// Copies at most [`MAX_SAVED_STDERR`] bytes of `file` to `<test_name>.err` in the directory.
+    ///
+    /// `file` is rewound before and after copying
+    pub(crate) fn save(&self, test_name: &str, mut file: &File) -> Result<(), String> {
+        let path = self.directory.join(format!("{}.err", test_name));
+        let error = |error: io::Error| format!("Failed to save {}: {}", path.display(), error);
+        file.rewind().map_err(error)?;
+        let mut saved_file = File::create(&path).map_err(error)?;
+        let copied = io::copy(&mut file.take(MAX_SAVED_STDERR), &mut saved_file).map_err(error)?;
+        if copied == MAX_SAVED_STDERR && file.metadata().is_ok_and(|metadata| metadata.len() > MAX_SAVED_STDERR) {
+            writeln!(saved_file, "\n[The rest of the stderr was cut off after {} bytes]", MAX_SAVED_STDERR).map_err(error)?;
+        }
+        file.rewind().map_err(error)?;
+        Ok(())
+    }
+}
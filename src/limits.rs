@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use glob::Pattern;
+use serde::Deserialize;
+use crate::executor::ExecutionLimits;
+use crate::problem_config::ProblemLimits;
+use crate::units::{parse_duration, parse_memory};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsFile {
+    #[serde(default)]
+    tests: Vec<LimitsEntry>,
+}
+
+/// A time limit given either as a number of seconds or as a string with a unit
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum TimeLimit {
+    Seconds(f64),
+    WithUnit(String),
+}
+
+impl TimeLimit {
+    pub(crate) fn to_duration(&self) -> Result<Duration, String> {
+        match self {
+            TimeLimit::Seconds(seconds) => Duration::try_from_secs_f64(*seconds)
+                .map_err(|_| format!("{} is not a valid duration", seconds)),
+            TimeLimit::WithUnit(value) => parse_duration(value),
+        }
+    }
+}
+
+/// A memory limit given either as a number of KiB or as a string with a unit
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum MemoryLimit {
+    Kibibytes(u64),
+    WithUnit(String),
+}
+
+impl MemoryLimit {
+    pub(crate) fn to_kibibytes(&self) -> Result<u64, String> {
+        match self {
+            MemoryLimit::Kibibytes(0) => Err("0 is not a valid amount of memory".to_string()),
+            MemoryLimit::Kibibytes(kibibytes) => Ok(*kibibytes),
+            MemoryLimit::WithUnit(value) => parse_memory(value),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsEntry {
+    pattern: String,
+    time: Option<TimeLimit>,
+    memory: Option<MemoryLimit>,
+}
+
+struct LimitsOverride {
+    pattern: Pattern,
+    timeout: Option<Duration>,
+    memory_limit: Option<u64>,
+}
+
+/// Resolves the limits of each test, taking per-test overrides from the limits file into account
+pub(crate) struct TestLimits {
+    default: ExecutionLimits,
+    overrides: Vec<LimitsOverride>,
+}
+
+impl TestLimits {
+    pub(crate) fn new(default: ExecutionLimits) -> Self {
+        TestLimits {
+            default,
+            overrides: vec![],
+        }
+    }
+
+    /// Reads the per-test overrides from a limits file in the following format:
+    /// ```toml
+    /// [[tests]]
+    /// pattern = "abc1*"
+    /// time = "2.5s"
+    /// memory = "256M"
+    /// ```
+    pub(crate) fn load(path: &Path, default: ExecutionLimits) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read the limits file: {}", error))?;
+        let file: LimitsFile = toml::from_str(&contents)
+            .map_err(|error| format!("The limits file is invalid:\n{}", error))?;
+
+        let overrides = file.tests.into_iter().map(|entry| {
+            Ok(LimitsOverride {
+                pattern: Pattern::new(&entry.pattern)
+                    .map_err(|error| format!("The limits file contains an invalid pattern \"{}\": {}", entry.pattern, error))?,
+                timeout: entry.time.map(|time| {
+                    time.to_duration()
+                        .map_err(|error| format!("The limits file contains an invalid time limit: {}", error))
+                }).transpose()?,
+                memory_limit: entry.memory.map(|memory| {
+                    memory.to_kibibytes()
+                        .map_err(|error| format!("The limits file contains an invalid memory limit: {}", error))
+                }).transpose()?,
+            })
+        }).collect::<Result<Vec<LimitsOverride>, String>>()?;
+
+        Ok(TestLimits { default, overrides })
+    }
+
+    /// Uses the limits from a problem config, in which the tests of group 1 are named like "abc1" or "abc1a" for the problem "abc".
+    ///
+    /// The memory limits are only used if `use_memory_limits` is set, as they can only be enforced with sio2jail
+    pub(crate) fn from_problem(problem: ProblemLimits, default: ExecutionLimits, use_memory_limits: bool) -> Result<Self, String> {
+        let task_id = Pattern::escape(problem.task_id.as_deref().unwrap_or(""));
+        let group_patterns = |group: u32| -> Result<[Pattern; 2], String> {
+            let exact = format!("{}{}", task_id, group);
+            let with_suffix = format!("{}{}[!0-9]*", task_id, group);
+            Ok([
+                Pattern::new(&exact).map_err(|error| format!("Failed to create the pattern of group {}: {}", group, error))?,
+                Pattern::new(&with_suffix).map_err(|error| format!("Failed to create the pattern of group {}: {}", group, error))?,
+            ])
+        };
+
+        let mut overrides = vec![];
+        for (group, timeout) in problem.group_time_limits {
+            for pattern in group_patterns(group)? {
+                overrides.push(LimitsOverride { pattern, timeout: Some(timeout), memory_limit: None });
+            }
+        }
+        if use_memory_limits {
+            for (group, memory_limit) in problem.group_memory_limits {
+                for pattern in group_patterns(group)? {
+                    overrides.push(LimitsOverride { pattern, timeout: None, memory_limit: Some(memory_limit) });
+                }
+            }
+        }
+        let memory_limit = problem.memory_limit.filter(|_| use_memory_limits);
+        if problem.time_limit.is_some() || memory_limit.is_some() {
+            overrides.push(LimitsOverride {
+                pattern: Pattern::new("*").expect("The pattern matching all tests should be valid"),
+                timeout: problem.time_limit,
+                memory_limit,
+            });
+        }
+
+        Ok(TestLimits { default, overrides })
+    }
+
+    pub(crate) fn global(&self) -> &ExecutionLimits {
+        &self.default
+    }
+
+    /// Whether any test has different limits than the default ones
+    pub(crate) fn has_overrides(&self) -> bool {
+        !self.overrides.is_empty()
+    }
+
+    pub(crate) fn has_memory_limits(&self) -> bool {
+        self.overrides.iter().any(|entry| entry.memory_limit.is_some())
+    }
+
+    /// Returns the limits for the given test.
+    ///
+    /// Each limit is taken from the first entry in the limits file whose pattern matches the test name
+    /// and which sets it, falling back to the global limits
+    pub(crate) fn for_test(&self, test_name: &str) -> ExecutionLimits {
+        let matching = self.overrides.iter().filter(|entry| entry.pattern.matches(test_name));
+        let mut timeout = None;
+        let mut memory_limit = None;
+        for entry in matching {
+            timeout = timeout.or(entry.timeout);
+            memory_limit = memory_limit.or(entry.memory_limit);
+        }
+
+        ExecutionLimits {
+            timeout: timeout.unwrap_or(self.default.timeout),
+            memory_limit: memory_limit.or(self.default.memory_limit),
+            #[cfg(unix)]
+            max_processes: self.default.max_processes,
+            #[cfg(unix)]
+            cpu_timeout: self.default.cpu_timeout,
+            #[cfg(unix)]
+            kill_grace: self.default.kill_grace,
+            #[cfg(target_os = "linux")]
+            sandbox: self.default.sandbox,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_limits() -> ExecutionLimits {
+        ExecutionLimits {
+            timeout: Duration::from_secs(5),
+            memory_limit: Some(1024),
+            #[cfg(unix)]
+            max_processes: None,
+            #[cfg(unix)]
+            cpu_timeout: false,
+            #[cfg(unix)]
+            kill_grace: Duration::ZERO,
+            #[cfg(target_os = "linux")]
+            sandbox: false,
+        }
+    }
+
+    #[test]
+    fn applies_problem_limits_to_groups() {
+        let problem = ProblemLimits {
+            task_id: Some("abc".to_string()),
+            time_limit: Some(Duration::from_secs(1)),
+            memory_limit: Some(2048),
+            group_time_limits: vec![(1, Duration::from_secs(2))],
+            group_memory_limits: vec![(12, 4096)],
+        };
+        let limits = TestLimits::from_problem(problem, default_limits(), true).unwrap();
+        for (test_name, timeout, memory_limit) in [
+            ("abc1", 2, 2048),
+            ("abc1b", 2, 2048),
+            ("abc12a", 1, 4096),
+            ("abc2", 1, 2048),
+            ("xyz1", 1, 2048),
+        ] {
+            let test_limits = limits.for_test(test_name);
+            assert_eq!(test_limits.timeout, Duration::from_secs(timeout), "{}", test_name);
+            assert_eq!(test_limits.memory_limit, Some(memory_limit), "{}", test_name);
+        }
+    }
+
+    #[test]
+    fn keeps_the_default_limits_without_problem_limits() {
+        let limits = TestLimits::from_problem(ProblemLimits::default(), default_limits(), true).unwrap();
+        assert!(!limits.has_overrides());
+        assert_eq!(limits.for_test("abc1").timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rejects_a_memory_limit_of_zero() {
+        assert_eq!(MemoryLimit::Kibibytes(512).to_kibibytes(), Ok(512));
+        assert!(MemoryLimit::Kibibytes(0).to_kibibytes().is_err());
+        assert!(MemoryLimit::WithUnit("0M".to_string()).to_kibibytes().is_err());
+    }
+}
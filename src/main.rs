@@ -8,90 +8,137 @@ mod test_summary;
 mod temp_files;
 mod checker;
 mod compiler;
+mod config;
 mod formatted_error;
+mod limits;
+mod units;
+mod baseline;
+mod output;
+mod diff_tool;
+mod child_handles;
+mod cache;
+mod comparator;
+mod history;
+mod program_diff;
+mod precompiled_header;
+mod verbose;
+mod keep_temp;
+mod clipboard;
+mod open_failed;
+mod contest;
+mod grade;
+mod gha;
+mod progress_events;
+mod problem_config;
+mod compression;
+mod file_contents;
+mod manifest;
+mod dry_run;
+mod wsl;
+mod profile;
+mod crash_report;
+mod archive;
+mod clean_outputs;
+mod compile;
+mod test_outcome;
+#[cfg(target_os = "linux")]
+mod core_dumps;
+#[cfg(target_os = "linux")]
+mod rr_record;
+#[cfg(unix)]
+mod early_exit;
+#[cfg(unix)]
+mod disk_space;
+#[cfg(target_os = "linux")]
+mod cpu_affinity;
+mod thread_pools;
 
-use std::{fs, panic};
-use std::fmt::Write as FmtWrite;
+use std::{env, fs, panic, thread};
 use std::fs::File;
 use std::panic::PanicHookInfo;
-use std::path::PathBuf;
+use std::io;
+use std::io::{IsTerminal, Read, Seek};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use std::process::{exit, ExitCode};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::{Acquire, Release};
-use clap::Parser;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Release};
+use clap::{CommandFactory, FromArgMatches};
+use clap::parser::ValueSource;
 use colored::Colorize;
 use human_panic::{handle_dump, print_msg};
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressState, ProgressStyle};
+use indicatif::ProgressBar;
 use rayon::prelude::*;
-use tempfile::tempdir;
+use tempfile::NamedTempFile;
 use args::Args;
-use crate::args::{ActionType, InputConfig, ParsedConfig};
+use crate::baseline::Baseline;
+use crate::args::{ActionType, ExistingOutputs, InputConfig, ModelConfig, OutputChecker, ParsedConfig};
 use crate::args::ExecuteMode::*;
-use crate::checker::Checker;
-use crate::compiler::Compiler;
+use crate::checker::{CheckedTest, Checker, CheckerProtocol};
+use crate::compiler::{CompiledCache, Compiler, Executable, FileKind};
+use crate::prepare_input::{find_unmatched_files, normalize_inputs, prepare_file_inputs, prepare_manifest_inputs, prepare_multitest_inputs, test_arguments, Test, TestingInputs};
 use crate::executor::simple::SimpleExecutor;
-use crate::prepare_input::{prepare_file_inputs, Test, TestingInputs};
+use crate::executor::remote::{clean_up_remote, RemoteExecutor};
+use crate::executor::wsl::WslExecutor;
 use crate::executor::{AnyTestExecutor, test_to_temp, TestExecutor};
 use crate::test_errors::{ExecutionMetrics, TestError};
-use crate::test_errors::TestError::{Cancelled, ProgramError};
+use crate::test_errors::ExecutionError;
+use crate::test_errors::ExecutionError::Interrupted;
+use crate::test_errors::TestError::{Cancelled, CheckerError, InvalidTestFile, ProgramError};
 use crate::test_summary::TestSummary;
-use crate::testing_utils::compare_output;
+use crate::testing_utils::{compare_output, is_hash_file, redact_diffs, LineEndings};
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-use crate::executor::sio2jail::Sio2jailExecutor;
+use crate::executor::sio2jail::{install_sio2jail, Sio2jailExecutor};
+use crate::args::ToolCommand;
+#[cfg(target_os = "linux")]
+use crate::executor::instructions::InstructionsExecutor;
 use crate::formatted_error::FormattedError;
+#[allow(deprecated)]
 use crate::generic_utils::halt;
+use crate::temp_files::{create_named_temp_file, create_temp_dir, create_temp_file, set_temp_directory, write_next_to};
+#[cfg(target_os = "linux")]
+use crate::temp_files::disable_memfd;
+use crate::output::{create_progress_bar, create_progress_style, format_test_result, print_output, StreamErrors};
+use crate::child_handles::kill_running_children;
+use crate::dry_run::print_dry_run;
+use crate::compression::{decompress_to_named_file, open_decompressed};
+use crate::cache::{ExpectedOutputs, ResultCache};
+use crate::history::{History, Run, RUN_FILE_VARIABLE};
+use crate::program_diff::run_program_diff;
+use crate::verbose::{log, set_verbosity};
+use crate::keep_temp::{KeptFiles, StderrDirectory};
+use crate::clipboard::copy_to_clipboard;
+use crate::open_failed::open_failed_tests;
+use crate::contest::run_contest;
+use crate::grade::run_grade;
+use crate::profile::run_profile;
+use crate::clean_outputs::run_clean_outputs;
+use crate::compile::run_compile;
+use crate::archive::{create_archive, run_show};
+use crate::crash_report::{command_line, extend_report, set_compiler, set_configuration, set_stage};
+#[cfg(target_os = "linux")]
+use crate::core_dumps::{collect_core_dump, enable_core_dumps};
+#[cfg(target_os = "linux")]
+use crate::rr_record::record_failed_tests;
+#[cfg(unix)]
+use crate::early_exit::{early_exit_expected_output, test_with_early_exit};
+#[cfg(unix)]
+use crate::disk_space::check_disk_space;
+use crate::gha::{annotate_compiler_error, annotate_failed_tests};
+use crate::progress_events::{report, ProgressEvent};
+#[cfg(unix)]
+use crate::progress_events::open_progress_fd;
+use crate::problem_config::PROBLEM_CONFIG_PATH;
+#[cfg(target_os = "linux")]
+use crate::cpu_affinity::available_cpus;
+use crate::thread_pools::{build_thread_pools, checking_pool, CheckingQueue};
 
 static RECEIVED_CTRL_C: AtomicBool = AtomicBool::new(false);
+static TOTAL_TIMEOUT_EXCEEDED: AtomicBool = AtomicBool::new(false);
 
-fn print_output(stopped_early: bool, test_summary: &mut Option<TestSummary>) {
-	let Some(test_summary) = test_summary else {
-		println!("{}", "Toster was stopped before testing could start".red());
-		exit(0);
-	};
-
-	if stopped_early {
-		println!();
-	}
-
-	let additional_info = match (&test_summary.slowest_test, &test_summary.most_memory_used) {
-		(None, None) => "".to_string(),
-		(Some((duration, slowest_test_name)), None) => format!(
-			" (Slowest test: {} at {:.3}s)",
-			slowest_test_name, duration.as_secs_f32(),
-		),
-		(None, Some((memory, most_memory_test_name))) => format!(
-			" (Most memory used: {} at {:.3}KiB)",
-			most_memory_test_name, memory,
-		),
-		(Some((duration, slowest_test_name)), Some((memory, most_memory_test_name))) => format!(
-			" (Slowest test: {} at {:.3}s, most memory used: {} at {}KiB)",
-			slowest_test_name, duration.as_secs_f32(),
-			most_memory_test_name, memory,
-		),
-	};
-
-	println!(
-		"{} {} {:.2}s{}\nResults: {}",
-        if test_summary.generate_mode { "Generating" } else { "Testing" },
-        if stopped_early {"stopped after"} else {"finished in"},
-        test_summary.start_time.elapsed().as_secs_f64(),
-        additional_info,
-        test_summary.format_counts(true),
-	);
-
-	let incorrect_results = test_summary.get_errors();
-	if !incorrect_results.is_empty() {
-		println!("Errors were found in the following tests:");
-
-		for (test_name, error) in incorrect_results.iter() {
-			println!("{}", error.to_string(test_name));
-		}
-	}
-
-	exit(0);
-}
-
+#[allow(deprecated)]
 fn setup_panic() {
 	let is_panicking = AtomicBool::new(false);
 	match human_panic::PanicStyle::default() {
@@ -106,7 +153,11 @@ fn setup_panic() {
 				is_panicking.store(true, Release);
 
 				let file_path = handle_dump(&meta, info);
+				if let Some(file_path) = &file_path {
+					let _ = extend_report(file_path);
+				}
 				print_msg(file_path, &meta).expect("human-panic: printing error message to console failed");
+				clean_up_remote();
 				exit(0);
 			}));
 		},
@@ -114,46 +165,381 @@ fn setup_panic() {
 	}
 }
 
-fn check_ctrlc() -> Result<(), TestError> {
-	if RECEIVED_CTRL_C.load(Acquire) { Err(Cancelled) }
+/// Checks if testing should stop because of Ctrl+C or the total timeout
+fn check_stopped() -> Result<(), TestError> {
+	if RECEIVED_CTRL_C.load(Acquire) || TOTAL_TIMEOUT_EXCEEDED.load(Acquire) { Err(Cancelled) }
 	else { Ok(()) }
 }
 
-fn init_runner(executable: PathBuf, config: &ParsedConfig) -> Result<AnyTestExecutor, FormattedError> {
+fn init_runner(executable: Executable, config: &ParsedConfig) -> Result<AnyTestExecutor, FormattedError> {
+	log(1, || format!("Execute mode: {:?}", config.execute_mode));
 	Ok(match config.execute_mode {
 		Simple => AnyTestExecutor::Simple(SimpleExecutor {
-			executable_path: executable,
-			timeout: config.execute_timeout,
+			executable,
+			environment: config.environment.clone(),
 		}),
+		#[cfg(target_os = "linux")]
+		Instructions => AnyTestExecutor::Instructions(InstructionsExecutor::init_and_test(SimpleExecutor {
+			executable,
+			environment: config.environment.clone(),
+		})?),
 		#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-		Sio2jail { memory_limit } => AnyTestExecutor::Sio2Jail(Sio2jailExecutor::init_and_test(
-			config.execute_timeout,
+		Sio2jail { ref path, .. } => AnyTestExecutor::Sio2Jail(Sio2jailExecutor::init_and_test(
+			executable,
+			path.as_deref(),
+			config.environment.clone(),
+			config.test_limits.global(),
+		)?),
+		Remote { ref host } => AnyTestExecutor::Remote(RemoteExecutor::init(
 			executable,
-			memory_limit,
+			host,
+			config.environment.clone(),
 		)?),
+		Wsl => WslExecutor::init(SimpleExecutor {
+			executable,
+			environment: config.environment.clone(),
+		}),
 	})
 }
 
-fn map_tests<T>(
+/// Runs every test with `run` and checks its output with `check`, adding the results to the test summary.
+///
+/// Unless testing is sequential, the outputs are checked on a separate thread pool,
+/// so that the testing threads can start running the next tests instead of waiting for big outputs to be compared
+fn map_tests<T, R: Send>(
 	inputs: TestingInputs<T>,
 	progress_bar: ProgressBar,
 	test_summary: &Arc<Mutex<Option<TestSummary>>>,
-	callback: impl Fn(Test) -> Result<ExecutionMetrics, TestError> + Sync
+	config: &ParsedConfig,
+	cache: Option<&ResultCache>,
+	run: impl Fn(&Test, &mut ExecutionMetrics) -> Result<R, TestError> + Sync,
+	check: impl Fn(&Test, R) -> Result<(), TestError> + Sync,
 ) where T: IndexedParallelIterator<Item = Test> {
-	inputs.iterator.progress_with(progress_bar).try_for_each(|input| {
-		let test_name = input.test_name.clone();
-
-		let result = callback(input);
-
-		let mut test_summary = test_summary.lock().expect("Failed to lock test summary mutex");
-		let test_summary = test_summary.as_mut().unwrap();
-		match result {
-			Ok(metrics) => test_summary.add_success(&metrics, &test_name),
-			Err(Cancelled) => return None,
-			Err(error) => test_summary.add_test_error(error, test_name),
+	let (sequential, stream_errors) = (config.sequential, config.stream_errors);
+	// Adds the result of a test to the summary, returning None if testing was stopped
+	let add_result = |test_name: String, metrics: ExecutionMetrics, result: Result<(), TestError>| {
+		let finished_test = test_name.clone();
+		let (finished_result, printed_result) = {
+			let mut test_summary = test_summary.lock().expect("Failed to lock test summary mutex");
+			let test_summary = test_summary.as_mut().unwrap();
+			test_summary.mark_finished(&test_name);
+			let mut streamed_error = None;
+			match result {
+				Ok(()) => test_summary.add_success(&metrics, &test_name),
+				Err(Cancelled | ProgramError { error: Interrupted } | CheckerError { error: Interrupted }) => return None,
+				Err(error) => {
+					if stream_errors == Some(StreamErrors::Full) {
+						streamed_error = Some(error.to_string(&test_name, &metrics));
+					}
+					test_summary.add_test_error(error, test_name, &metrics)
+				},
+			};
+			let result = test_summary.test_results.last().expect("The test result should have just been added");
+			let printed_result = match streamed_error {
+				Some(streamed_error) if sequential => Some(format!("{}\n{}", format_test_result(result), streamed_error)),
+				Some(streamed_error) => Some(streamed_error),
+				None if sequential || (stream_errors.is_some() && !result.verdict.is_success()) => Some(format_test_result(result)),
+				None => None,
+			};
+			((result.verdict, result.time, result.memory_kibibytes), printed_result)
 		};
+		// The test is only counted as done once its output is checked
+		progress_bar.inc(1);
+		// The event is written after the lock is released, so that a slow reader doesn't block the other tests
+		let (verdict, time, memory_kibibytes) = finished_result;
+		report(ProgressEvent::TestFinished {
+			test: &finished_test,
+			verdict,
+			time: time.map(|time| time.as_secs_f64()),
+			memory_kibibytes,
+		});
+		// The progress bar locks the test summary when it's redrawn, so it can only be suspended after the lock is released.
+		// ProgressBar::println isn't used, because it doesn't print anything when the progress bar is hidden
+		if let Some(printed_result) = printed_result {
+			progress_bar.suspend(|| println!("{}", printed_result));
+		}
 		Some(())
+	};
+	let finish_test = |input: Test, cache_key: Option<String>, metrics: ExecutionMetrics, result: Result<(), TestError>| {
+		// The program might have left files it can't remove in the directory, this isn't worth failing the test over
+		let _ = fs::remove_dir_all(&input.working_directory);
+		let result = match (cache.zip(cache_key), result) {
+			(_, Err(error @ (Cancelled | ProgramError { error: Interrupted } | CheckerError { error: Interrupted }))) => Err(error),
+			(Some((cache, key)), result) => cache.save(&key, &metrics, result),
+			(None, result) => result,
+		};
+		add_result(input.test_name, metrics, result)
+	};
+	let (check, finish_test) = (&check, &finish_test);
+
+	// Makes the checks spawned into the scope able to borrow everything declared before it
+	fn checked_in<'scope, F: FnOnce(Option<&rayon::Scope<'scope>>)>(test_all: F) -> F {
+		test_all
+	}
+	let checking_queue = checking_pool().map(CheckingQueue::new);
+	let test_all = checked_in(|scope| {
+		inputs.iterator.try_for_each(|mut input| {
+			check_stopped().ok()?;
+			if let Err(error) = input.decompress_input() {
+				return add_result(input.test_name, ExecutionMetrics::NONE, Err(InvalidTestFile { error }));
+			}
+			let cache_key = cache.map(|cache| cache.key(&input));
+
+			let cached = cache.zip(cache_key.as_deref()).and_then(|(cache, key)| cache.get(key));
+			if let Some((metrics, result)) = cached {
+				log(2, || format!("The result of test {} was taken from the cache", input.test_name));
+				return add_result(input.test_name, metrics, result);
+			}
+
+			fs::create_dir_all(&input.working_directory).expect("Failed to create the test's working directory");
+			test_summary.lock().expect("Failed to lock test summary mutex").as_mut().unwrap().mark_running(&input.test_name);
+			let mut metrics = ExecutionMetrics::NONE;
+			match (scope.zip(checking_queue.as_ref()), run(&input, &mut metrics)) {
+				(Some((scope, queue)), Ok(output)) => {
+					let queued = queue.enqueue();
+					scope.spawn(move |_| {
+						let result = check(&input, output);
+						let _ = finish_test(input, cache_key, metrics, result);
+						drop(queued);
+					});
+					Some(())
+				},
+				(_, output) => {
+					let result = output.and_then(|output| check(&input, output));
+					finish_test(input, cache_key, metrics, result)
+				},
+			}
+		});
 	});
+	match checking_pool() {
+		Some(pool) => pool.in_place_scope(|scope| test_all(Some(scope))),
+		None => test_all(None),
+	}
+}
+
+/// An output generated in generate mode, which is written next to the output file of the test,
+/// and only replaces it once the checker accepts it. It's deleted if it's dropped before that
+struct GeneratedOutput {
+	file: NamedTempFile,
+	path: PathBuf,
+}
+
+impl GeneratedOutput {
+	fn save(self) -> Result<(), TestError> {
+		self.file.persist(&self.path)
+			.map_err(|persist_error| write_output_error(&self.path, persist_error.error))?;
+		Ok(())
+	}
+}
+
+fn write_output_error(path: &Path, error: io::Error) -> TestError {
+	InvalidTestFile { error: format!("Failed to write {}: {}", path.display(), error) }
+}
+
+fn generate_output(runner: &impl TestExecutor, input: &Test, metrics: &mut ExecutionMetrics, output_directory: &Path, output_ext: &str, kept_files: Option<&KeptFiles>, stderr_directory: Option<&StderrDirectory>) -> Result<GeneratedOutput, TestError> {
+	check_stopped()?;
+
+	let stderr_file = create_temp_file().expect("Failed to create memfile");
+	let (run_metrics, result) = test_to_temp(runner, &input.input_source.get_file(), &stderr_file, &input.arguments, &input.working_directory, &input.limits);
+	*metrics = run_metrics;
+	if let Some(kept_files) = kept_files {
+		warn_if_failed(kept_files.save_test_file(&input.test_name, "stderr", &stderr_file));
+	}
+	if let Some(stderr_directory) = stderr_directory {
+		warn_if_failed(stderr_directory.save(&input.test_name, &stderr_file));
+	}
+	check_stopped()?;
+
+	let mut output = result.map_err(|error| program_error(input, error))?;
+	if let Some(kept_files) = kept_files {
+		warn_if_failed(kept_files.save_test_file(&input.test_name, "stdout", &output));
+	}
+	let path = input.generated_output_path(output_directory, output_ext);
+	let file = write_next_to(&path, &mut output).map_err(|error| write_output_error(&path, error))?;
+	Ok(GeneratedOutput { file, path })
+}
+
+/// Runs the checker on an output file created in generate mode. There is no expected output to give it,
+/// since the generated file is what would be used as one
+fn check_generated_output(checker: &Checker, protocol: CheckerProtocol, input: &Test, output_file_path: &Path) -> Result<(), TestError> {
+	let test = CheckedTest {
+		test_name: &input.test_name,
+		input_path: input.input_source.path(),
+		expected_path: None,
+		working_directory: &input.working_directory,
+	};
+	match protocol {
+		CheckerProtocol::Files => checker.check_files(output_file_path, &test),
+		CheckerProtocol::Stdin => {
+			let mut checker_input = Checker::prepare_checker_input(&input.input_source);
+			let mut output = File::open(output_file_path).expect("Failed to open generated output file");
+			io::copy(&mut output, &mut checker_input).expect("Failed to write checker input");
+			checker.check(checker_input, &test)
+		}
+	}
+}
+
+/// Prints the error of saving a file that isn't needed for testing, e.g. with --keep-temp, without failing the test
+fn warn_if_failed(result: Result<(), String>) {
+	if let Err(error) = result {
+		eprintln!("{}", error.yellow());
+	}
+}
+
+/// Turns an error of the tested program into the error of the test, collecting its core dump if it crashed
+fn program_error(#[cfg_attr(not(target_os = "linux"), allow(unused_variables))] input: &Test, error: ExecutionError) -> TestError {
+	#[cfg(target_os = "linux")]
+	let error = collect_core_dump(&input.test_name, &input.working_directory, error);
+	ProgramError { error }
+}
+
+/// Returns the output file of the test given to the checker. Checkers need the whole file, so hash files can't be used with them
+fn checker_expected_output(input: &Test, output_directory: &Path, output_ext: &str, fallback_output_exts: &[String]) -> Option<PathBuf> {
+	input.output_files(output_directory, output_ext, fallback_output_exts)
+		.into_iter()
+		.find(|path| path.is_file() && !is_hash_file(path))
+}
+
+/// Decompresses the output file given to the checker if it's compressed, since checkers get the paths of the files.
+/// The input doesn't need this, since it's decompressed before the test is run
+fn decompress_expected_output(expected_path: Option<&Path>) -> Result<Option<NamedTempFile>, TestError> {
+	match expected_path {
+		Some(path) => decompress_to_named_file(path).map_err(|error| InvalidTestFile { error }),
+		None => Ok(None),
+	}
+}
+
+/// Compiles the program and runs it once on the input read from stdin, printing its output, stderr and metrics
+fn run_stdin_test(config: &ParsedConfig, compiler: &Compiler, working_directory: &Path, arguments: Option<&str>) -> Result<(), FormattedError> {
+	let (executable, compilation) = compiler
+		.prepare_executable(&config.source_path, "program", config.source_kind, CompiledCache::Unused)
+		.map_err(|error| error.to_formatted(false))?;
+	if let Some(compilation) = compilation {
+		compilation.print("Program compilation", config.show_warnings);
+	}
+	let runner = init_runner(executable, config)?;
+
+	if io::stdin().is_terminal() {
+		println!("{}", "Reading the input from stdin, press Ctrl+D (Ctrl+Z and Enter on Windows) to finish".bold());
+	}
+	let mut input = create_temp_file().expect("Failed to create memfile");
+	// The handles are locked for the whole copy, which also lets io::copy splice the data on Linux
+	io::copy(&mut io::stdin().lock(), &mut input)
+		.map_err(|error| FormattedError::from_str(&format!("Failed to read the input from stdin: {}", error)))?;
+	input.rewind().expect("Failed to rewind memfile");
+
+	fs::create_dir_all(working_directory).expect("Failed to create the test's working directory");
+	let arguments = test_arguments(None, "stdin", arguments)?;
+	let mut stderr_file = create_temp_file().expect("Failed to create memfile");
+	let (metrics, result) = test_to_temp(&runner, &input, &stderr_file, &arguments, working_directory, config.test_limits.global());
+
+	if let Ok(mut output) = result.as_ref() {
+		io::copy(&mut output, &mut io::stdout().lock()).expect("Failed to print the program's output");
+	}
+	stderr_file.rewind().expect("Failed to rewind memfile");
+	io::copy(&mut stderr_file, &mut io::stderr().lock()).expect("Failed to print the program's stderr");
+	match result {
+		Ok(_) => println!("{}", format!("Finished{}", metrics.format_suffix()).green()),
+		Err(error) => println!("{}", ProgramError { error }.to_string("stdin", &metrics)),
+	}
+	Ok(())
+}
+
+/// Copies the input of the failed test that comes first by name to the clipboard
+fn copy_failed_input(test_summary: &TestSummary, input_paths: &HashMap<String, PathBuf>) {
+	let Some(test_name) = test_summary.test_results.iter()
+		.filter(|result| !result.verdict.is_success())
+		.map(|result| &result.test_name)
+		.min_by(|a, b| human_sort::compare(a, b)) else {
+		return;
+	};
+	let mut input = vec![];
+	if let Err(error) = open_decompressed(&input_paths[test_name]).and_then(|mut file| file.read_to_end(&mut input).map_err(|error| error.to_string())) {
+		println!("{}", format!("Failed to read the input of test {}: {}", test_name, error).red());
+		return;
+	}
+	match copy_to_clipboard(&input) {
+		Ok(()) => println!("{}", format!("The input of test {} was copied to the clipboard", test_name).green()),
+		Err(error) => println!("{}", format!("Failed to copy the input of test {} to the clipboard: {}", test_name, error).red()),
+	}
+}
+
+/// Creates the output directory and removes the tests whose output files already exist
+/// from `tests`, or fails if they shouldn't exist
+fn handle_existing_outputs(tests: &mut Vec<Test>, output_directory: &Path, output_ext: &str, existing_outputs: ExistingOutputs) -> Result<(), FormattedError> {
+	if !output_directory.is_dir() {
+		fs::create_dir_all(output_directory).expect("Failed to create output directory");
+	}
+
+	let output_exists = |test: &Test| test.generated_output_path(output_directory, output_ext).exists();
+	match existing_outputs {
+		ExistingOutputs::Error => {
+			let existing_count = tests.iter().filter(|test| output_exists(test)).count();
+			if existing_count > 0 {
+				return Err(FormattedError::from_str(&format!(
+					"{} of the output files already exist. Use --force to overwrite them or --skip-existing to only generate the missing ones",
+					existing_count,
+				)));
+			}
+		}
+		ExistingOutputs::Skip => tests.retain(|test| !output_exists(test)),
+		ExistingOutputs::Overwrite => {}
+	}
+	Ok(())
+}
+
+/// Generates the output files with the model solution before testing the main program against them.
+///
+/// Returns whether testing should continue, which it shouldn't if generation was stopped early
+fn generate_model_outputs(
+	config: &ParsedConfig,
+	model: &ModelConfig,
+	tests: &[Test],
+	compiler: &Compiler,
+	test_summary: &Arc<Mutex<Option<TestSummary>>>,
+) -> Result<bool, FormattedError> {
+	set_stage("generating the outputs with the model solution");
+	let mut tests = tests.to_vec();
+	handle_existing_outputs(&mut tests, &model.output_directory, &model.output_ext, model.existing_outputs)?;
+	if tests.is_empty() {
+		println!("{}", "All of the output files already exist, skipping generation".green());
+		return Ok(true);
+	}
+
+	let (executable, compilation) = compiler
+		.prepare_executable(&model.path, "model", FileKind::Detect, CompiledCache::Unused)
+		.map_err(|error| error.to_formatted(false))?;
+	if let Some(compilation) = compilation {
+		compilation.print("Model solution compilation", config.show_warnings);
+	}
+	let runner = init_runner(executable, config)?;
+	#[cfg(unix)]
+	if config.space_check {
+		check_disk_space(&runner, &tests, &model.output_directory, &model.output_ext)?;
+	}
+
+	let inputs = TestingInputs::from_tests(tests);
+	*test_summary.lock().expect("Failed to lock test summary mutex") = Some(TestSummary::new(true, inputs.test_count));
+	report(ProgressEvent::Started { stage: "model", total: inputs.test_count });
+	let progress_bar = create_progress_bar(inputs.test_count, create_progress_style(test_summary.clone()));
+	map_tests(inputs, progress_bar, test_summary, config, None, |input, metrics| {
+		generate_output(&runner, input, metrics, &model.output_directory, &model.output_ext, None, None)
+	}, |_, output| output.save());
+
+	let stopped_early = RECEIVED_CTRL_C.load(Acquire) || TOTAL_TIMEOUT_EXCEEDED.load(Acquire);
+	report(ProgressEvent::Finished { stopped_early });
+	let mut test_summary = test_summary.lock().expect("Failed to lock test summary mutex");
+	set_stage("printing the results");
+	print_output(stopped_early, &mut test_summary, &config.output);
+	let test_summary = test_summary.as_ref().expect("The test summary should be initialized");
+	if stopped_early {
+		return Ok(false);
+	}
+	if test_summary.success < test_summary.total {
+		return Err(FormattedError::from_str("The model solution failed on some of the tests, fix it before testing the program"));
+	}
+	println!();
+	Ok(true)
 }
 
 fn main() -> ExitCode {
@@ -166,135 +552,509 @@ fn main() -> ExitCode {
 	ExitCode::SUCCESS
 }
 
+/// The stage reported in the crash report while running a subcommand
+fn subcommand_stage(command: &ToolCommand) -> &'static str {
+	match command {
+		ToolCommand::Diff(_) => "comparing the outputs of the programs",
+		ToolCommand::Contest(_) => "testing the problems of the contest",
+		ToolCommand::Grade(_) => "grading the submissions",
+		ToolCommand::Profile(_) => "profiling the program",
+		ToolCommand::CleanOutputs(_) => "cleaning the outputs",
+		ToolCommand::Show(_) => "showing the archived results",
+		ToolCommand::Compile(_) => "compiling the program",
+		#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+		ToolCommand::InstallSio2jail { .. } => "installing sio2jail",
+	}
+}
+
 fn try_main() -> Result<(), FormattedError> {
-    let config = ParsedConfig::try_from(Args::parse())
+	let start_time = Instant::now();
+	let matches = Args::command().get_matches();
+	let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|error| error.format(&mut Args::command()).exit());
+	args.timeout_given = matches.value_source("timeout") == Some(ValueSource::CommandLine);
+	let configuration = format!("{}\n\n{:#?}", command_line(), args);
+	set_configuration(configuration.clone());
+	set_verbosity(args.verbose);
+	#[cfg(unix)]
+	if let Some(fd) = args.progress_fd {
+		open_progress_fd(fd).map_err(|error| FormattedError::from_str(&error))?;
+	}
+	args.color.apply();
+	if let Some(command) = &args.command {
+		set_stage(subcommand_stage(command));
+	}
+	match args.command.take() {
+		Some(ToolCommand::Diff(diff_args)) => return run_program_diff(diff_args),
+		Some(ToolCommand::Contest(contest_args)) => return run_contest(contest_args),
+		Some(ToolCommand::Grade(grade_args)) => return run_grade(grade_args),
+		Some(ToolCommand::Profile(profile_args)) => return run_profile(profile_args),
+		Some(ToolCommand::CleanOutputs(clean_outputs_args)) => return run_clean_outputs(clean_outputs_args),
+		Some(ToolCommand::Show(show_args)) => return run_show(show_args),
+		Some(ToolCommand::Compile(compile_args)) => return run_compile(compile_args),
+		#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+		Some(ToolCommand::InstallSio2jail { path }) => {
+			let path = install_sio2jail(path)?;
+			println!("{}", format!("Sio2jail was installed at {}", path.display()).green());
+			return Ok(());
+		}
+		None => {}
+	}
+
+	let config = ParsedConfig::try_from(args)
+		.map_err(|error| FormattedError::from_str(&error))?;
+	if config.problem_config_used {
+		println!("{}", format!("Using the limits from {}, use --no-problem-config to ignore them", PROBLEM_CONFIG_PATH).bright_black());
+	}
+	set_stage("preparing the tests");
+	if let Some(temp_dir) = &config.temp_dir {
+		set_temp_directory(temp_dir.clone());
+	}
+	#[cfg(target_os = "linux")]
+	if config.no_memfd {
+		disable_memfd();
+	}
+	if config.redact_diff {
+		redact_diffs();
+	}
+	// The files of the archive are collected like the ones kept with --keep-temp
+	let archive_directory = config.archive.as_ref()
+		.map(|_| create_temp_dir().expect("Failed to create temporary directory"));
+	let kept_files = config.keep_temp.clone()
+		.or_else(|| archive_directory.as_ref().map(|directory| directory.path().to_path_buf()))
+		.map(KeptFiles::new).transpose()
+		.map_err(|error| FormattedError::from_str(&error))?;
+	let stderr_directory = config.stderr_dir.clone().map(StderrDirectory::new).transpose()
 		.map_err(|error| FormattedError::from_str(&error))?;
 	let test_summary: Arc<Mutex<Option<TestSummary>>> = Arc::new(Mutex::new(None));
 	{
 		let test_summary = test_summary.clone();
+		let output_config = config.output.clone();
 		ctrlc::set_handler(move || {
+			// The first Ctrl+C only stops new tests from starting, try_main prints the results once the running ones finish
+			let testing_started = test_summary.lock().expect("Failed to lock test summary mutex").is_some();
+			if testing_started && !RECEIVED_CTRL_C.swap(true, AcqRel) {
+				return;
+			}
+
 			RECEIVED_CTRL_C.store(true, Release);
-			print_output(true, &mut test_summary.lock().expect("Failed to lock test summary mutex"));
+			kill_running_children();
+			print_output(true, &mut test_summary.lock().expect("Failed to lock test summary mutex"), &output_config);
+			report(ProgressEvent::Finished { stopped_early: true });
+			clean_up_remote();
+			exit(0);
 		}).expect("Error setting Ctrl-C handler");
 	}
 
-	let tempdir = tempdir().expect("Failed to create temporary directory");
+	if let Some(total_timeout) = config.total_timeout {
+		thread::spawn(move || {
+			thread::sleep(total_timeout);
+			TOTAL_TIMEOUT_EXCEEDED.store(true, Release);
+		});
+	}
 
-	if let ActionType::Generate { output_directory, .. } = &config.action_type {
-		if !output_directory.is_dir() {
-			fs::create_dir_all(output_directory).expect("Failed to create output directory");
-		}
+	#[cfg(target_os = "linux")]
+	if config.core_dumps {
+		enable_core_dumps().map_err(|error| FormattedError::from_str(&error))?;
 	}
+	#[cfg(target_os = "linux")]
+	let pinned_cpus = config.pin_cpus.then(available_cpus).transpose().map_err(|error| FormattedError::from_str(&error))?;
+	#[cfg(not(target_os = "linux"))]
+	let pinned_cpus = None;
+	build_thread_pools(config.sequential, pinned_cpus).map_err(|error| FormattedError::from_str(&error))?;
+
+	let tempdir = create_temp_dir().expect("Failed to create temporary directory");
+	log(1, || format!("Temporary directory: {}", tempdir.path().display()));
 
 	let compiler = Compiler {
 		tempdir: &tempdir,
 		compile_timeout: config.compile_timeout,
-		compile_command: &config.compile_command,
+		languages: &config.languages,
+		compile_shell: config.compile_shell,
+		precompile_header: config.precompile_header,
+		wsl: config.wsl,
+	};
+
+	if let InputConfig::Stdin { arguments } = &config.input {
+		return run_stdin_test(&config, &compiler, &tempdir.path().join("stdin"), arguments.as_deref());
+	}
+
+	let (mut tests, input_directory) = match &config.input {
+		InputConfig::Directory { directory, pattern, exclude, arguments } => {
+			let tests = prepare_file_inputs(directory, pattern, exclude, &config.test_limits, &tempdir.path().join("tests"), arguments.as_deref())?;
+			(tests, Some(directory.clone()))
+		},
+		InputConfig::MultitestFile { path, separator, arguments } => {
+			let directory = tempdir.path().join("multitest");
+			let tests = prepare_multitest_inputs(path, separator, &directory, &config.test_limits, &tempdir.path().join("tests"), arguments.as_deref())?;
+			(tests, Some(directory))
+		},
+		InputConfig::Manifest { manifest, arguments } => {
+			let tests = prepare_manifest_inputs(manifest, &config.test_limits, &tempdir.path().join("tests"), arguments.as_deref())?;
+			(tests, None)
+		},
+		InputConfig::Stdin { .. } => unreachable!("The stdin test is run before the tests are prepared"),
 	};
+	log(1, || format!("Found {} tests", tests.len()));
+	if !config.tag_filter.is_empty() {
+		config.tag_filter.apply(&mut tests)?;
+		log(1, || format!("{} tests match the tags", tests.len()));
+	}
+	if config.dry_run {
+		print_dry_run(&config, &compiler, &tests);
+		return Ok(());
+	}
+	if let LineEndings::Normalize = config.line_endings {
+		normalize_inputs(&mut tests, &tempdir.path().join("inputs"))?;
+	}
+
+	if let Some(model) = &config.model {
+		if !generate_model_outputs(&config, model, &tests, &compiler, &test_summary)? {
+			return Ok(());
+		}
+	}
+
+	if let ActionType::Generate { output_directory, output_ext, existing_outputs, .. } = &config.action_type {
+		handle_existing_outputs(&mut tests, output_directory, output_ext, *existing_outputs)?;
+		if tests.is_empty() {
+			println!("{}", "All of the output files already exist".green());
+			return Ok(());
+		}
+	}
 
+	if let ActionType::SimpleCompare { output_directory, output_ext, fallback_output_exts, fail_on_unmatched, .. } = &config.action_type {
+		let unmatched = find_unmatched_files(&tests, input_directory.as_deref(), output_directory, output_ext, fallback_output_exts);
+		if !unmatched.is_empty() {
+			if *fail_on_unmatched {
+				return Err(FormattedError::from_str(&unmatched.describe()));
+			}
+			println!("{}", unmatched.describe().yellow());
+		}
+	}
+
+	set_stage("compiling the program");
+	let compilation_start = Instant::now();
+	let compiler_name = compiler.compiler_name(&config.source_path, config.source_kind);
+	if let Some(compiler_name) = compiler_name.clone() {
+		set_compiler(compiler_name);
+	}
+	// Only the checker is saved after compiling it, programs are saved by "toster compile",
+	// so that a program isn't reused after changing a file it includes without the user knowing
+	let (program_cache, checker_cache) = if config.reuse_compiled {
+		(CompiledCache::Reuse, CompiledCache::ReuseAndSave)
+	} else {
+		(CompiledCache::Unused, CompiledCache::Unused)
+	};
 	let executable = {
-		let (executable, compilation_time) = compiler
-			.prepare_executable(&config.source_path, "program")
-			.map_err(|error| error.to_formatted(false))?;
-		if let Some(compilation_time) = compilation_time {
-			println!("{}", format!("Program compilation completed in {:.2}", compilation_time.as_secs_f32()).green());
+		let (executable, compilation) = compiler
+			.prepare_executable(&config.source_path, "program", config.source_kind, program_cache)
+			.map_err(|error| {
+				if config.gha {
+					annotate_compiler_error(&config.source_path, &error);
+				}
+				error.to_formatted(false)
+			})?;
+		match &compilation {
+			Some(compilation) => compilation.print("Program compilation", config.show_warnings),
+			None if compiler_name.is_some() => println!("{}", "Using the program compiled earlier with toster compile, use --no-cache to compile it again".bright_black()),
+			None => {},
+		}
+		if let Some(kept_files) = &kept_files {
+			kept_files.save_executable(&executable, "program").map_err(|error| FormattedError::from_str(&error))?;
+			if let Some(compilation) = &compilation {
+				kept_files.save_text("program_compilation.log", &compilation.diagnostics).map_err(|error| FormattedError::from_str(&error))?;
+			}
 		}
 		executable
 	};
 
-	let checker_executable = if let ActionType::Checker { path } = &config.action_type {
-		let (executable, compilation_time) = compiler
-			.prepare_executable(path, "checker")
-			.map_err(|error| error.to_formatted(true))?;
-		if let Some(compilation_time) = compilation_time {
-			println!("{}", format!("Checker compilation completed in {:.2}", compilation_time.as_secs_f32()).green());
+	let checker_path = match &config.action_type {
+		ActionType::Checker { path, .. } | ActionType::Generate { checker: Some(OutputChecker { path, .. }), .. } => Some(path),
+		_ => None,
+	};
+	let checker_executable = if let Some(path) = checker_path {
+		let (executable, compilation) = compiler
+			.prepare_executable(path, "checker", FileKind::Detect, checker_cache)
+			.map_err(|error| {
+				if config.gha {
+					annotate_compiler_error(path, &error);
+				}
+				error.to_formatted(true)
+			})?;
+		if let Some(compilation) = &compilation {
+			compilation.print("Checker compilation", config.show_warnings);
+		}
+		if let Some(kept_files) = &kept_files {
+			kept_files.save_executable(&executable, "checker").map_err(|error| FormattedError::from_str(&error))?;
+			if let Some(compilation) = &compilation {
+				kept_files.save_text("checker_compilation.log", &compilation.diagnostics).map_err(|error| FormattedError::from_str(&error))?;
+			}
 		}
 		Some(executable)
 	} else { None };
 
+	let cache = if config.use_cache {
+		let mut executables = vec![executable.path.as_path()];
+		executables.extend(checker_executable.as_ref().map(|checker| checker.path.as_path()));
+		let expected_outputs = match &config.action_type {
+			ActionType::SimpleCompare { output_directory, output_ext, fallback_output_exts, .. }
+			| ActionType::Checker { protocol: CheckerProtocol::Files, output_directory, output_ext, fallback_output_exts, .. } => Some(ExpectedOutputs {
+				directory: output_directory.clone(),
+				ext: output_ext.clone(),
+				fallback_exts: fallback_output_exts.clone(),
+			}),
+			_ => None,
+		};
+		let verdict_settings = match &config.action_type {
+			// The cached descriptions of the differences would otherwise show the contents of the output files with --redact-diff
+			ActionType::SimpleCompare { comparator, .. } => format!("{:?} {}", comparator, config.redact_diff),
+			ActionType::Checker { protocol, verdict, .. } => format!("{:?} {:?}", protocol, verdict),
+			ActionType::Generate { .. } => String::new(),
+		};
+		let settings = format!("{:?} {:?} {}", config.execute_mode, config.environment, verdict_settings);
+		Some(ResultCache::new(&executables, &settings, expected_outputs).map_err(|error| FormattedError::from_str(&error))?)
+	} else { None };
+
+	#[cfg(target_os = "linux")]
+	let recorded_executable = config.record_failures.then(|| executable.clone());
 	let runner = init_runner(executable, &config)?;
+	#[cfg(unix)]
+	if let (ActionType::Generate { output_directory, output_ext, .. }, true) = (&config.action_type, config.space_check) {
+		check_disk_space(&runner, &tests, output_directory, output_ext)?;
+	}
 	let checker = checker_executable.map(|checker_executable| {
-		Checker::new(checker_executable, config.execute_timeout)
+		let (ActionType::Checker { verdict, .. } | ActionType::Generate { checker: Some(OutputChecker { verdict, .. }), .. }) = &config.action_type else {
+			unreachable!("The checker is only compiled with a checker action or a checked generation");
+		};
+		Checker::new(checker_executable, config.execute_timeout, *verdict, config.seed)
 	});
 
-	// Progress bar styling
-    let style: ProgressStyle = {
-        let test_summary = test_summary.clone();
-        ProgressStyle::with_template("[{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})\n{counts} {ctrlc}")
-            .expect("Progress bar creation failed")
-            .with_key("eta", |state: &ProgressState, w: &mut dyn FmtWrite| write!(w, "{:.1}s", state.eta().as_secs_f64()).expect("Displaying the progress bar failed"))
-            .progress_chars("#>-")
-            .with_key("counts", move |_state: &ProgressState, w: &mut dyn FmtWrite| {
-                write!(w, "{}", test_summary.lock().expect("Failed to lock test summary mutex").as_ref().unwrap().format_counts(false)).expect("Displaying the progress bar failed")
-            })
-            .with_key("ctrlc", |_state: &ProgressState, w: &mut dyn FmtWrite|
-                write!(w, "{}", "(Press Ctrl+C to stop testing and print current results)".bright_black()).expect("Displaying the progress bar Ctrl+C message failed")
-            )
-    };
-
-	let inputs = match &config.input {
-		InputConfig::Directory { directory, ext } => {
-			prepare_file_inputs(directory, ext)?
-		},
-	};
-	*test_summary.lock().expect("Failed to lock test summary mutex") = Some(TestSummary::new(config.generate_mode(), inputs.test_count));
-
-	let progress_bar = ProgressBar::new(inputs.test_count as u64).with_style(style);
+	let style = create_progress_style(test_summary.clone());
 
-	match config.action_type {
-		ActionType::Generate { output_directory, output_ext } => {
-			map_tests(inputs, progress_bar, &test_summary, |input| {
-				check_ctrlc()?;
+	if config.sequential {
+		tests.sort_by(|a, b| human_sort::compare(&a.test_name, &b.test_name));
+	}
+	let input_paths: HashMap<String, PathBuf> = tests.iter()
+		.map(|test| (test.test_name.clone(), test.input_source.original_path().to_path_buf()))
+		.collect();
+	#[cfg(target_os = "linux")]
+	let recorded_tests = if config.record_failures { tests.clone() } else { vec![] };
+	let inputs = TestingInputs::from_tests(tests);
+	*test_summary.lock().expect("Failed to lock test summary mutex") = Some(TestSummary::new(config.generate_mode(), inputs.test_count));
+	report(ProgressEvent::Started { stage: "testing", total: inputs.test_count });
+	set_stage("testing");
 
-				let output_file_path = output_directory.join(format!("{}{}", input.test_name, &output_ext));
-				let file = File::create(output_file_path).expect("Failed to create output file");
-				check_ctrlc()?;
+	let testing_start = Instant::now();
+	let progress_bar = create_progress_bar(inputs.test_count, style);
 
-				let (metrics, result) = runner.test_to_file(&input.input_source.get_file(), &file);
-				check_ctrlc()?;
+	match &config.action_type {
+		ActionType::Generate { output_directory, output_ext, checker: output_checker, .. } => {
+			map_tests(inputs, progress_bar, &test_summary, &config, cache.as_ref(), |input, metrics| {
+				generate_output(&runner, input, metrics, output_directory, output_ext, kept_files.as_ref(), stderr_directory.as_ref())
+			}, |input, output| {
+				if let (Some(checker), Some(output_checker)) = (&checker, output_checker) {
+					check_generated_output(checker, output_checker.protocol, input, output.file.path())?;
+					check_stopped()?;
+				}
 
-				result.map_err(|error| ProgramError { error })?;
-				Ok(metrics)
+				output.save()
 			});
 		},
-		ActionType::SimpleCompare { output_directory, output_ext } => {
-			map_tests(inputs, progress_bar, &test_summary, |input| {
-				check_ctrlc()?;
+		ActionType::SimpleCompare { output_directory, output_ext, fallback_output_exts, diff_tool, comparator, .. } => {
+			map_tests(inputs, progress_bar, &test_summary, &config, cache.as_ref(), |input, metrics| {
+				check_stopped()?;
 
-				let (metrics, result) = test_to_temp(&runner, &input.input_source.get_file());
-				check_ctrlc()?;
+				let output_file_paths = input.output_files(output_directory, output_ext, fallback_output_exts);
+				let stderr_file = create_temp_file().expect("Failed to create memfile");
+				#[cfg(unix)]
+				let early_exit_output = (config.early_exit && diff_tool.is_none() && comparator.requires_same_tokens())
+					.then(|| early_exit_expected_output(&output_file_paths))
+					.flatten();
+				#[cfg(unix)]
+				let (run_metrics, result) = match early_exit_output {
+					Some(expected_output) => test_with_early_exit(&runner, input, &stderr_file, &expected_output),
+					None => test_to_temp(&runner, &input.input_source.get_file(), &stderr_file, &input.arguments, &input.working_directory, &input.limits),
+				};
+				#[cfg(not(unix))]
+				let (run_metrics, result) = test_to_temp(&runner, &input.input_source.get_file(), &stderr_file, &input.arguments, &input.working_directory, &input.limits);
+				*metrics = run_metrics;
+				if let Some(kept_files) = &kept_files {
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "stderr", &stderr_file));
+				}
+				if let Some(stderr_directory) = &stderr_directory {
+					warn_if_failed(stderr_directory.save(&input.test_name, &stderr_file));
+				}
+				check_stopped()?;
 
-				let result = result.map_err(|error| ProgramError { error })?;
-				let output_file_path = output_directory.join(format!("{}{}", input.test_name, output_ext));
-				compare_output(&output_file_path, result)?;
-				check_ctrlc()?;
+				let result = result.map_err(|error| program_error(input, error))?;
+				if let Some(kept_files) = &kept_files {
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "stdout", &result));
+				}
+				Ok((result, output_file_paths))
+			}, |input, (result, output_file_paths)| {
+				compare_output(&input.test_name, &output_file_paths, result, diff_tool.as_ref(), comparator.as_ref())?;
+				check_stopped()?;
 
-				Ok(metrics)
+				Ok(())
 			});
 		},
-		ActionType::Checker { .. } => {
+		ActionType::Checker { protocol: CheckerProtocol::Files, output_directory, output_ext, fallback_output_exts, .. } => {
+			let checker = checker.expect("Checker should be initialized");
+			map_tests(inputs, progress_bar, &test_summary, &config, cache.as_ref(), |input, metrics| {
+				check_stopped()?;
+
+				let output_file = create_named_temp_file().expect("Failed to create temporary file");
+				let stderr_file = create_temp_file().expect("Failed to create memfile");
+				let (run_metrics, result) = runner.test_to_file(
+					&input.input_source.get_file(),
+					output_file.as_file(),
+					&stderr_file,
+					&input.arguments,
+					&input.working_directory,
+					&input.limits,
+				);
+				*metrics = run_metrics;
+				if let Some(kept_files) = &kept_files {
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "stdout", output_file.as_file()));
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "stderr", &stderr_file));
+				}
+				if let Some(stderr_directory) = &stderr_directory {
+					warn_if_failed(stderr_directory.save(&input.test_name, &stderr_file));
+				}
+				check_stopped()?;
+
+				result.map_err(|error| program_error(input, error))?;
+				Ok(output_file)
+			}, |input, output_file| {
+				let expected_path = checker_expected_output(input, output_directory, output_ext, fallback_output_exts);
+				let decompressed_expected = decompress_expected_output(expected_path.as_deref())?;
+				checker.check_files(output_file.path(), &CheckedTest {
+					test_name: &input.test_name,
+					input_path: input.input_source.path(),
+					expected_path: decompressed_expected.as_ref().map(|file| file.path()).or(expected_path.as_deref()),
+					working_directory: &input.working_directory,
+				})?;
+				check_stopped()?;
+
+				Ok(())
+			})
+		}
+		ActionType::Checker { protocol: CheckerProtocol::Stdin, output_directory, output_ext, fallback_output_exts, .. } => {
 			let checker = checker.expect("Checker should be initialized");
-			map_tests(inputs, progress_bar, &test_summary, |input| {
-				check_ctrlc()?;
+			map_tests(inputs, progress_bar, &test_summary, &config, cache.as_ref(), |input, metrics| {
+				check_stopped()?;
 
 				let checker_input = Checker::prepare_checker_input(&input.input_source);
-				check_ctrlc()?;
+				check_stopped()?;
 
-				let (metrics, result) = runner.test_to_file(
+				let stderr_file = create_temp_file().expect("Failed to create memfile");
+				let (run_metrics, result) = runner.test_to_file(
 					&input.input_source.get_file(),
 					&checker_input,
+					&stderr_file,
+					&input.arguments,
+					&input.working_directory,
+					&input.limits,
 				);
-				check_ctrlc()?;
+				*metrics = run_metrics;
+				if let Some(kept_files) = &kept_files {
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "checker_input", &checker_input));
+					warn_if_failed(kept_files.save_test_file(&input.test_name, "stderr", &stderr_file));
+				}
+				if let Some(stderr_directory) = &stderr_directory {
+					warn_if_failed(stderr_directory.save(&input.test_name, &stderr_file));
+				}
+				check_stopped()?;
 
-				result.map_err(|error| ProgramError { error })?;
-				checker.check(checker_input)?;
-				check_ctrlc()?;
+				result.map_err(|error| program_error(input, error))?;
+				Ok(checker_input)
+			}, |input, checker_input| {
+				let expected_path = checker_expected_output(input, output_directory, output_ext, fallback_output_exts);
+				let decompressed_expected = decompress_expected_output(expected_path.as_deref())?;
+				checker.check(checker_input, &CheckedTest {
+					test_name: &input.test_name,
+					input_path: input.input_source.path(),
+					expected_path: decompressed_expected.as_ref().map(|file| file.path()).or(expected_path.as_deref()),
+					working_directory: &input.working_directory,
+				})?;
+				check_stopped()?;
 
-				Ok(metrics)
+				Ok(())
 			})
 		}
 	}
 
-	print_output(false, &mut test_summary.lock().expect("Failed to lock test summary mutex"));
+	let testing_end = Instant::now();
+	log(1, || format!(
+		"Time spent: {:.2}s preparing, {:.2}s compiling, {:.2}s testing",
+		(compilation_start - start_time).as_secs_f64(),
+		(testing_start - compilation_start).as_secs_f64(),
+		(testing_end - testing_start).as_secs_f64(),
+	));
+
+	let stopped_early = RECEIVED_CTRL_C.load(Acquire) || TOTAL_TIMEOUT_EXCEEDED.load(Acquire);
+	report(ProgressEvent::Finished { stopped_early });
+	let mut test_summary = test_summary.lock().expect("Failed to lock test summary mutex");
+	set_stage("printing the results");
+	print_output(stopped_early, &mut test_summary, &config.output);
+	if TOTAL_TIMEOUT_EXCEEDED.load(Acquire) && !RECEIVED_CTRL_C.load(Acquire) {
+		println!("{}", "Testing was stopped because the total timeout was exceeded".yellow());
+	}
+	if let Some(hits) = cache.as_ref().map(ResultCache::hits).filter(|hits| *hits > 0) {
+		println!("{}", format!("The results of {} unchanged tests were taken from the cache, use --no-cache to run them again", hits).bright_black());
+	}
+
+	let test_summary = test_summary.as_mut().expect("The test summary should be initialized");
+	if !config.generate_mode() {
+		let mut history = History::load();
+		let run = Run::from_summary(test_summary);
+		if let Some(last_run) = history.last_run(&config.source_path) {
+			println!("{}", last_run.compare(&run));
+		}
+		// Partial results would make the comparison with the next run misleading
+		if !stopped_early {
+			if let Some(run_file) = env::var_os(RUN_FILE_VARIABLE) {
+				run.save_to(Path::new(&run_file)).map_err(|error| FormattedError::from_str(&error))?;
+			}
+			history.add_run(&config.source_path, run);
+			history.save().map_err(|error| FormattedError::from_str(&error))?;
+		}
+	}
+
+	if config.gha {
+		annotate_failed_tests(&config.source_path, test_summary);
+	}
+	if config.copy_failed {
+		copy_failed_input(test_summary, &input_paths);
+	}
+	if let ActionType::SimpleCompare { diff_tool: Some(diff_tool), .. } = &config.action_type {
+		diff_tool.run_interactive();
+	}
+	#[cfg(target_os = "linux")]
+	if let Some(executable) = &recorded_executable {
+		if !stopped_early {
+			record_failed_tests(executable, &config.environment, &recorded_tests, test_summary);
+		}
+	}
+	if config.open_failed {
+		open_failed_tests(test_summary, &input_paths);
+	}
+	if let (Some(archive), Some(kept_files)) = (&config.archive, &kept_files) {
+		let duration = testing_end.duration_since(test_summary.start_time);
+		create_archive(archive, kept_files, test_summary, stopped_early, duration, &configuration, config.archive_passed).map_err(|error| FormattedError::from_str(&error))?;
+		println!("{}", format!("Saved the archive of the run to {}", archive.display()).green());
+	}
+
+	let baseline = Baseline::from_summary(test_summary);
+	if let Some((name, old_baseline)) = &config.compare_baseline {
+		println!("{}", old_baseline.compare(name, &baseline, config.regression_threshold));
+	}
+	if let Some(name) = &config.save_baseline {
+		if stopped_early {
+			println!("{}", format!("Testing was stopped early, not saving baseline \"{}\"", name).yellow());
+			return Ok(());
+		}
+		baseline.save(name).map_err(|error| FormattedError::from_str(&error))?;
+		println!("{}", format!("Saved the test runtimes as baseline \"{}\"", name).green());
+	}
 	Ok(())
 }
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use crate::limits::{MemoryLimit, TimeLimit};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    tests: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    name: String,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    time: Option<TimeLimit>,
+    memory: Option<MemoryLimit>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// A test listed in the manifest, with the paths resolved relative to the manifest's directory
+pub(crate) struct ManifestTest {
+    pub(crate) name: String,
+    pub(crate) input: PathBuf,
+    /// The expected output, which is looked for in the output directory if it isn't given
+    pub(crate) output: Option<PathBuf>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) memory_limit: Option<u64>,
+    pub(crate) tags: Vec<String>,
+}
+
+/// Checks that a test name can be used as a file name, since the working directory and output file of the test are named after it
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("The manifest contains a test with an empty name".to_string());
+    }
+    if name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(format!("The name of test \"{}\" can't contain path separators or be \".\" or \"..\"", name));
+    }
+    Ok(())
+}
+
+/// A file listing the tests explicitly instead of finding them in the input directory
+pub(crate) struct Manifest {
+    pub(crate) tests: Vec<ManifestTest>,
+}
+
+impl Manifest {
+    /// Reads a manifest in the following format:
+    /// ```toml
+    /// [[tests]]
+    /// name = "sample1"
+    /// input = "samples/1.in"
+    /// output = "samples/1.out"
+    /// time = "2.5s"
+    /// memory = "256M"
+    /// tags = ["samples"]
+    /// ```
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read the manifest: {}", error))?;
+        Self::parse(&contents, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Parses the contents of a manifest, resolving the paths relative to `directory`
+    fn parse(contents: &str, directory: &Path) -> Result<Self, String> {
+        let file: ManifestFile = toml::from_str(contents)
+            .map_err(|error| format!("The manifest is invalid:\n{}", error))?;
+
+        let mut names = HashSet::new();
+        let tests = file.tests.into_iter().map(|entry| {
+            validate_name(&entry.name)?;
+            if !names.insert(entry.name.clone()) {
+                return Err(format!("The manifest contains more than one test named \"{}\"", entry.name));
+            }
+            let input = directory.join(&entry.input);
+            if !input.is_file() {
+                return Err(format!("The input file of test {} does not exist: {}", entry.name, input.display()));
+            }
+            Ok(ManifestTest {
+                input,
+                output: entry.output.map(|output| directory.join(output)),
+                timeout: entry.time.map(|time| {
+                    time.to_duration()
+                        .map_err(|error| format!("The manifest contains an invalid time limit: {}", error))
+                }).transpose()?,
+                memory_limit: entry.memory.map(|memory| {
+                    memory.to_kibibytes()
+                        .map_err(|error| format!("The manifest contains an invalid memory limit: {}", error))
+                }).transpose()?,
+                tags: entry.tags,
+                name: entry.name,
+            })
+        }).collect::<Result<Vec<ManifestTest>, String>>()?;
+
+        if tests.is_empty() {
+            return Err("The manifest doesn't contain any tests".to_string());
+        }
+        Ok(Manifest { tests })
+    }
+
+    pub(crate) fn has_memory_limits(&self) -> bool {
+        self.tests.iter().any(|test| test.memory_limit.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{validate_name, Manifest};
+
+    #[test]
+    fn validates_test_names() {
+        assert!(validate_name("sample_1.2").is_ok());
+        for name in ["", ".", "..", "../a", "a/b", "a\\b", "/a"] {
+            assert!(validate_name(name).is_err(), "{:?} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn parses_manifests() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("1.in"), "1\n").unwrap();
+        let manifest = Manifest::parse(
+            "[[tests]]\nname = \"sample\"\ninput = \"1.in\"\noutput = \"1.out\"\ntime = \"2.5s\"\ntags = [\"samples\"]\n",
+            directory.path(),
+        ).unwrap();
+        let test = &manifest.tests[0];
+        assert_eq!(test.name, "sample");
+        assert_eq!(test.input, directory.path().join("1.in"));
+        assert_eq!(test.output, Some(directory.path().join("1.out")));
+        assert_eq!(test.timeout.map(|timeout| timeout.as_millis()), Some(2500));
+        assert_eq!(test.tags, vec!["samples"]);
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("1.in"), "1\n").unwrap();
+        let parse = |contents: &str| Manifest::parse(contents, directory.path()).err();
+        assert!(parse("[[tests]]\nname = \"../a\"\ninput = \"1.in\"\n").is_some());
+        assert!(parse("[[tests]]\nname = \"a\"\ninput = \"1.in\"\n[[tests]]\nname = \"a\"\ninput = \"1.in\"\n").is_some());
+        assert!(parse("[[tests]]\nname = \"a\"\ninput = \"2.in\"\n").is_some());
+        assert!(parse("[[tests]]\nname = \"a\"\ninput = \"1.in\"\nunknown = 1\n").is_some());
+        assert!(parse("").is_some());
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::{env, fs, io};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use colored::Colorize;
+use crate::generic_utils::strip_colors;
+use crate::temp_files::create_temp_dir;
+use crate::test_summary::TestSummary;
+
+/// Replaces the characters of the test name that can't safely be used in a file name
+fn file_name(test_name: &str) -> String {
+    test_name.chars()
+        .map(|character| if character.is_alphanumeric() || matches!(character, '-' | '_' | '.') { character } else { '_' })
+        .collect()
+}
+
+fn open_in_editor(paths: &[PathBuf]) -> io::Result<()> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or("vi".to_string());
+    let editor = shlex::split(&editor)
+        .filter(|editor| !editor.is_empty())
+        .ok_or_else(|| io::Error::other(format!("\"{}\" has unmatched quotes or is empty", editor)))?;
+    let status = Command::new(&editor[0])
+        .args(&editor[1..])
+        .args(paths)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("the editor exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Shows a menu of the failed tests after testing, opening the input and the error report
+/// of the chosen test in `$VISUAL` or `$EDITOR` until the user quits
+pub(crate) fn open_failed_tests(test_summary: &mut TestSummary, input_paths: &HashMap<String, PathBuf>) {
+    let errors = test_summary.get_errors();
+    if errors.is_empty() {
+        return;
+    }
+    if !io::stdin().is_terminal() {
+        println!("{}", "The failed tests can only be opened when toster is run in a terminal".yellow());
+        return;
+    }
+
+    let directory = create_temp_dir().expect("Failed to create temporary directory");
+    loop {
+        println!("{}", "Failed tests:".bold());
+        for (index, failed_test) in errors.iter().enumerate() {
+            println!("{}. {} ({})", index + 1, failed_test.test_name, failed_test.verdict);
+        }
+        print!("Choose the test to open (1-{}), or press Enter to quit: ", errors.len());
+        io::stdout().flush().expect("Failed to flush stdout");
+
+        let mut choice = String::new();
+        if io::stdin().read_line(&mut choice).is_err() || choice.trim().is_empty() {
+            return;
+        }
+        let Some(failed_test) = choice.trim().parse::<usize>().ok()
+            .and_then(|choice| choice.checked_sub(1))
+            .and_then(|index| errors.get(index)) else {
+            println!("{}", format!("\"{}\" is not a valid choice", choice.trim()).red());
+            continue;
+        };
+
+        let test_name = &failed_test.test_name;
+        let report_path = directory.path().join(format!("{}.result", file_name(test_name)));
+        fs::write(&report_path, strip_colors(&failed_test.error().to_string(test_name, &failed_test.metrics)) + "\n")
+            .expect("Failed to write the test's error report");
+        // Only the report is opened if the input file of the test isn't known
+        let paths: Vec<PathBuf> = input_paths.get(test_name).cloned().into_iter().chain([report_path]).collect();
+        if let Err(error) = open_in_editor(&paths) {
+            println!("{}", format!("Failed to open the editor: {}", error).red());
+            return;
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::{env, thread};
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::process::{exit, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering::Acquire;
+use std::time::Duration;
+use clap::ValueEnum;
+use colored::Colorize;
+use comfy_table::{Attribute, Cell, Color, Table};
+use comfy_table::ContentArrangement::Dynamic;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use terminal_size::Height;
+use crate::test_errors::Verdict;
+use crate::test_summary::{TestResult, TestSummary};
+use crate::{RECEIVED_CTRL_C, TOTAL_TIMEOUT_EXCEEDED};
+use crate::verbose::verbosity;
+
+/// The order of the tests in the table printed with --details
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum SortOrder {
+	Name,
+	Time,
+	Memory,
+}
+
+/// What is printed about every failed test as soon as it fails with --stream-errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum StreamErrors {
+	/// Only the name of the test and its verdict
+	Short,
+	/// The whole error, like it's printed after testing, including the diff of the outputs
+	Full,
+}
+
+/// When the output is colored
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum ColorMode {
+	/// Only when stdout is a terminal and toster isn't run in CI
+	Auto,
+	Always,
+	Never,
+}
+
+impl ColorMode {
+	/// Makes the colored crate, and through it the tables and the progress bar, follow the mode
+	pub(crate) fn apply(self) {
+		match self {
+			ColorMode::Auto if !is_interactive() => colored::control::set_override(false),
+			ColorMode::Auto => {}
+			ColorMode::Always => colored::control::set_override(true),
+			ColorMode::Never => colored::control::set_override(false),
+		}
+	}
+}
+
+/// Whether the output should be colored
+fn use_colors() -> bool {
+	colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+/// Creates a table, which is styled only if the output should be colored
+pub(crate) fn create_table() -> Table {
+	let mut table = Table::new();
+	if use_colors() {
+		table.enforce_styling();
+	} else {
+		table.force_no_tty();
+	}
+	table
+}
+
+/// Removes the color of the progress bar from its template if the output shouldn't be colored
+pub(crate) fn progress_template(template: &str) -> String {
+	if use_colors() {
+		template.to_string()
+	} else {
+		template.replace("{wide_bar:.cyan/blue}", "{wide_bar}")
+	}
+}
+
+/// Options controlling how the results are printed
+#[derive(Clone)]
+pub(crate) struct OutputConfig {
+	pub(crate) details: Option<SortOrder>,
+	pub(crate) max_errors: Option<usize>,
+	pub(crate) use_pager: bool,
+}
+
+/// How often the progress is printed when the progress bar isn't shown
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether stdout is a terminal and toster isn't run in CI, so that colors and the progress bar can be shown
+pub(crate) fn is_interactive() -> bool {
+	let in_ci = env::var("CI").is_ok_and(|ci| !ci.is_empty() && ci != "false");
+	io::stdout().is_terminal() && !in_ci
+}
+
+/// Creates the progress bar shown while testing.
+///
+/// When toster isn't run interactively, the progress is printed as a plain line every few seconds instead
+pub(crate) fn create_progress_bar(test_count: usize, style: ProgressStyle) -> ProgressBar {
+	let progress_bar = ProgressBar::new(test_count as u64).with_style(style);
+	if !is_interactive() {
+		progress_bar.set_draw_target(ProgressDrawTarget::hidden());
+		print_plain_progress(&progress_bar);
+		return progress_bar;
+	}
+	if verbosity() >= 2 {
+		// The progress bar would be drawn over the commands printed for every test
+		progress_bar.set_draw_target(ProgressDrawTarget::hidden());
+	}
+	progress_bar.enable_steady_tick(Duration::from_millis(100));
+	progress_bar
+}
+
+/// Prints the progress periodically until the progress bar is finished or dropped
+fn print_plain_progress(progress_bar: &ProgressBar) {
+	let progress_bar = progress_bar.downgrade();
+	thread::spawn(move || loop {
+		thread::sleep(PLAIN_PROGRESS_INTERVAL);
+		let Some(progress_bar) = progress_bar.upgrade().filter(|progress_bar| !progress_bar.is_finished()) else {
+			return;
+		};
+		println!(
+			"Tested {}/{} ({:.0}s elapsed)",
+			progress_bar.position(),
+			progress_bar.length().unwrap_or(0),
+			progress_bar.elapsed().as_secs_f64(),
+		);
+	});
+}
+
+pub(crate) fn create_progress_style(test_summary: Arc<Mutex<Option<TestSummary>>>) -> ProgressStyle {
+	let running_test_summary = test_summary.clone();
+	let finished_test_summary = test_summary.clone();
+	ProgressStyle::with_template(&progress_template("[{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})\n{counts} {ctrlc}\n{finished}\n{running}"))
+		.expect("Progress bar creation failed")
+		.with_key("eta", |state: &ProgressState, w: &mut dyn FmtWrite| write!(w, "{:.1}s", state.eta().as_secs_f64()).expect("Displaying the progress bar failed"))
+		.progress_chars("#>-")
+		.with_key("running", move |_state: &ProgressState, w: &mut dyn FmtWrite| {
+			let running = running_test_summary.lock().expect("Failed to lock test summary mutex").as_ref().unwrap().format_running(5);
+			if !running.is_empty() {
+				write!(w, "{}", format!("Running: {}", running).bright_black()).expect("Displaying the progress bar failed")
+			}
+		})
+		.with_key("finished", move |_state: &ProgressState, w: &mut dyn FmtWrite| {
+			let finished = finished_test_summary.lock().expect("Failed to lock test summary mutex").as_ref().unwrap().format_finished(5);
+			if !finished.is_empty() {
+				write!(w, "{}{}", "Finished: ".bright_black(), finished).expect("Displaying the progress bar failed")
+			}
+		})
+		.with_key("counts", move |_state: &ProgressState, w: &mut dyn FmtWrite| {
+			write!(w, "{}", test_summary.lock().expect("Failed to lock test summary mutex").as_ref().unwrap().format_counts(false)).expect("Displaying the progress bar failed")
+		})
+		.with_key("ctrlc", |_state: &ProgressState, w: &mut dyn FmtWrite| {
+			let message = if RECEIVED_CTRL_C.load(Acquire) {
+				"(Waiting for the running tests to finish, press Ctrl+C again to stop immediately)"
+			} else if TOTAL_TIMEOUT_EXCEEDED.load(Acquire) {
+				"(The total timeout was exceeded, waiting for the running tests to finish)"
+			} else {
+				"(Press Ctrl+C to stop testing and print current results)"
+			};
+			write!(w, "{}", message.bright_black()).expect("Displaying the progress bar Ctrl+C message failed")
+		})
+}
+
+fn print_details(test_summary: &TestSummary, sort_order: SortOrder) {
+	let mut results: Vec<&TestResult> = test_summary.test_results.iter().collect();
+	results.sort_by(|a, b| human_sort::compare(&a.test_name, &b.test_name));
+	match sort_order {
+		SortOrder::Name => {}
+		SortOrder::Time => results.sort_by_key(|result| Reverse(result.time)),
+		SortOrder::Memory => results.sort_by_key(|result| Reverse(result.memory_kibibytes)),
+	}
+
+	let show_cpu_time = results.iter().any(|result| result.cpu_time.is_some());
+	let show_memory = results.iter().any(|result| result.memory_kibibytes.is_some());
+	let mut header = vec![
+		Cell::new("Test").add_attribute(Attribute::Bold),
+		Cell::new("Verdict").add_attribute(Attribute::Bold),
+		Cell::new("Time").add_attribute(Attribute::Bold),
+	];
+	if show_cpu_time {
+		header.push(Cell::new("CPU time").add_attribute(Attribute::Bold));
+	}
+	if show_memory {
+		header.push(Cell::new("Memory").add_attribute(Attribute::Bold));
+	}
+
+	let mut table = create_table();
+	table.set_content_arrangement(Dynamic).set_header(header);
+	for result in results {
+		let mut row = vec![
+			Cell::new(&result.test_name),
+			Cell::new(result.verdict).fg(if result.verdict.is_success() { Color::Green } else { Color::Red }),
+			Cell::new(result.time.map(|time| format!("{:.3}s", time.as_secs_f64())).unwrap_or("-".to_string())),
+		];
+		if show_cpu_time {
+			row.push(Cell::new(result.cpu_time.map(|time| format!("{:.3}s", time.as_secs_f64())).unwrap_or("-".to_string())));
+		}
+		if show_memory {
+			row.push(Cell::new(result.memory_kibibytes.map(|memory| format!("{}KiB", memory)).unwrap_or("-".to_string())));
+		}
+		table.add_row(row);
+	}
+
+	println!("{}", table.to_string().replace('\r', ""));
+}
+
+/// Formats the verdict and metrics of a single test, printed as soon as it finishes with --sequential
+pub(crate) fn format_test_result(result: &TestResult) -> String {
+	let metrics: Vec<String> = [
+		result.time.map(|time| format!("{:.3}s", time.as_secs_f64())),
+		result.cpu_time.map(|cpu_time| format!("{:.3}s CPU", cpu_time.as_secs_f64())),
+		result.memory_kibibytes.map(|memory| format!("{}KiB", memory)),
+	].into_iter().flatten().collect();
+
+	format!(
+		"{}: {}{}",
+		result.test_name,
+		if result.verdict.is_success() { result.verdict.to_string().green() } else { result.verdict.to_string().red() },
+		if metrics.is_empty() { String::new() } else { format!(" ({})", metrics.join(", ")).bright_black().to_string() },
+	)
+}
+
+pub(crate) fn print_output(stopped_early: bool, test_summary: &mut Option<TestSummary>, output_config: &OutputConfig) {
+	let Some(test_summary) = test_summary else {
+		println!("{}", "Toster was stopped before testing could start".red());
+		exit(0);
+	};
+
+	if stopped_early {
+		println!();
+	}
+
+	let additional_info = match (&test_summary.slowest_test, &test_summary.most_memory_used) {
+		(None, None) => "".to_string(),
+		(Some((duration, slowest_test_name)), None) => format!(
+			" (Slowest test: {} at {:.3}s)",
+			slowest_test_name, duration.as_secs_f32(),
+		),
+		(None, Some((memory, most_memory_test_name))) => format!(
+			" (Most memory used: {} at {:.3}KiB)",
+			most_memory_test_name, memory,
+		),
+		(Some((duration, slowest_test_name)), Some((memory, most_memory_test_name))) => format!(
+			" (Slowest test: {} at {:.3}s, most memory used: {} at {}KiB)",
+			slowest_test_name, duration.as_secs_f32(),
+			most_memory_test_name, memory,
+		),
+	};
+
+	println!(
+		"{} {} {:.2}s{}\nResults: {}",
+        if test_summary.generate_mode { "Generating" } else { "Testing" },
+        if stopped_early {"stopped after"} else {"finished in"},
+        test_summary.start_time.elapsed().as_secs_f64(),
+        additional_info,
+        test_summary.format_counts(true),
+	);
+
+	if let Some(statistics) = test_summary.runtime_statistics() {
+		println!(
+			"Runtime: mean {:.3}s, median {:.3}s, 95th percentile {:.3}s, total wall time {:.3}s{}",
+			statistics.mean.as_secs_f64(),
+			statistics.median.as_secs_f64(),
+			statistics.percentile_95.as_secs_f64(),
+			statistics.total.as_secs_f64(),
+			statistics.total_cpu_time.map(|total| format!(", total CPU time {:.3}s", total.as_secs_f64())).unwrap_or_default(),
+		);
+	}
+	if let Some(sparkline) = format_runtime_sparkline(test_summary) {
+		println!("{}", sparkline);
+	}
+
+	if let Some(sort_order) = output_config.details {
+		print_details(test_summary, sort_order);
+	}
+
+	if let Some(report) = format_error_report(test_summary, output_config.max_errors) {
+		println!("Errors were found in the following tests:");
+		print_paged(&report, output_config.use_pager);
+	}
+}
+
+/// The maximum number of characters in the runtime sparkline, longer ones are made by merging neighbouring tests
+const MAX_SPARKLINE_WIDTH: usize = 60;
+
+/// Formats a sparkline of the runtimes of the tests ordered by their names,
+/// which shows whether the runtime grows with the test size or some tests are outliers
+fn format_runtime_sparkline(test_summary: &TestSummary) -> Option<String> {
+	const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+	let mut times: Vec<(&str, f64)> = test_summary.test_results.iter()
+		.filter_map(|result| result.time.map(|time| (result.test_name.as_str(), time.as_secs_f64())))
+		.collect();
+	if times.len() < 2 {
+		return None;
+	}
+	times.sort_by(|a, b| human_sort::compare(a.0, b.0));
+
+	// Every character shows the slowest of the tests it covers
+	let chunk_size = times.len().div_ceil(MAX_SPARKLINE_WIDTH);
+	let values: Vec<f64> = times.chunks(chunk_size)
+		.map(|chunk| chunk.iter().map(|(_, time)| *time).fold(0.0, f64::max))
+		.collect();
+	let max = values.iter().copied().fold(0.0, f64::max);
+	let sparkline: String = values.iter()
+		.map(|value| if max == 0.0 { BARS[0] } else { BARS[((value / max) * (BARS.len() - 1) as f64).round() as usize] })
+		.collect();
+
+	Some(format!(
+		"Runtimes by test: {} {}",
+		sparkline.cyan(),
+		format!("({} to {}, slowest {:.3}s)", times[0].0, times[times.len() - 1].0, max).bright_black(),
+	))
+}
+
+fn format_error_report(test_summary: &mut TestSummary, max_errors: Option<usize>) -> Option<String> {
+	let incorrect_results = test_summary.get_errors();
+	if incorrect_results.is_empty() {
+		return None;
+	}
+
+	// Tests that failed in exactly the same way are listed together.
+	// The errors are hashed, so that their descriptions don't all have to be rendered and kept in memory at once
+	let mut groups: Vec<Vec<usize>> = vec![];
+	let mut group_indices: HashMap<(Verdict, u64), usize> = HashMap::new();
+	for (index, failed_test) in incorrect_results.iter().enumerate() {
+		let mut hasher = DefaultHasher::new();
+		failed_test.error().hash(&mut hasher);
+		let group_index = *group_indices.entry((failed_test.verdict, hasher.finish())).or_insert_with(|| {
+			groups.push(vec![]);
+			groups.len() - 1
+		});
+		groups[group_index].push(index);
+	}
+
+	let shown_count = max_errors.unwrap_or(usize::MAX).min(groups.len());
+	let mut entries: Vec<String> = groups.iter().take(shown_count).map(|indices| {
+		if let [index] = indices[..] {
+			let failed_test = &incorrect_results[index];
+			failed_test.error().to_string(&failed_test.test_name, &failed_test.metrics)
+		} else {
+			let test_names: Vec<&str> = indices.iter().map(|index| incorrect_results[*index].test_name.as_str()).collect();
+			incorrect_results[indices[0]].error().to_grouped_string(&test_names)
+		}
+	}).collect();
+	if shown_count < groups.len() {
+		entries.push(format!(
+			"...and {} more (use --max-errors to show more)",
+			groups.len() - shown_count,
+		).yellow().to_string());
+	}
+
+	Some(entries.join("\n"))
+}
+
+/// Prints the text through `$PAGER` (or `less -R`) if it doesn't fit on the screen,
+/// falling back to printing it directly
+fn print_paged(text: &str, use_pager: bool) {
+	let fits_on_screen = match terminal_size::terminal_size() {
+		Some((_, Height(height))) => text.lines().count() < height as usize,
+		None => true,
+	};
+	if !use_pager || fits_on_screen || !io::stdout().is_terminal() || try_paging(text).is_err() {
+		println!("{}", text);
+	}
+}
+
+fn try_paging(text: &str) -> io::Result<()> {
+	let pager = env::var("PAGER").unwrap_or("less -R".to_string());
+	let mut pager = pager.split_whitespace();
+	let mut child = Command::new(pager.next().ok_or(io::ErrorKind::NotFound)?)
+		.args(pager)
+		.stdin(Stdio::piped())
+		.spawn()?;
+
+	// The pager can be closed before reading the whole text, which isn't an error
+	let _ = child.stdin.take().expect("The pager's stdin should be piped").write_all(text.as_bytes());
+	child.wait()?;
+	Ok(())
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::{Command, Stdio};
+use std::time::{Duration, UNIX_EPOCH};
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use wait_timeout::ChildExt;
+use which::which;
+use crate::generic_utils::DATA_DIRECTORY;
+
+/// Removes the source file and executable arguments from a split compile command,
+/// leaving the compiler and the flags that the precompiled header has to be built with
+fn header_flags(compile_args: &[String]) -> Vec<String> {
+    let mut flags: Vec<String> = vec![];
+    for arg in compile_args {
+        if arg.contains("<OUT>") {
+            if flags.last().is_some_and(|last| last == "-o") {
+                flags.pop();
+            }
+        } else if !arg.contains("<IN>") {
+            flags.push(arg.clone());
+        }
+    }
+    flags
+}
+
+/// Builds a precompiled bits/stdc++.h with the flags of the compile command in `.toster/pch`,
+/// or reuses the one built earlier for the same flags and compiler.
+///
+/// Returns the directory which has to be added to the include path for the compiler to use the header
+pub(crate) fn prepare_precompiled_header(compile_args: &[String], timeout: Duration) -> Result<PathBuf, String> {
+    let flags = header_flags(compile_args);
+    let compiler = which(&flags[0]).map_err(|error| format!("The compiler was not found: {}", error))?;
+    // The header has to be rebuilt when the compiler is updated
+    let compiler_modified = fs::metadata(&compiler)
+        .and_then(|metadata| metadata.modified())
+        .map_or(0, |modified| modified.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos()));
+
+    let mut hasher = Sha256::new();
+    hasher.update(flags.join("\0"));
+    hasher.update(compiler_modified.to_le_bytes());
+    let key: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    let directory = PathBuf::from(DATA_DIRECTORY).join("pch").join(key);
+    let header_path = directory.join("bits").join("stdc++.h.gch");
+    if header_path.is_file() {
+        return Ok(directory);
+    }
+
+    println!("{}", "Precompiling bits/stdc++.h, this is only done once for every compile command".bright_black());
+    fs::create_dir_all(directory.join("bits"))
+        .map_err(|error| format!("Failed to create the precompiled header directory: {}", error))?;
+    // The header is built under a name unique to this process and then renamed, so that a partially
+    // written header is never used, even if another toster is building the same header at the same time
+    let source_path = directory.join(format!("pch.{}.h", process::id()));
+    let temp_path = directory.join("bits").join(format!("stdc++.h.gch.{}.tmp", process::id()));
+    fs::write(&source_path, "#include <bits/stdc++.h>\n")
+        .map_err(|error| format!("Failed to write the precompiled header source: {}", error))?;
+
+    let result = build_header(&flags, &source_path, &temp_path, timeout)
+        .and_then(|_| fs::rename(&temp_path, &header_path)
+            .map_err(|error| format!("Failed to save the precompiled header: {}", error)));
+    let _ = fs::remove_file(&source_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.map(|_| directory)
+}
+
+fn build_header(flags: &[String], source_path: &Path, output_path: &Path, timeout: Duration) -> Result<(), String> {
+    let mut child = Command::new(&flags[0])
+        .args(&flags[1..])
+        .args(["-x", "c++-header"])
+        .arg(source_path)
+        .arg("-o")
+        .arg(output_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| format!("Failed to run the compiler: {}", error))?;
+    match child.wait_timeout(timeout).map_err(|error| format!("Failed to wait for the compiler: {}", error))? {
+        Some(status) if status.success() => Ok(()),
+        Some(_) => Err("The compiler failed to build it".to_string()),
+        None => {
+            // The compiler might have exited since the timeout, which isn't an error
+            let _ = child.kill();
+            let _ = child.wait();
+            Err("Building it timed out".to_string())
+        }
+    }
+}
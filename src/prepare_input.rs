@@ -1,24 +1,130 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::fs::{File, read_dir};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use glob::Pattern;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator};
 use rayon::vec::IntoIter;
+use tempfile::NamedTempFile;
+use crate::compression::{decompress_to_named_file, open_decompressed, strip_compression_ext};
+use crate::executor::ExecutionLimits;
 use crate::formatted_error::FormattedError;
+use crate::limits::TestLimits;
+use crate::manifest::Manifest;
+use crate::testing_utils::{find_output_files, HASH_FILE_EXT};
 
+#[derive(Clone)]
 pub(crate) enum TestInputSource {
-    File(PathBuf)
+    File(PathBuf),
+    /// A compressed input file that was decompressed into a temporary file, see [`Test::decompress_input`]
+    Decompressed {
+        original: PathBuf,
+        file: Arc<NamedTempFile>,
+    },
 }
 
 impl TestInputSource {
     pub(crate) fn get_file(&self) -> File {
         match self {
-            TestInputSource::File(path) => { File::open(path).expect("Failed to open input file") },
+            TestInputSource::File(path) => open_decompressed(path).expect("Failed to open input file"),
+            TestInputSource::Decompressed { file, .. } => file.reopen().expect("Failed to open the decompressed input file"),
+        }
+    }
+
+    /// The path of the input file, which is the decompressed file once the input was decompressed
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            TestInputSource::File(path) => path,
+            TestInputSource::Decompressed { file, .. } => file.path(),
+        }
+    }
+
+    /// The path of the input file in the input directory, which can be compressed
+    pub(crate) fn original_path(&self) -> &Path {
+        match self {
+            TestInputSource::File(path) | TestInputSource::Decompressed { original: path, .. } => path,
         }
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct Test {
     pub(crate) test_name: String,
     pub(crate) input_source: TestInputSource,
+    pub(crate) limits: ExecutionLimits,
+    /// The scratch directory the test is run in, created right before running it and removed afterwards
+    pub(crate) working_directory: PathBuf,
+    /// The command line arguments passed to the tested program
+    pub(crate) arguments: Vec<String>,
+    /// The expected output given in the manifest, used instead of looking for the output file in the output directory
+    pub(crate) expected_output: Option<PathBuf>,
+    pub(crate) tags: Vec<String>,
+}
+
+/// Selects the tests to run by their tags
+pub(crate) struct TagFilter {
+    /// If it isn't empty, only the tests with any of these tags are run
+    pub(crate) only: Vec<String>,
+    pub(crate) skip: Vec<String>,
+}
+
+impl TagFilter {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty()
+    }
+
+    fn matches(&self, test: &Test) -> bool {
+        (self.only.is_empty() || test.tags.iter().any(|tag| self.only.contains(tag)))
+            && !test.tags.iter().any(|tag| self.skip.contains(tag))
+    }
+
+    /// Removes the tests that don't match the filter, failing if none of them do
+    pub(crate) fn apply(&self, tests: &mut Vec<Test>) -> Result<(), FormattedError> {
+        tests.retain(|test| self.matches(test));
+        if tests.is_empty() {
+            return Err(FormattedError::from_str("None of the tests match the provided tags"));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the tag following from the name of a test, which is the part before the first underscore (e.g. "sample" for "sample_1")
+fn name_tag(test_name: &str) -> Option<String> {
+    test_name.split_once('_')
+        .map(|(tag, _)| tag.to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+impl Test {
+    /// Decompresses the input of the test into a temporary file if it's compressed,
+    /// so that it's only decompressed once no matter how many times it's read
+    pub(crate) fn decompress_input(&mut self) -> Result<(), String> {
+        let TestInputSource::File(path) = &self.input_source else {
+            return Ok(());
+        };
+        if let Some(file) = decompress_to_named_file(path)? {
+            self.input_source = TestInputSource::Decompressed { original: path.clone(), file: Arc::new(file) };
+        }
+        Ok(())
+    }
+
+    /// Returns the paths of the output files accepted for the test, see [`find_output_files`]
+    pub(crate) fn output_files(&self, output_directory: &Path, output_ext: &str, fallback_exts: &[String]) -> Vec<PathBuf> {
+        match &self.expected_output {
+            Some(path) => vec![path.clone()],
+            None => find_output_files(output_directory, &self.test_name, output_ext, fallback_exts),
+        }
+    }
+
+    /// Returns the path the output of the test is saved to when generating outputs
+    pub(crate) fn generated_output_path(&self, output_directory: &Path, output_ext: &str) -> PathBuf {
+        match &self.expected_output {
+            Some(path) => path.clone(),
+            None => output_directory.join(format!("{}{}", self.test_name, output_ext)),
+        }
+    }
 }
 
 pub(crate) struct TestingInputs<T: IndexedParallelIterator<Item = Test>> {
@@ -26,32 +132,333 @@ pub(crate) struct TestingInputs<T: IndexedParallelIterator<Item = Test>> {
     pub(crate) iterator: T,
 }
 
-pub(crate) fn prepare_file_inputs(input_dir: &Path, in_ext: &str) -> Result<TestingInputs<IntoIter<Test>>, FormattedError> {
+impl TestingInputs<IntoIter<Test>> {
+    pub(crate) fn from_tests(tests: Vec<Test>) -> Self {
+        TestingInputs { test_count: tests.len(), iterator: tests.into_par_iter() }
+    }
+}
+
+/// Returns the command line arguments of a test, read from its `.args` file in the input directory if there is one and it exists,
+/// or otherwise made from the template by replacing `<TEST>` with the test name. The arguments are split like in a shell
+pub(crate) fn test_arguments(input_dir: Option<&Path>, test_name: &str, template: Option<&str>) -> Result<Vec<String>, FormattedError> {
+    let arguments_path = input_dir.map(|input_dir| input_dir.join(format!("{}.args", test_name)));
+    let arguments = if let Some(arguments_path) = arguments_path.filter(|path| path.is_file()) {
+        fs::read_to_string(&arguments_path).map_err(|error| FormattedError::from_str(
+            &format!("Failed to read the arguments file {}: {}", arguments_path.display(), error)
+        ))?
+    } else {
+        template.unwrap_or("").replace("<TEST>", test_name)
+    };
+
+    shlex::split(&arguments).ok_or_else(|| FormattedError::from_str(
+        &format!("The arguments of test {} contain an unclosed quote or a trailing backslash", test_name)
+    ))
+}
+
+/// Replaces the inputs of the tests with copies without CRLF line endings and a byte order mark,
+/// saved in `directory`
+pub(crate) fn normalize_inputs(tests: &mut [Test], directory: &Path) -> Result<(), FormattedError> {
+    fs::create_dir_all(directory).expect("Failed to create the normalized input directory");
+    for test in tests {
+        let mut contents = vec![];
+        open_decompressed(test.input_source.original_path())
+            .and_then(|mut file| file.read_to_end(&mut contents).map_err(|error| error.to_string()))
+            .map_err(|error| FormattedError::from_str(&format!("Failed to read the input of test {}: {}", test.test_name, error)))?;
+        let contents = contents.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&contents);
+        let mut normalized = Vec::with_capacity(contents.len());
+        for (index, byte) in contents.iter().enumerate() {
+            if *byte != b'\r' || contents.get(index + 1) != Some(&b'\n') {
+                normalized.push(*byte);
+            }
+        }
+
+        let normalized_path = directory.join(&test.test_name);
+        fs::write(&normalized_path, normalized).expect("Failed to write the normalized input file");
+        test.input_source = TestInputSource::File(normalized_path);
+    }
+    Ok(())
+}
+
+pub(crate) fn prepare_file_inputs(input_dir: &Path, pattern: &Pattern, exclude: &[Pattern], test_limits: &TestLimits, working_directories: &Path, arguments_template: Option<&str>) -> Result<Vec<Test>, FormattedError> {
     let tests: Vec<Test> = read_dir(input_dir)
         .expect("Cannot open input directory")
         .map(|input| {
             input.expect("Failed to read contents of input directory").path()
         })
         .filter(|path| {
-            return match path.extension() {
-                None => false,
-                Some(ext) => ".".to_owned() + ext.to_str().unwrap_or("") == in_ext
+            // Compressed inputs are matched like their decompressed versions
+            let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()).map(strip_compression_ext) else {
+                return false;
             };
+            path.is_file()
+                && pattern.matches(file_name)
+                && !exclude.iter().any(|exclude| exclude.matches(file_name))
         })
         .map(|file_path| {
-            let test_name = file_path.file_stem().unwrap_or_else(|| panic!("The input file {} is invalid", file_path.display())).to_str().unwrap_or_else(|| panic!("The input file {} is invalid", file_path.display())).to_string();
-            Test {
+            let file_name = file_path.file_name().and_then(|file_name| file_name.to_str()).expect("The file name was checked to be valid");
+            let test_name = input_test_name(file_name).unwrap_or_else(|| panic!("The input file {} is invalid", file_path.display())).to_string();
+            Ok(Test {
+                limits: test_limits.for_test(&test_name),
+                working_directory: working_directories.join(&test_name),
+                arguments: test_arguments(Some(input_dir), &test_name, arguments_template)?,
+                tags: name_tag(&test_name).into_iter().collect(),
                 test_name,
-                input_source: TestInputSource::File(file_path)
+                input_source: TestInputSource::File(file_path),
+                expected_output: None,
+            })
+        })
+        .collect::<Result<Vec<Test>, FormattedError>>()?;
+
+    if tests.is_empty() {
+        return Err(FormattedError::from_str("There are no files in the input directory matching the provided file extension or pattern"));
+    }
+    if let Some((first, second)) = find_duplicate_input(tests.iter().map(|test| (test.test_name.as_str(), test.input_source.original_path()))) {
+        return Err(FormattedError::from_str(&format!(
+            "The input files {} and {} belong to the same test, remove one of them",
+            first.display(),
+            second.display(),
+        )));
+    }
+
+    Ok(tests)
+}
+
+/// Finds two input files with the same test name, like `test1.in` and `test1.in.gz`, given the test names and input paths
+fn find_duplicate_input<'a>(inputs: impl IntoIterator<Item = (&'a str, &'a Path)>) -> Option<(&'a Path, &'a Path)> {
+    let mut paths: HashMap<&str, &Path> = HashMap::new();
+    inputs.into_iter().find_map(|(test_name, path)| {
+        paths.insert(test_name, path).map(|other_path| (other_path.min(path), other_path.max(path)))
+    })
+}
+
+/// Splits the contents of a multitest file into the inputs of the tests, skipping the empty ones
+fn split_multitest(contents: &str, separator: &str) -> Vec<String> {
+    let mut inputs = vec![String::new()];
+    for line in contents.lines() {
+        if line.trim_end() == separator {
+            inputs.push(String::new());
+        } else {
+            let input = inputs.last_mut().expect("There is always at least one input");
+            input.push_str(line);
+            input.push('\n');
+        }
+    }
+    inputs.retain(|input| !input.trim().is_empty());
+    inputs
+}
+
+/// Splits the multitest file into tests named 1, 2, 3 and so on, whose inputs are saved in `directory`
+/// (e.g. as `1.in`). The tests are separated by lines consisting of `separator`, and empty tests are skipped
+pub(crate) fn prepare_multitest_inputs(path: &Path, separator: &str, directory: &Path, test_limits: &TestLimits, working_directories: &Path, arguments_template: Option<&str>) -> Result<Vec<Test>, FormattedError> {
+    let mut contents = String::new();
+    open_decompressed(path).and_then(|mut file| file.read_to_string(&mut contents).map_err(|error| error.to_string()))
+        .map_err(|error| FormattedError::from_str(&format!("Failed to read the multitest file: {}", error)))?;
+
+    let inputs = split_multitest(&contents, separator);
+    if inputs.is_empty() {
+        return Err(FormattedError::from_str("The multitest file doesn't contain any tests"));
+    }
+
+    fs::create_dir_all(directory).expect("Failed to create the multitest input directory");
+    inputs.into_iter().enumerate().map(|(index, input)| {
+        let test_name = (index + 1).to_string();
+        let input_path = directory.join(format!("{}.in", test_name));
+        fs::write(&input_path, input).expect("Failed to write the multitest input file");
+        Ok(Test {
+            limits: test_limits.for_test(&test_name),
+            working_directory: working_directories.join(&test_name),
+            arguments: test_arguments(Some(directory), &test_name, arguments_template)?,
+            test_name,
+            input_source: TestInputSource::File(input_path),
+            expected_output: None,
+            tags: vec![],
+        })
+    }).collect()
+}
+
+/// Creates the tests listed in the manifest. The limits given in the manifest take precedence over the ones from the limits file
+pub(crate) fn prepare_manifest_inputs(manifest: &Manifest, test_limits: &TestLimits, working_directories: &Path, arguments_template: Option<&str>) -> Result<Vec<Test>, FormattedError> {
+    manifest.tests.iter()
+        .map(|test| {
+            let mut limits = test_limits.for_test(&test.name);
+            if let Some(timeout) = test.timeout {
+                limits.timeout = timeout;
+            }
+            if let Some(memory_limit) = test.memory_limit {
+                limits.memory_limit = Some(memory_limit);
             }
+            Ok(Test {
+                limits,
+                working_directory: working_directories.join(&test.name),
+                arguments: test_arguments(Some(test.input.parent().unwrap_or(Path::new("."))), &test.name, arguments_template)?,
+                test_name: test.name.clone(),
+                input_source: TestInputSource::File(test.input.clone()),
+                expected_output: test.output.clone(),
+                tags: test.tags.iter().cloned().chain(name_tag(&test.name)).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Tests that don't have an output file and output files that don't have a matching test
+pub(crate) struct UnmatchedFiles {
+    pub(crate) missing_outputs: Vec<String>,
+    pub(crate) orphaned_outputs: Vec<String>,
+}
+
+impl UnmatchedFiles {
+    const MAX_LISTED: usize = 10;
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.missing_outputs.is_empty() && self.orphaned_outputs.is_empty()
+    }
+
+    fn format_list(names: &[String]) -> String {
+        let mut result = names.iter().take(Self::MAX_LISTED).cloned().collect::<Vec<String>>().join(", ");
+        if names.len() > Self::MAX_LISTED {
+            result.push_str(&format!(" and {} more", names.len() - Self::MAX_LISTED));
+        }
+        result
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let mut lines = vec![];
+        if !self.missing_outputs.is_empty() {
+            lines.push(format!(
+                "{} {} no output file: {}",
+                self.missing_outputs.len(),
+                if self.missing_outputs.len() == 1 { "test has" } else { "tests have" },
+                Self::format_list(&self.missing_outputs),
+            ));
+        }
+        if !self.orphaned_outputs.is_empty() {
+            lines.push(format!(
+                "{} output {} no matching input file: {}",
+                self.orphaned_outputs.len(),
+                if self.orphaned_outputs.len() == 1 { "file has" } else { "files have" },
+                Self::format_list(&self.orphaned_outputs),
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+fn file_names(directory: &Path) -> impl Iterator<Item = String> {
+    read_dir(directory)
+        .expect("Cannot open directory")
+        .map(|entry| {
+            entry.expect("Failed to read contents of directory").file_name()
+        })
+        .filter_map(|file_name| file_name.to_str().map(|file_name| file_name.to_string()))
+}
+
+/// Returns the name of the test an input file belongs to, which is its name without the extension
+/// (and without the compression extension, e.g. `test1` for `test1.in.gz`)
+fn input_test_name(file_name: &str) -> Option<&str> {
+    Path::new(strip_compression_ext(file_name)).file_stem()?.to_str()
+}
+
+/// Returns the name of the test an output file with `out_ext` belongs to, counting the alternative outputs
+/// (`test1.out.2`), hash files (`test1.out.sha256`) and compressed outputs (`test1.out.gz`) as well
+fn output_test_name<'a>(file_name: &'a str, out_ext: &str) -> Option<&'a str> {
+    let is_alternative = |suffix: &str| suffix.bytes().all(|byte| byte.is_ascii_digit()) && suffix.parse::<u32>().is_ok_and(|number| number >= 2);
+    let main_output = match file_name.rsplit_once('.') {
+        Some((main_output, suffix)) if is_alternative(suffix) => main_output,
+        _ => file_name.strip_suffix(HASH_FILE_EXT).unwrap_or_else(|| strip_compression_ext(file_name)),
+    };
+    main_output.strip_suffix(out_ext)
+}
+
+/// Finds the output files without an input file of the same test in the input directory, sorted by their names
+pub(crate) fn find_orphaned_outputs(input_dir: &Path, output_dir: &Path, out_exts: &[&str]) -> Vec<String> {
+    let input_names: HashSet<String> = file_names(input_dir)
+        .filter(|file_name| !out_exts.iter().any(|ext| output_test_name(file_name, ext).is_some()))
+        .filter_map(|file_name| input_test_name(&file_name).map(|test_name| test_name.to_string()))
+        .collect();
+    let mut orphaned_outputs: Vec<String> = file_names(output_dir)
+        .filter(|file_name| {
+            out_exts.iter()
+                .find_map(|ext| output_test_name(file_name, ext))
+                .is_some_and(|test_name| !input_names.contains(test_name))
         })
         .collect();
 
-    if tests.is_empty() {
-        return Err(FormattedError::from_str("There are no files in the input directory with the provided file extension"));
+    orphaned_outputs.sort_by(|a, b| human_sort::compare(a, b));
+    orphaned_outputs
+}
+
+/// Finds tests without an output file and output files without any file with the same name
+/// (excluding the extension) in the input directory. The output files are only checked if there is an input directory.
+///
+/// Input files that were excluded from testing still count as matching inputs
+pub(crate) fn find_unmatched_files(tests: &[Test], input_dir: Option<&Path>, output_dir: &Path, out_ext: &str, fallback_exts: &[String]) -> UnmatchedFiles {
+    let out_exts: Vec<&str> = [out_ext].into_iter().chain(fallback_exts.iter().map(|ext| ext.as_str())).collect();
+    let mut missing_outputs: Vec<String> = tests.iter()
+        .filter(|test| !test.output_files(output_dir, out_ext, fallback_exts).iter().any(|path| path.is_file()))
+        .map(|test| test.test_name.clone())
+        .collect();
+    missing_outputs.sort_by(|a, b| human_sort::compare(a, b));
+    let orphaned_outputs = match input_dir {
+        Some(input_dir) => find_orphaned_outputs(input_dir, output_dir, &out_exts),
+        None => vec![],
+    };
+    UnmatchedFiles { missing_outputs, orphaned_outputs }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::fs;
+    use super::{find_duplicate_input, find_orphaned_outputs, output_test_name, split_multitest, test_arguments};
+
+    #[test]
+    fn splits_multitest_files() {
+        let contents = "1 2\n===\n3 4\n5\n===  \n\n===\n6\n";
+        assert_eq!(split_multitest(contents, "==="), vec!["1 2\n", "3 4\n5\n", "6\n"]);
     }
 
-    let test_count = tests.len();
+    #[test]
+    fn keeps_lines_only_containing_the_separator() {
+        assert_eq!(split_multitest("a===\n=== b\n", "==="), vec!["a===\n=== b\n"]);
+        assert!(split_multitest("===\n===\n", "===").is_empty());
+    }
+
+    #[test]
+    fn finds_duplicate_inputs() {
+        let inputs = [("a", Path::new("in/a.in.gz")), ("b", Path::new("in/b.in")), ("a", Path::new("in/a.in"))];
+        assert_eq!(find_duplicate_input(inputs), Some((Path::new("in/a.in"), Path::new("in/a.in.gz"))));
+        assert_eq!(find_duplicate_input(inputs[..2].iter().copied()), None);
+    }
 
-    Ok(TestingInputs { test_count, iterator: tests.into_par_iter() })
-}
\ No newline at end of file
+    #[test]
+    fn splits_test_arguments_like_a_shell() {
+        let arguments = test_arguments(None, "t1", Some("--name <TEST> 'a b' c\\ d \"\"")).ok().unwrap();
+        assert_eq!(arguments, vec!["--name", "t1", "a b", "c d", ""]);
+        assert!(test_arguments(None, "t1", Some("'a")).is_err());
+    }
+
+    #[test]
+    fn finds_the_tests_of_output_files() {
+        for file_name in ["test1.out", "test1.out.2", "test1.out.sha256", "test1.out.gz", "test1.out.zst"] {
+            assert_eq!(output_test_name(file_name, ".out"), Some("test1"), "{}", file_name);
+        }
+        for file_name in ["notes.out.txt", "test1.out.1", "test1.out.", "test1.in", "test1.out.2.gz"] {
+            assert_eq!(output_test_name(file_name, ".out"), None, "{}", file_name);
+        }
+    }
+
+    #[test]
+    fn finds_orphaned_outputs() {
+        let directory = tempfile::tempdir().unwrap();
+        let (input_dir, output_dir) = (directory.path().join("in"), directory.path().join("out"));
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        for file_name in ["a.in", "b.in.gz"] {
+            fs::write(input_dir.join(file_name), "").unwrap();
+        }
+        for file_name in ["a.out", "a.out.2", "b.out.sha256", "c.out", "c.out.gz", "c.ans", "notes.out.txt"] {
+            fs::write(output_dir.join(file_name), "").unwrap();
+        }
+        assert_eq!(find_orphaned_outputs(&input_dir, &output_dir, &[".out", ".ans"]), vec!["c.ans", "c.out", "c.out.gz"]);
+    }
+}
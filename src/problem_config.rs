@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+
+/// The config file of problem packages prepared with sinol-make
+pub(crate) const PROBLEM_CONFIG_PATH: &str = "config.yml";
+
+/// A group number, which can be written in the config file both as a number and as a string
+#[derive(Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(untagged)]
+enum GroupKey {
+    Number(u32),
+    Text(String),
+}
+
+impl GroupKey {
+    fn number(&self) -> Result<u32, String> {
+        match self {
+            GroupKey::Number(group) => Ok(*group),
+            GroupKey::Text(group) => group.parse::<u32>()
+                .map_err(|_| format!("The group \"{}\" in {} is not a number", group, PROBLEM_CONFIG_PATH)),
+        }
+    }
+}
+
+/// The limits set at the top level of the config file or in a language override, in milliseconds and KiB
+#[derive(Deserialize, Default)]
+struct LimitsConfig {
+    time_limit: Option<u64>,
+    memory_limit: Option<u64>,
+    time_limits: Option<BTreeMap<GroupKey, u64>>,
+    memory_limits: Option<BTreeMap<GroupKey, u64>>,
+}
+
+/// The fields of a sinol-make config file used by toster, the other ones are ignored
+#[derive(Deserialize, Default)]
+struct ProblemConfig {
+    sinol_task_id: Option<String>,
+    #[serde(flatten)]
+    limits: LimitsConfig,
+    override_limits: Option<HashMap<String, LimitsConfig>>,
+}
+
+/// The limits of a problem read from its config file, the memory limits are in KiB
+#[derive(Default)]
+pub(crate) struct ProblemLimits {
+    /// The ID of the problem, which the test names start with
+    pub(crate) task_id: Option<String>,
+    pub(crate) time_limit: Option<Duration>,
+    pub(crate) memory_limit: Option<u64>,
+    /// The time limits of test groups, keyed by the group number
+    pub(crate) group_time_limits: Vec<(u32, Duration)>,
+    pub(crate) group_memory_limits: Vec<(u32, u64)>,
+}
+
+impl ProblemLimits {
+    /// Applies the limits set in `limits` (the top level of the file or a language override) on top of the current ones
+    fn apply(&mut self, limits: &LimitsConfig) -> Result<(), String> {
+        if let Some(time_limit) = limits.time_limit {
+            self.time_limit = Some(Duration::from_millis(time_limit));
+        }
+        if let Some(memory_limit) = limits.memory_limit {
+            self.memory_limit = Some(memory_limit);
+        }
+        for (group, time_limit) in limits.time_limits.iter().flatten() {
+            let group = group.number()?;
+            self.group_time_limits.retain(|(existing, _)| *existing != group);
+            self.group_time_limits.push((group, Duration::from_millis(*time_limit)));
+        }
+        for (group, memory_limit) in limits.memory_limits.iter().flatten() {
+            let group = group.number()?;
+            self.group_memory_limits.retain(|(existing, _)| *existing != group);
+            self.group_memory_limits.push((group, *memory_limit));
+        }
+        Ok(())
+    }
+
+    /// Reads the limits from a sinol-make config file, including the overrides for the language with the given extension
+    pub(crate) fn load(path: &Path, extension: Option<&str>) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        let mut limits = Self::parse(&contents, extension)?;
+        if limits.task_id.is_none() {
+            // Problem packages are named after the problem
+            limits.task_id = fs::canonicalize(path).ok()
+                .and_then(|path| Some(path.parent()?.file_name()?.to_string_lossy().to_string()));
+        }
+        Ok(limits)
+    }
+
+    fn parse(contents: &str, extension: Option<&str>) -> Result<Self, String> {
+        // An empty file is a null document rather than an empty mapping
+        let config: Option<ProblemConfig> = serde_yaml::from_str(contents)
+            .map_err(|error| format!("Failed to parse {}: {}", PROBLEM_CONFIG_PATH, error))?;
+        let config = config.unwrap_or_default();
+
+        let mut limits = ProblemLimits {
+            task_id: config.sinol_task_id,
+            ..ProblemLimits::default()
+        };
+        limits.apply(&config.limits)?;
+        let language_limits = extension.zip(config.override_limits.as_ref())
+            .and_then(|(extension, overrides)| overrides.get(extension));
+        if let Some(language_limits) = language_limits {
+            limits.apply(language_limits)?;
+        }
+        Ok(limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+title: \"Problem #1: abc\" # the title
+sinol_task_id: abc
+description: |
+  time_limit: 1
+  not a key
+time_limit: 1000 # ms
+memory_limit: 262144
+time_limits:
+  1: 2000
+  '2': 3000
+scores:
+  - 50
+  - 50
+override_limits:
+  py:
+    time_limit: 5000
+    time_limits:
+      2: 10000
+";
+
+    #[test]
+    fn parses_limits() {
+        let limits = ProblemLimits::parse(CONFIG, Some("cpp")).unwrap();
+        assert_eq!(limits.task_id.as_deref(), Some("abc"));
+        assert_eq!(limits.time_limit, Some(Duration::from_secs(1)));
+        assert_eq!(limits.memory_limit, Some(262144));
+        assert_eq!(limits.group_time_limits, [(1, Duration::from_secs(2)), (2, Duration::from_secs(3))]);
+        assert!(limits.group_memory_limits.is_empty());
+    }
+
+    #[test]
+    fn applies_language_overrides() {
+        let limits = ProblemLimits::parse(CONFIG, Some("py")).unwrap();
+        assert_eq!(limits.time_limit, Some(Duration::from_secs(5)));
+        assert_eq!(limits.group_time_limits, [(1, Duration::from_secs(2)), (2, Duration::from_secs(10))]);
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        assert!(ProblemLimits::parse("time_limit: *limit\n", None).is_err());
+        assert!(ProblemLimits::parse("time_limit: 1s\n", None).is_err());
+        assert!(ProblemLimits::parse("time_limits:\n  a: 1000\n", None).is_err());
+    }
+
+    #[test]
+    fn parses_flow_mappings_and_empty_files() {
+        let limits = ProblemLimits::parse("time_limits: {1: 2000}\nscores: {1: 100}\n", None).unwrap();
+        assert_eq!(limits.group_time_limits, [(1, Duration::from_secs(2))]);
+        assert!(ProblemLimits::parse("", None).unwrap().time_limit.is_none());
+    }
+}
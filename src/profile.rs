@@ -0,0 +1,351 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::fs::File;
+use std::io::{read_to_string, Seek};
+use std::path::Path;
+use std::process::{Command, Stdio};
+#[cfg(unix)]
+use std::time::Duration;
+use colored::Colorize;
+use which::which;
+use crate::args::{parse_pattern, ProfileArgs, ProfileTool};
+use crate::compiler::{CompiledCache, Compiler, Executable, FileKind, Languages};
+use crate::config::ConfigFile;
+use crate::executor::{test_to_temp, Environment, ExecutionLimits};
+use crate::executor::simple::SimpleExecutor;
+use crate::formatted_error::FormattedError;
+use crate::history::History;
+use crate::limits::TestLimits;
+use crate::prepare_input::{prepare_file_inputs, Test};
+use crate::temp_files::{create_temp_dir, create_temp_file, make_cloned_stdio};
+use crate::verbose::{log, set_verbosity};
+
+/// The commands the program is compiled with for profiling when --compile-command isn't given
+const DEBUG_INFO_COMPILE_COMMAND: &str = "g++ -std=c++20 -O3 -g -fno-omit-frame-pointer <IN> -o <OUT>";
+const GPROF_COMPILE_COMMAND: &str = "g++ -std=c++20 -O3 -pg <IN> -o <OUT>";
+
+/// The pairs of programs turning the output of "perf script" into a flamegraph, read from stdin and written to stdout
+const FLAMEGRAPH_TOOLS: [(&str, &str); 2] = [
+    ("inferno-collapse-perf", "inferno-flamegraph"),
+    ("stackcollapse-perf.pl", "flamegraph.pl"),
+];
+
+/// Runs a profiling tool, failing with what it wrote to stderr if it doesn't succeed
+fn run_tool(command: &mut Command, stdin: Stdio, stdout: Stdio) -> Result<(), String> {
+    log(1, || format!("Running {:?}", command));
+    let output = command.stdin(stdin).stdout(stdout).stderr(Stdio::piped()).output()
+        .map_err(|error| format!("Failed to run {}: {}", command.get_program().to_string_lossy(), error))?;
+    if !output.status.success() {
+        return Err(format!("{} failed:\n{}", command.get_program().to_string_lossy(), String::from_utf8_lossy(&output.stderr).trim_end()));
+    }
+    Ok(())
+}
+
+/// Runs a profiling tool, returning its output
+fn tool_output(command: &mut Command) -> Result<String, String> {
+    let mut output = create_temp_file().map_err(|error| error.to_string())?;
+    run_tool(command, Stdio::null(), make_cloned_stdio(&output))?;
+    output.rewind().map_err(|error| error.to_string())?;
+    read_to_string(output).map_err(|error| error.to_string())
+}
+
+/// The command the program is run through to profile it, which saves the profile to `profile_path`.
+/// Programs compiled for gprof profile themselves, so they're run directly
+fn profiler_command(tool: ProfileTool, profile_path: &Path) -> Option<Vec<String>> {
+    let profile_path = profile_path.to_string_lossy();
+    let command = match tool {
+        ProfileTool::Perf => vec!["perf".to_string(), "record".to_string(), "-g".to_string(), "-o".to_string(), profile_path.to_string()],
+        ProfileTool::Gprof => return None,
+        ProfileTool::Massif => vec!["valgrind".to_string(), "--tool=massif".to_string(), format!("--massif-out-file={}", profile_path)],
+        ProfileTool::Dhat => vec!["valgrind".to_string(), "--tool=dhat".to_string(), format!("--dhat-out-file={}", profile_path)],
+    };
+    Some(command.into_iter().chain(["--".to_string()]).collect())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{}KiB", bytes.div_ceil(1024))
+}
+
+/// Picks the test with the given name, or the slowest test of the last run of the program if there's none
+fn choose_test(tests: Vec<Test>, name: Option<&str>, source_path: &Path) -> Result<Test, String> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => History::load().last_run(source_path)
+            .and_then(|run| run.slowest_test())
+            .ok_or("There is no earlier run of the program to find its slowest test in, give the name of the test to profile")?
+            .to_string(),
+    };
+    tests.into_iter().find(|test| test.test_name == name)
+        .ok_or(format!("The test {} does not exist", name))
+}
+
+/// Prints the functions taking up the most time according to perf, and saves a flamegraph if requested
+fn perf_report(data_path: &Path, top: usize, flamegraph: Option<&Path>) -> Result<(), String> {
+    let report = tool_output(Command::new("perf")
+        .args(["report", "--stdio", "--no-children", "--sort", "symbol", "-i"])
+        .arg(data_path))?;
+    let lines: Vec<&str> = report.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter(|line| line.trim_start().chars().next().is_some_and(|char| char.is_ascii_digit()))
+        .take(top)
+        .collect();
+    println!("{}", "The functions taking up the most time:".bold());
+    println!("{}", lines.join("\n"));
+
+    let Some(flamegraph) = flamegraph else {
+        return Ok(());
+    };
+    let (collapse, render) = FLAMEGRAPH_TOOLS.iter()
+        .find(|(collapse, render)| which(collapse).is_ok() && which(render).is_ok())
+        .ok_or("Neither inferno nor the FlameGraph scripts were found, install one of them to create flamegraphs")?;
+    let mut script = create_temp_file().map_err(|error| error.to_string())?;
+    run_tool(Command::new("perf").arg("script").arg("-i").arg(data_path), Stdio::null(), make_cloned_stdio(&script))?;
+    script.rewind().map_err(|error| error.to_string())?;
+    let mut collapsed = create_temp_file().map_err(|error| error.to_string())?;
+    run_tool(&mut Command::new(collapse), Stdio::from(script), make_cloned_stdio(&collapsed))?;
+    collapsed.rewind().map_err(|error| error.to_string())?;
+    let output = File::create(flamegraph)
+        .map_err(|error| format!("Failed to create {}: {}", flamegraph.display(), error))?;
+    run_tool(&mut Command::new(render), Stdio::from(collapsed), Stdio::from(output))?;
+    println!("{}", format!("The flamegraph was saved to {}", flamegraph.display()).green());
+    Ok(())
+}
+
+/// Prints the flat profile of the functions taking up the most time according to gprof
+fn gprof_report(executable_path: &Path, profile_path: &Path, top: usize) -> Result<(), String> {
+    if !profile_path.is_file() {
+        return Err("The program didn't write a gprof profile, make sure it's compiled with -pg and exits normally".to_string());
+    }
+    let report = tool_output(Command::new("gprof")
+        .args(["-b", "-p"])
+        .arg(executable_path)
+        .arg(profile_path))?;
+    // The table of the flat profile has a two-line header, which starts with "%"
+    let start = report.lines().position(|line| line.trim_start().starts_with('%')).unwrap_or(0);
+    println!("{}", "The functions taking up the most time:".bold());
+    println!("{}", report.lines().skip(start).take(top + 2).collect::<Vec<&str>>().join("\n"));
+    Ok(())
+}
+
+/// The heap usage at the peak, read from the output of massif
+#[derive(Debug, PartialEq)]
+struct MassifPeak<'a> {
+    heap: u64,
+    extra: u64,
+    /// The allocation sites with the bytes they use at the peak, in the order massif lists them (from the largest)
+    sites: Vec<(u64, &'a str)>,
+}
+
+fn parse_massif(contents: &str) -> Result<MassifPeak<'_>, String> {
+    // The snapshots are separated by lines of dashes, the peak one is marked with "heap_tree=peak"
+    let peak = contents.split("#-----------").find(|snapshot| snapshot.contains("heap_tree=peak"))
+        .ok_or("The program didn't allocate any memory on the heap")?;
+    let value = |key: &str| peak.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('=')?.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    // The allocation sites are the children of the root of the heap tree, which are indented by one space,
+    // e.g. " n1: 4000000 0x109199: main (a.cpp:5)"
+    let sites = peak.lines()
+        .filter(|line| line.starts_with(" n"))
+        .filter_map(|line| {
+            let (bytes, site) = line.split_once(": ")?.1.split_once(' ')?;
+            Some((bytes.parse().ok()?, site))
+        })
+        .collect();
+    Ok(MassifPeak { heap: value("mem_heap_B"), extra: value("mem_heap_extra_B"), sites })
+}
+
+/// Prints the peak heap usage from a massif output file, and the allocation sites using the most memory at the peak
+fn massif_report(path: &Path, top: usize) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|error| format!("Failed to read the massif output: {}", error))?;
+    let peak = parse_massif(&contents)?;
+    println!("{} {} ({} of it allocator overhead)", "Peak heap usage:".bold(), format_bytes(peak.heap + peak.extra), format_bytes(peak.extra));
+    println!("{}", "The allocation sites using the most memory at the peak:".bold());
+    for (bytes, site) in peak.sites.iter().take(top) {
+        println!("{:>12} {}", format_bytes(*bytes), site);
+    }
+    Ok(())
+}
+
+/// Reads the allocation sites from the output of DHAT, with the bytes they use at the peak and in total,
+/// sorted from the one using the most memory at the peak
+fn parse_dhat(contents: &str) -> Result<Vec<(u64, u64, String)>, String> {
+    let profile: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|error| format!("The DHAT output is invalid: {}", error))?;
+    let frames = profile["ftbl"].as_array().map_or(&[][..], Vec::as_slice);
+    let mut sites: Vec<(u64, u64, String)> = profile["pps"].as_array().map_or(&[][..], Vec::as_slice).iter().map(|site| {
+        // The first frames are inside of valgrind's replacements of the allocation functions
+        let frame = site["fs"].as_array().map_or(&[][..], Vec::as_slice).iter()
+            .filter_map(|index| frames.get(index.as_u64()? as usize)?.as_str())
+            .find(|frame| !frame.contains("vg_replace_malloc"))
+            .unwrap_or("unknown");
+        (site["gb"].as_u64().unwrap_or(0), site["tb"].as_u64().unwrap_or(0), frame.to_string())
+    }).collect();
+    sites.sort_by_key(|(at_peak, _, _)| Reverse(*at_peak));
+    Ok(sites)
+}
+
+/// Prints the peak heap usage from a DHAT output file, and the allocation sites using the most memory at the peak
+fn dhat_report(path: &Path, top: usize) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|error| format!("Failed to read the DHAT output: {}", error))?;
+    let sites = parse_dhat(&contents)?;
+    let peak: u64 = sites.iter().map(|(at_peak, _, _)| at_peak).sum();
+    let total: u64 = sites.iter().map(|(_, total, _)| total).sum();
+    println!("{} {} ({} allocated in total)", "Peak heap usage:".bold(), format_bytes(peak), format_bytes(total));
+    println!("{}", "The allocation sites using the most memory at the peak:".bold());
+    for (at_peak, total, frame) in sites.iter().take(top) {
+        println!("{:>12} {} ({} in total)", format_bytes(*at_peak), frame, format_bytes(*total));
+    }
+    Ok(())
+}
+
+/// Reruns a single test of the program under a profiler and prints a summary of its report
+pub(crate) fn run_profile(args: ProfileArgs) -> Result<(), FormattedError> {
+    set_verbosity(args.verbose);
+    let error = |error: String| FormattedError::from_str(&error);
+    if !args.filename.is_file() {
+        return Err(error(format!("The file {} does not exist", args.filename.display())));
+    }
+    if !args.r#in.is_dir() {
+        return Err(FormattedError::from_str("The input directory does not exist"));
+    }
+    let (tool_name, tool_program) = match args.tool {
+        ProfileTool::Perf => ("perf", "perf"),
+        ProfileTool::Gprof => ("gprof", "gprof"),
+        ProfileTool::Massif => ("massif", "valgrind"),
+        ProfileTool::Dhat => ("DHAT", "valgrind"),
+    };
+    which(tool_program).map_err(|_| error(format!("{} was not found, install it to profile programs with {}", tool_program, tool_name)))?;
+    if args.flamegraph.is_some() && !matches!(args.tool, ProfileTool::Perf) {
+        return Err(FormattedError::from_str("Flamegraphs can only be created with perf"));
+    }
+
+    let compile_command = args.compile_command.unwrap_or(match args.tool {
+        ProfileTool::Gprof => GPROF_COMPILE_COMMAND.to_string(),
+        ProfileTool::Perf | ProfileTool::Massif | ProfileTool::Dhat => DEBUG_INFO_COMPILE_COMMAND.to_string(),
+    });
+    let languages = ConfigFile::load(args.config.as_deref())
+        .and_then(|config_file| Languages::new(&config_file, Some(compile_command), None, false))
+        .map_err(error)?;
+
+    let tempdir = create_temp_dir().expect("Failed to create temporary directory");
+    let limits = TestLimits::new(ExecutionLimits {
+        timeout: args.timeout,
+        memory_limit: None,
+        #[cfg(unix)]
+        max_processes: None,
+        #[cfg(unix)]
+        cpu_timeout: false,
+        #[cfg(unix)]
+        kill_grace: Duration::ZERO,
+        #[cfg(target_os = "linux")]
+        sandbox: false,
+    });
+    let pattern = parse_pattern(&format!("*{}", glob::Pattern::escape(&args.in_ext))).map_err(error)?;
+    let tests = prepare_file_inputs(&args.r#in, &pattern, &[], &limits, &tempdir.path().join("tests"), None)?;
+    let test = choose_test(tests, args.test.as_deref(), &args.filename).map_err(error)?;
+
+    let compiler = Compiler {
+        tempdir: &tempdir,
+        compile_timeout: args.compile_timeout,
+        languages: &languages,
+        compile_shell: false,
+        precompile_header: false,
+        wsl: false,
+    };
+    let (executable, compilation) = compiler.prepare_executable(&args.filename, "program", FileKind::Detect, CompiledCache::Unused)
+        .map_err(|error| error.to_formatted(false))?;
+    if let Some(compilation) = compilation {
+        compilation.print("Compilation", false);
+    }
+
+    let profile_path = tempdir.path().join("profile");
+    let executable_path = executable.path.clone();
+    let executor = SimpleExecutor {
+        executable: match profiler_command(args.tool, &profile_path) {
+            Some(mut interpreter) => {
+                interpreter.extend(executable.interpreter.into_iter().flatten());
+                Executable { path: executable.path, interpreter: Some(interpreter) }
+            }
+            None => executable,
+        },
+        environment: Environment { inherit: true, variables: vec![] },
+    };
+
+    println!("Profiling test {} with {}", test.test_name, tool_name);
+    fs::create_dir_all(&test.working_directory).expect("Failed to create the test's working directory");
+    let stderr = create_temp_file().expect("Failed to create memfile");
+    let (metrics, result) = test_to_temp(&executor, &test.input_source.get_file(), &stderr, &test.arguments, &test.working_directory, &test.limits);
+    if let Err(execution_error) = result {
+        let mut stderr = stderr;
+        stderr.rewind().expect("Failed to rewind memfile");
+        let stderr = read_to_string(stderr).unwrap_or_default();
+        return Err(error(format!("The program failed while being profiled: {}\n{}", execution_error, stderr.trim_end())));
+    }
+    if let Some(time) = metrics.time {
+        println!("The test ran in {:.3}s", time.as_secs_f64());
+    }
+
+    match args.tool {
+        ProfileTool::Perf => perf_report(&profile_path, args.top, args.flamegraph.as_deref()),
+        ProfileTool::Gprof => gprof_report(&executable_path, &test.working_directory.join("gmon.out"), args.top),
+        ProfileTool::Massif => massif_report(&profile_path, args.top),
+        ProfileTool::Dhat => dhat_report(&profile_path, args.top),
+    }.map_err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_dhat, parse_massif, MassifPeak};
+
+    #[test]
+    fn parses_the_massif_peak() {
+        let output = "desc: (none)
+cmd: ./program
+time_unit: i
+#-----------
+snapshot=0
+#-----------
+time=0
+mem_heap_B=0
+mem_heap_extra_B=0
+mem_stacks_B=0
+heap_tree=empty
+#-----------
+snapshot=1
+#-----------
+time=2000
+mem_heap_B=5000
+mem_heap_extra_B=24
+mem_stacks_B=0
+heap_tree=peak
+n2: 5000 (heap allocation functions) malloc/new/new[], --alloc-fns, etc.
+ n1: 4000 0x109199: main (a.cpp:5)
+  n0: 4000 0x4A1B2C: __libc_start_main (libc-start.c:308)
+ n0: 1000 0x1091A4: solve() (a.cpp:9)
+";
+        assert_eq!(parse_massif(output), Ok(MassifPeak {
+            heap: 5000,
+            extra: 24,
+            sites: vec![(4000, "0x109199: main (a.cpp:5)"), (1000, "0x1091A4: solve() (a.cpp:9)")],
+        }));
+        assert!(parse_massif("desc: (none)\n#-----------\nsnapshot=0\n#-----------\nheap_tree=empty\n").is_err());
+    }
+
+    #[test]
+    fn parses_the_dhat_sites() {
+        let output = r#"{
+            "ftbl": ["[root]", "0x483B7F3: malloc (vg_replace_malloc.c:309)", "0x109199: main (a.cpp:5)", "0x1091A4: solve() (a.cpp:9)"],
+            "pps": [
+                {"tb": 1000, "gb": 0, "fs": [1, 3]},
+                {"tb": 8000, "gb": 4000, "fs": [1, 2]}
+            ]
+        }"#;
+        assert_eq!(parse_dhat(output), Ok(vec![
+            (4000, 8000, "0x109199: main (a.cpp:5)".to_string()),
+            (0, 1000, "0x1091A4: solve() (a.cpp:9)".to_string()),
+        ]));
+        assert!(parse_dhat("not json").is_err());
+    }
+}
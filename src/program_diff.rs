@@ -0,0 +1,153 @@
+use std::io::read_to_string;
+use std::path::Path;
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::time::Duration;
+use std::fs;
+use colored::Colorize;
+use indicatif::{ParallelProgressIterator, ProgressStyle};
+use rayon::prelude::*;
+use crate::args::{parse_pattern, DiffArgs};
+use crate::compiler::{CompiledCache, Compiler, FileKind, Languages};
+use crate::config::ConfigFile;
+use crate::executor::{test_to_temp, Environment, ExecutionLimits};
+use crate::executor::simple::SimpleExecutor;
+use crate::formatted_error::FormattedError;
+use crate::limits::TestLimits;
+use crate::prepare_input::{prepare_file_inputs, Test};
+use crate::temp_files::{create_temp_dir, create_temp_file};
+use crate::testing_utils::{compare_lines, LineEndings, WhitespaceRules};
+use crate::verbose::set_verbosity;
+use crate::output::{create_progress_bar, progress_template};
+
+/// The outcome of running a program on a single test
+enum ProgramOutcome {
+    Output(String),
+    Error(String),
+}
+
+impl ProgramOutcome {
+    fn run(executor: &SimpleExecutor, test: &Test) -> Self {
+        let stderr = create_temp_file().expect("Failed to create memfile");
+        let (_, result) = test_to_temp(executor, &test.input_source.get_file(), &stderr, &test.arguments, &test.working_directory, &test.limits);
+        match result {
+            Ok(output) => match read_to_string(output) {
+                Ok(output) => ProgramOutcome::Output(output),
+                Err(_) => ProgramOutcome::Error("The output contained invalid characters".to_string()),
+            },
+            Err(error) => ProgramOutcome::Error(error.to_string()),
+        }
+    }
+
+    fn describe(&self) -> &str {
+        match self {
+            ProgramOutcome::Output(_) => "Finished successfully",
+            ProgramOutcome::Error(error) => error,
+        }
+    }
+}
+
+fn compile(compiler: &Compiler, path: &Path, name: &'static str, show_warnings: bool) -> Result<SimpleExecutor, FormattedError> {
+    let (executable, compilation) = compiler.prepare_executable(path, name, FileKind::Detect, CompiledCache::Unused)
+        .map_err(|error| error.to_formatted(false))?;
+    if let Some(compilation) = compilation {
+        compilation.print(&format!("Compilation of {}", path.display()), show_warnings);
+    }
+    Ok(SimpleExecutor {
+        executable,
+        environment: Environment { inherit: false, variables: vec![] },
+    })
+}
+
+/// Runs both programs on every input and prints the tests where their outputs differ
+pub(crate) fn run_program_diff(args: DiffArgs) -> Result<(), FormattedError> {
+    set_verbosity(args.verbose);
+    for path in [&args.first, &args.second] {
+        if !path.is_file() {
+            return Err(FormattedError::from_str(&format!("The file {} does not exist", path.display())));
+        }
+    }
+    if !args.r#in.is_dir() {
+        return Err(FormattedError::from_str("The input directory does not exist"));
+    }
+    let languages = ConfigFile::load(args.config.as_deref())
+        .and_then(|config_file| Languages::new(&config_file, args.compile_command, args.profile.as_deref(), args.compile_shell))
+        .map_err(|error| FormattedError::from_str(&error))?;
+
+    let tempdir = create_temp_dir().expect("Failed to create temporary directory");
+    let limits = TestLimits::new(ExecutionLimits {
+        timeout: args.timeout,
+        memory_limit: None,
+        #[cfg(unix)]
+        max_processes: None,
+        #[cfg(unix)]
+        cpu_timeout: false,
+        #[cfg(unix)]
+        kill_grace: Duration::ZERO,
+        #[cfg(target_os = "linux")]
+        sandbox: false,
+    });
+    let pattern = parse_pattern(&format!("*{}", glob::Pattern::escape(&args.in_ext)))
+        .map_err(|error| FormattedError::from_str(&error))?;
+    let mut tests = prepare_file_inputs(&args.r#in, &pattern, &[], &limits, &tempdir.path().join("tests"), None)?;
+    tests.sort_by(|a, b| human_sort::compare(&a.test_name, &b.test_name));
+
+    let compiler = Compiler {
+        tempdir: &tempdir,
+        compile_timeout: args.compile_timeout,
+        languages: &languages,
+        compile_shell: args.compile_shell,
+        precompile_header: args.precompile_header,
+        wsl: false,
+    };
+    let first = compile(&compiler, &args.first, "first", args.show_warnings)?;
+    let second = compile(&compiler, &args.second, "second", args.show_warnings)?;
+
+    let headers = (args.first.to_string_lossy().to_string(), args.second.to_string_lossy().to_string());
+    let differences: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
+    let progress_bar = create_progress_bar(tests.len(), ProgressStyle::with_template(&progress_template("[{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len}"))
+        .expect("Progress bar creation failed")
+        .progress_chars("#>-"));
+    tests.par_iter().progress_with(progress_bar.clone()).for_each(|test| {
+        fs::create_dir_all(&test.working_directory).expect("Failed to create the test's working directory");
+        let first_outcome = ProgramOutcome::run(&first, test);
+        let second_outcome = ProgramOutcome::run(&second, test);
+        let _ = fs::remove_dir_all(&test.working_directory);
+
+        let difference = match (&first_outcome, &second_outcome) {
+            (ProgramOutcome::Output(first_output), ProgramOutcome::Output(second_output)) => {
+                compare_lines(first_output, second_output, LineEndings::Lenient, WhitespaceRules::default(), (&headers.0, &headers.1))
+                    .err()
+                    .map(|difference| difference.render())
+            }
+            (ProgramOutcome::Error(first_error), ProgramOutcome::Error(second_error)) if first_error == second_error => None,
+            _ => Some(format!(
+                "{}: {}\n{}: {}",
+                headers.0, first_outcome.describe(), headers.1, second_outcome.describe(),
+            ).red().to_string()),
+        };
+        if let Some(difference) = difference {
+            differences.lock().expect("Failed to lock differences mutex").push((test.test_name.clone(), difference));
+        }
+    });
+    progress_bar.finish_and_clear();
+
+    let mut differences = differences.into_inner().expect("Failed to lock differences mutex");
+    if differences.is_empty() {
+        println!("{}", format!("The outputs of both programs are the same on all {} tests", tests.len()).green());
+        return Ok(());
+    }
+
+    differences.sort_by(|a, b| human_sort::compare(&a.0, &b.0));
+    println!("{}", format!("The outputs of the programs differ on {} of {} tests:", differences.len(), tests.len()).red());
+    for (test_name, difference) in differences.iter().take(args.max_errors) {
+        println!("{}\n{}", format!("Test {}:", test_name).bold(), difference);
+    }
+    if differences.len() > args.max_errors {
+        println!("{}", format!(
+            "...and {} more (use --max-errors to show more)",
+            differences.len() - args.max_errors,
+        ).yellow());
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use serde::Serialize;
+use crate::test_errors::Verdict;
+
+/// The file given with --progress-fd, which the progress events are written to
+static PROGRESS_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// A progress update, written as a single line of JSON
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum ProgressEvent<'a> {
+    /// The tests of a stage (running the model solution or testing) started
+    Started { stage: &'a str, total: usize },
+    TestFinished {
+        test: &'a str,
+        verdict: Verdict,
+        /// The runtime in seconds
+        time: Option<f64>,
+        memory_kibibytes: Option<u64>,
+    },
+    Finished { stopped_early: bool },
+}
+
+/// Makes the progress events get written to the file descriptor `fd`, which has to be open
+#[cfg(unix)]
+pub(crate) fn open_progress_fd(fd: i32) -> Result<(), String> {
+    use std::io;
+    use std::os::fd::FromRawFd;
+
+    if fd < 3 {
+        return Err(format!("The file descriptor {} given with --progress-fd is stdin, stdout or stderr, which toster already uses", fd));
+    }
+    // The descriptor is duplicated with FD_CLOEXEC set and the original is closed,
+    // so that the tested programs and checkers don't inherit it
+    // SAFETY: fcntl and close only operate on the descriptor, which isn't used anywhere else in toster
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+    if duplicate == -1 {
+        return Err(format!("The file descriptor {} given with --progress-fd is not open: {}", fd, io::Error::last_os_error()));
+    }
+    unsafe { libc::close(fd); }
+    // SAFETY: the duplicate was just created and nothing else owns it
+    let file = unsafe { File::from_raw_fd(duplicate) };
+    let _ = PROGRESS_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Writes the event to the progress file descriptor, if one was given
+pub(crate) fn report(event: ProgressEvent) {
+    let Some(file) = PROGRESS_FILE.get() else {
+        return;
+    };
+    let mut line = serde_json::to_string(&event).expect("Failed to serialize the progress event");
+    line.push('\n');
+    // The reader might have closed its end, which isn't a reason to stop testing
+    let _ = file.lock().expect("Failed to lock the progress file mutex").write_all(line.as_bytes());
+}
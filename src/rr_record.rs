@@ -0,0 +1,89 @@
+use std::fs;
+use std::io::{read_to_string, Seek};
+use std::path::PathBuf;
+use std::time::Duration;
+use colored::Colorize;
+use crate::compiler::Executable;
+use crate::executor::{Environment, ExecutionLimits, TestExecutor};
+use crate::executor::simple::SimpleExecutor;
+use crate::generic_utils::DATA_DIRECTORY;
+use crate::prepare_input::Test;
+use crate::temp_files::create_temp_file;
+use crate::test_summary::TestSummary;
+
+/// The most failing tests recorded after a single run, since recording is slow and the traces are large
+const MAX_RECORDED_TESTS: usize = 5;
+
+/// How many times longer than its time limit a test can take while it's recorded
+const RECORDING_SLOWDOWN: u32 = 10;
+
+/// Reruns one failing test under rr record, returning the directory of the trace
+fn record_test(executable: &Executable, environment: &Environment, test: &Test) -> Result<PathBuf, String> {
+    let traces_directory = PathBuf::from(DATA_DIRECTORY).join("rr");
+    fs::create_dir_all(&traces_directory).map_err(|error| format!("Failed to create the trace directory: {}", error))?;
+    // rr runs in the working directory of the test, so the path has to be absolute
+    let trace_directory = fs::canonicalize(&traces_directory)
+        .map_err(|error| format!("Failed to create the trace directory: {}", error))?
+        .join(&test.test_name);
+    // rr refuses to overwrite an existing trace
+    if trace_directory.exists() {
+        fs::remove_dir_all(&trace_directory).map_err(|error| format!("Failed to remove the old trace: {}", error))?;
+    }
+
+    let mut interpreter = vec!["rr".to_string(), "record".to_string(), "-o".to_string(), trace_directory.to_string_lossy().to_string()];
+    interpreter.extend(executable.interpreter.iter().flatten().cloned());
+    let recorder = SimpleExecutor {
+        executable: Executable { path: executable.path.clone(), interpreter: Some(interpreter) },
+        environment: environment.clone(),
+    };
+    // rr has to write the trace, so the sandbox and the process limit aren't applied
+    let limits = ExecutionLimits {
+        timeout: test.limits.timeout * RECORDING_SLOWDOWN,
+        max_processes: None,
+        cpu_timeout: false,
+        kill_grace: Duration::ZERO,
+        sandbox: false,
+        ..test.limits
+    };
+
+    fs::create_dir_all(&test.working_directory).map_err(|error| format!("Failed to create the working directory: {}", error))?;
+    let output_file = create_temp_file().map_err(|error| error.to_string())?;
+    let mut stderr_file = create_temp_file().map_err(|error| error.to_string())?;
+    let _ = recorder.test_to_file(&test.input_source.get_file(), &output_file, &stderr_file, &test.arguments, &test.working_directory, &limits);
+    let _ = fs::remove_dir_all(&test.working_directory);
+
+    // The program is expected to fail, so only a missing trace means that recording failed
+    if !trace_directory.is_dir() {
+        stderr_file.rewind().map_err(|error| error.to_string())?;
+        let stderr = read_to_string(stderr_file).unwrap_or_default();
+        let rr_errors: Vec<&str> = stderr.lines().filter(|line| line.starts_with("rr") || line.starts_with("[FATAL")).collect();
+        return Err(format!("rr didn't save a trace:\n{}", rr_errors.join("\n")));
+    }
+    Ok(trace_directory)
+}
+
+/// Reruns the failed tests (at most [`MAX_RECORDED_TESTS`] of them) under rr record,
+/// so that the failing executions can be replayed in a debugger with rr replay
+pub(crate) fn record_failed_tests(executable: &Executable, environment: &Environment, tests: &[Test], test_summary: &TestSummary) {
+    let mut failed: Vec<&Test> = tests.iter()
+        .filter(|test| test_summary.test_results.iter().any(|result| !result.verdict.is_success() && result.test_name == test.test_name))
+        .collect();
+    if failed.is_empty() {
+        return;
+    }
+    failed.sort_by(|a, b| human_sort::compare(&a.test_name, &b.test_name));
+    if failed.len() > MAX_RECORDED_TESTS {
+        println!("{}", format!("Only the first {} of the {} failed tests are recorded", MAX_RECORDED_TESTS, failed.len()).yellow());
+        failed.truncate(MAX_RECORDED_TESTS);
+    }
+
+    for test in failed {
+        match record_test(executable, environment, test) {
+            Ok(trace_directory) => println!(
+                "{}",
+                format!("Test {} was recorded to {}, replay it with \"rr replay {}\"", test.test_name, trace_directory.display(), trace_directory.display()).green(),
+            ),
+            Err(error) => println!("{}", format!("Failed to record test {}: {}", test.test_name, error).red()),
+        }
+    }
+}
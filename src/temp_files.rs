@@ -1,30 +1,89 @@
+use std::env;
 use std::fs::File;
 use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tempfile::{NamedTempFile, TempDir};
+
+/// The directory temporary files are created in instead of the system's temporary directory, set with --temp-dir
+static TEMP_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+/// Whether temporary files are stored in the directory instead of memfiles, set with --no-memfd and --temp-dir
+#[cfg(target_os = "linux")]
+static MEMFD_DISABLED: AtomicBool = AtomicBool::new(false);
 
 pub(crate) fn make_cloned_stdio(file: &File) -> Stdio {
     Stdio::from(file.try_clone().unwrap())
 }
 
+/// Makes all of the temporary files and directories be created in `directory`, which has to exist.
+/// On Linux, this also stops memfiles from being used
+pub(crate) fn set_temp_directory(directory: PathBuf) {
+    #[cfg(target_os = "linux")]
+    disable_memfd();
+    TEMP_DIRECTORY.set(directory).expect("The temporary directory should only be set once");
+}
+
+/// Makes [`create_temp_file`] create files in the temporary directory instead of memfiles
+#[cfg(target_os = "linux")]
+pub(crate) fn disable_memfd() {
+    MEMFD_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// The directory temporary files are created in
+fn temp_directory() -> PathBuf {
+    TEMP_DIRECTORY.get().cloned().unwrap_or_else(env::temp_dir)
+}
+
 /// Creates a memfile using the `memfile` crate on Linux
-/// or a tempfile using the `tempfile` crate on other systems.
+/// or a tempfile using the `tempfile` crate on other systems or if memfiles are disabled.
 ///
 /// These files should be deleted automatically when all file descriptors are closed
 ///
 /// Always returns a `File` struct
 pub(crate) fn create_temp_file() -> io::Result<File> {
     #[cfg(target_os = "linux")]
-    {
+    if !MEMFD_DISABLED.load(Ordering::Relaxed) {
         // The file is deleted when all file descriptors are closed
         // https://man7.org/linux/man-pages/man2/memfd_create.2.html
-        memfile::MemFile::create_default("toster temporary file")
-            .map(|memfile| memfile.into_file())
+        return memfile::MemFile::create_default("toster temporary file")
+            .map(|memfile| memfile.into_file());
     }
 
-    #[cfg(not(target_os = "linux"))]
-    {
-        // tempfile() adds FILE_FLAG_DELETE_ON_CLOSE flag on Windows and TMPFILE on Unix
-        // so the file should be deleted when all file descriptors are closed
-        tempfile::tempfile()
-    }
+    // tempfile_in() adds FILE_FLAG_DELETE_ON_CLOSE flag on Windows and TMPFILE on Unix
+    // so the file should be deleted when all file descriptors are closed
+    tempfile::tempfile_in(temp_directory())
+}
+
+/// Creates a named temporary file in the temporary directory, for programs that need a path
+pub(crate) fn create_named_temp_file() -> io::Result<NamedTempFile> {
+    NamedTempFile::new_in(temp_directory())
+}
+
+/// Creates a temporary directory in the temporary directory, which is deleted when it's dropped
+pub(crate) fn create_temp_dir() -> io::Result<TempDir> {
+    tempfile::tempdir_in(temp_directory())
+}
+
+/// Writes `contents` to a hidden temporary file next to `path`, which can then be renamed to `path` with [`NamedTempFile::persist`],
+/// so that the file at `path` is never left partially written. The file is deleted if it's dropped instead
+pub(crate) fn write_next_to(path: &Path, contents: &mut impl Read) -> io::Result<NamedTempFile> {
+    let directory = path.parent().expect("The output file should be in a directory");
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".toster-")
+        .tempfile_in(directory)?;
+    // On Linux, io::copy between files and pipes uses copy_file_range, sendfile or splice, so the data is copied
+    // by the kernel without going through userspace. That only happens for std's types, so the inner File is used
+    io::copy(contents, temp_file.as_file_mut())?;
+    Ok(temp_file)
+}
+
+/// Writes `contents` to a hidden temporary file next to `path` and then renames it to `path`,
+/// so that the file at `path` is never left partially written
+pub(crate) fn write_atomically(path: &Path, contents: &mut impl Read) -> io::Result<()> {
+    write_next_to(path, contents)?.persist(path).map_err(|error| error.error)?;
+    Ok(())
 }
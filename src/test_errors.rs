@@ -1,21 +1,144 @@
+use std::fmt::{Display, Formatter};
 use std::time::Duration;
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::testing_utils::{diffs_redacted, LineDiff};
 
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct ExecutionMetrics {
 	pub(crate) memory_kibibytes: Option<u64>,
+	/// The wall time
 	pub(crate) time: Option<Duration>,
+	pub(crate) cpu_time: Option<Duration>,
 }
 
 impl ExecutionMetrics {
-	// Currently only the sio2jail executor uses this constant,
-	// which is not compiled on Windows builds
-	#[allow(dead_code)]
-	pub const NONE: ExecutionMetrics = ExecutionMetrics { memory_kibibytes: None, time: None };
+	pub const NONE: ExecutionMetrics = ExecutionMetrics { memory_kibibytes: None, time: None, cpu_time: None };
+
+	/// Formats the metrics as e.g. ` (0.123s, 0.120s CPU, 2048KiB)`, or an empty string if there are none
+	pub(crate) fn format_suffix(&self) -> String {
+		let parts: Vec<String> = [
+			self.time.map(|time| format!("{:.3}s", time.as_secs_f64())),
+			self.cpu_time.map(|cpu_time| format!("{:.3}s CPU", cpu_time.as_secs_f64())),
+			self.memory_kibibytes.map(|memory| format!("{}KiB", memory)),
+		].into_iter().flatten().collect();
+
+		if parts.is_empty() { String::new() }
+		else { format!(" ({})", parts.join(", ")) }
+	}
+}
+
+/// The verdict of a single test, shown as e.g. "Wrong answer"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Verdict {
+	Correct,
+	/// The output was generated in generate mode
+	Successful,
+	#[serde(rename = "Wrong answer")]
+	WrongAnswer,
+	#[serde(rename = "Presentation error")]
+	PresentationError,
+	#[serde(rename = "Timed out")]
+	TimedOut,
+	#[serde(rename = "Out of memory")]
+	OutOfMemory,
+	#[serde(rename = "Runtime error")]
+	RuntimeError,
+	#[serde(rename = "Sio2jail error")]
+	Sio2jailError,
+	#[serde(rename = "Executor error")]
+	ExecutorError,
+	#[serde(rename = "Checker error")]
+	CheckerError,
+	#[serde(rename = "Invalid output")]
+	InvalidOutput,
+	#[serde(rename = "No output file")]
+	NoOutputFile,
+	#[serde(rename = "Invalid test file")]
+	InvalidTestFile,
+	Cancelled,
+}
+
+impl Verdict {
+	pub fn is_success(self) -> bool {
+		matches!(self, Verdict::Correct | Verdict::Successful)
+	}
+}
+
+impl Display for Verdict {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.pad(match self {
+			Verdict::Correct => "Correct",
+			Verdict::Successful => "Successful",
+			Verdict::WrongAnswer => "Wrong answer",
+			Verdict::PresentationError => "Presentation error",
+			Verdict::TimedOut => "Timed out",
+			Verdict::OutOfMemory => "Out of memory",
+			Verdict::RuntimeError => "Runtime error",
+			Verdict::Sio2jailError => "Sio2jail error",
+			Verdict::ExecutorError => "Executor error",
+			Verdict::CheckerError => "Checker error",
+			Verdict::InvalidOutput => "Invalid output",
+			Verdict::NoOutputFile => "No output file",
+			Verdict::InvalidTestFile => "Invalid test file",
+			Verdict::Cancelled => "Cancelled",
+		})
+	}
+}
+
+/// Describes how the program's output differs from the expected one
+#[derive(Clone, Hash, Serialize, Deserialize)]
+pub enum OutputDifference {
+	Message(String),
+	/// The differing lines, which are only rendered as a table when the error is shown,
+	/// since most of the errors usually aren't
+	Lines(LineDiff),
+	/// The comment the checker gave with its verdict, which can be empty
+	CheckerComment(String),
+}
+
+impl OutputDifference {
+	pub fn render(&self) -> String {
+		match self {
+			OutputDifference::Message(message) => message.clone(),
+			OutputDifference::Lines(diff) => diff.render(),
+			OutputDifference::CheckerComment(comment) if comment.is_empty() => "Incorrect output".red().to_string(),
+			OutputDifference::CheckerComment(comment) => format!("Incorrect output: {}", comment).red().to_string(),
+		}
+	}
+
+	/// Replaces the differing lines with their rendered table, which only shows the positions of the differences
+	/// when --redact-diff is set, so that the contents of the output files aren't saved with the error
+	pub fn redacted(self) -> Self {
+		match self {
+			OutputDifference::Lines(diff) if diffs_redacted() => OutputDifference::Message(diff.render()),
+			difference => difference,
+		}
+	}
+
+	/// The approximate number of bytes the difference takes up in memory
+	pub fn size(&self) -> usize {
+		match self {
+			OutputDifference::Message(message) | OutputDifference::CheckerComment(message) => message.len(),
+			OutputDifference::Lines(diff) => diff.size(),
+		}
+	}
+}
+
+impl From<String> for OutputDifference {
+	fn from(message: String) -> Self {
+		OutputDifference::Message(message)
+	}
 }
 
+#[derive(Clone, Hash, Serialize, Deserialize)]
 pub enum TestError {
 	Incorrect {
-		error: String
+		error: OutputDifference
+	},
+	/// The output has the right tokens, but differs from the output file in whitespace
+	PresentationError {
+		error: OutputDifference
 	},
 	ProgramError {
 		error: ExecutionError
@@ -24,62 +147,102 @@ pub enum TestError {
 		error: ExecutionError
 	},
 	NoOutputFile,
+	/// The input or output file of the test couldn't be read or written, e.g. because it's a corrupted compressed file
+	InvalidTestFile {
+		error: String
+	},
 	Cancelled,
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone, Debug, Hash, Serialize, Deserialize)]
 pub enum ExecutionError {
 	TimedOut,
 	MemoryLimitExceeded,
 	RuntimeError(String),
 	Sio2jailError(String),
+	/// The executor failed to run the program, e.g. because the connection to the remote machine was lost
+	ExecutorError(String),
 	PipeError,
 	OutputNotUtf8,
-	IncorrectCheckerFormat(String)
+	IncorrectCheckerFormat(String),
+	/// The process was killed by Ctrl+C
+	Interrupted,
 }
 
 impl TestError {
-	pub fn to_string(&self, test_name: &str) -> String {
-		let mut result: String = String::new();
+	/// The verdict of the test that failed with this error
+	pub fn verdict(&self) -> Verdict {
+		match self {
+			TestError::Incorrect { .. } => Verdict::WrongAnswer,
+			TestError::PresentationError { .. } => Verdict::PresentationError,
+			TestError::ProgramError { error: ExecutionError::TimedOut } => Verdict::TimedOut,
+			TestError::ProgramError { error: ExecutionError::MemoryLimitExceeded } => Verdict::OutOfMemory,
+			TestError::ProgramError { error: ExecutionError::RuntimeError(_) } => Verdict::RuntimeError,
+			TestError::ProgramError { error: ExecutionError::Sio2jailError(_) } => Verdict::Sio2jailError,
+			TestError::ProgramError { error: ExecutionError::ExecutorError(_) } => Verdict::ExecutorError,
+			TestError::ProgramError { error: ExecutionError::IncorrectCheckerFormat(_) } => Verdict::CheckerError,
+			TestError::ProgramError { error: ExecutionError::PipeError | ExecutionError::OutputNotUtf8 } => Verdict::InvalidOutput,
+			TestError::ProgramError { error: ExecutionError::Interrupted } => Verdict::Cancelled,
+			TestError::CheckerError { .. } => Verdict::CheckerError,
+			TestError::NoOutputFile => Verdict::NoOutputFile,
+			TestError::InvalidTestFile { .. } => Verdict::InvalidTestFile,
+			TestError::Cancelled => Verdict::Cancelled,
+		}
+	}
+
+	/// The details of the error, without the line naming the test
+	pub fn description(&self) -> String {
+		match self {
+			TestError::Incorrect { error } => error.render(),
+			TestError::PresentationError { error } => format!("{}\n{}", "The output is only formatted differently than the output file".yellow(), error.render()),
+			TestError::ProgramError { error } => error.to_string().red().to_string(),
+			TestError::CheckerError { error } => error.to_string().blue().to_string(),
+			TestError::NoOutputFile => "Output file does not exist".red().to_string(),
+			TestError::InvalidTestFile { error } => error.red().to_string(),
+			TestError::Cancelled => "Cancelled".yellow().to_string(),
+		}
+	}
+
+	fn format_with_header(&self, tests: &str) -> String {
+		let header = match self {
+			TestError::CheckerError { .. } => format!("{} encountered a checker error:", tests),
+			_ => format!("{}:", tests),
+		};
+		format!("{}\n{}", header.bold(), self.description())
+	}
 
+	/// The error with the differing lines redacted if --redact-diff is set, see [`OutputDifference::redacted`]
+	pub fn redacted(self) -> Self {
 		match self {
-			TestError::Incorrect { error } => {
-				result.push_str(&format!("{}", format!("Test {}:\n", test_name).bold()));
-				result.push_str(error);
-			}
-			TestError::ProgramError { error } => {
-				result.push_str(&format!("{}", format!("Test {}:\n", test_name).bold()));
-				result.push_str(&format!("{}", error.to_string().red()));
-			}
-			TestError::CheckerError { error } => {
-				result.push_str(&format!("{}", format!("Test {} encountered a checker error:\n", test_name).bold()));
-				result.push_str(&format!("{}", error.to_string().blue()));
-			}
-			TestError::NoOutputFile => {
-				result.push_str(&format!("{}", format!("Test {}:\n", test_name).bold()));
-				result.push_str(&format!("{}", "Output file does not exist".red()));
-			}
-			TestError::Cancelled => {
-				result.push_str(&format!("{}", format!("Test {}:\n", test_name).bold()));
-				result.push_str(&format!("{}", "Cancelled".yellow()));
-			}
+			TestError::Incorrect { error } => TestError::Incorrect { error: error.redacted() },
+			TestError::PresentationError { error } => TestError::PresentationError { error: error.redacted() },
+			error => error,
 		}
+	}
+
+	pub fn to_string(&self, test_name: &str, metrics: &ExecutionMetrics) -> String {
+		self.format_with_header(&format!("Test {}{}", test_name, metrics.format_suffix()))
+	}
 
-		result
+	/// Formats the error as a single entry shared by all of the given tests
+	pub fn to_grouped_string(&self, test_names: &[&str]) -> String {
+		self.format_with_header(&format!("Tests {} ({} tests)", test_names.join(", "), test_names.len()))
 	}
 }
 
-impl ExecutionError {
-	pub fn to_string(&self) -> String {
+impl Display for ExecutionError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		match self {
-			ExecutionError::TimedOut => "Timed out".to_string(),
-			ExecutionError::MemoryLimitExceeded => "Memory limit exceeded".to_string(),
-			ExecutionError::RuntimeError(error) => format!("Runtime error {}", error),
-			ExecutionError::Sio2jailError(error) => format!("Sio2jail error: {}", error),
-			ExecutionError::IncorrectCheckerFormat(error) => format!("The checker output didn't follow the Toster checker format - {}", error),
-			ExecutionError::PipeError => "Failed to read program output".to_string(),
-			ExecutionError::OutputNotUtf8 => "The output contained invalid characters".to_string(),
+			ExecutionError::TimedOut => write!(f, "Timed out"),
+			ExecutionError::MemoryLimitExceeded => write!(f, "Memory limit exceeded"),
+			ExecutionError::RuntimeError(error) => write!(f, "Runtime error {}", error),
+			ExecutionError::Sio2jailError(error) => write!(f, "Sio2jail error: {}", error),
+			ExecutionError::ExecutorError(error) => write!(f, "Failed to run the program: {}", error),
+			ExecutionError::IncorrectCheckerFormat(error) => write!(f, "The checker output didn't follow the Toster checker format - {}", error),
+			ExecutionError::PipeError => write!(f, "Failed to read program output"),
+			ExecutionError::OutputNotUtf8 => write!(f, "The output contained invalid characters"),
+			ExecutionError::Interrupted => write!(f, "Interrupted"),
 		}
 	}
 }
@@ -0,0 +1,43 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::test_errors::{ExecutionMetrics, TestError, Verdict};
+
+/// The result of a single test as plain data, without any of the formatting of the terminal output,
+/// so that everything writing the results in other formats can share it.
+///
+/// The details of a failed test are its [`TestError`], which is also what the terminal output is rendered from
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TestOutcome {
+    pub(crate) test: String,
+    pub(crate) verdict: Verdict,
+    /// The runtime in seconds
+    pub(crate) time: Option<f64>,
+    /// The CPU time in seconds
+    pub(crate) cpu_time: Option<f64>,
+    pub(crate) memory_kibibytes: Option<u64>,
+    /// The error of a failed test, with the differing lines redacted if --redact-diff is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<TestError>,
+}
+
+impl TestOutcome {
+    pub(crate) fn new(test: &str, verdict: Verdict, metrics: &ExecutionMetrics, error: Option<TestError>) -> Self {
+        TestOutcome {
+            test: test.to_string(),
+            verdict,
+            time: metrics.time.map(|time| time.as_secs_f64()),
+            cpu_time: metrics.cpu_time.map(|time| time.as_secs_f64()),
+            memory_kibibytes: metrics.memory_kibibytes,
+            // With --redact-diff the contents of the output files can't be written anywhere
+            error: error.map(TestError::redacted),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> ExecutionMetrics {
+        ExecutionMetrics {
+            memory_kibibytes: self.memory_kibibytes,
+            time: self.time.map(Duration::from_secs_f64),
+            cpu_time: self.cpu_time.map(Duration::from_secs_f64),
+        }
+    }
+}
@@ -1,10 +1,19 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use colored::Color::{Blue, Green, Red, Yellow};
 use colored::{Color, Colorize};
-use crate::test_errors::{ExecutionError, ExecutionMetrics, TestError};
+use crate::temp_files::create_temp_file;
+use crate::test_errors::{ExecutionError, ExecutionMetrics, TestError, Verdict};
 use crate::test_errors::TestError::*;
 
+/// Once the descriptions of the errors kept in memory take up this many bytes,
+/// the errors of the next failed tests are moved to a temporary file
+const MAX_ERRORS_IN_MEMORY: usize = 64 * 1024 * 1024;
+
 pub(crate) struct TestSummary {
     pub(crate) generate_mode: bool,
     pub(crate) start_time: Instant,
@@ -13,20 +22,79 @@ pub(crate) struct TestSummary {
     pub(crate) processed: usize,
     pub(crate) success: usize,
     pub(crate) incorrect: usize,
+    pub(crate) presentation_error: usize,
     pub(crate) timed_out: usize,
     pub(crate) invalid_output: usize,
     pub(crate) memory_limit_exceeded: usize,
     pub(crate) runtime_error: usize,
     pub(crate) sio2jail_error: usize,
+    pub(crate) executor_error: usize,
     pub(crate) checker_error: usize,
     pub(crate) no_output_file: usize,
+    pub(crate) invalid_test_file: usize,
 
-    test_errors: Vec<(String, TestError)>,
+    test_errors: Vec<FailedTest>,
+    /// The total size of the descriptions of the errors kept in memory
+    errors_in_memory_size: usize,
+    /// The file the errors are moved to once they take up too much memory
+    spilled_errors: Option<Arc<File>>,
+    pub(crate) test_results: Vec<TestResult>,
+    /// The tests that are currently being executed and the time they started at
+    running_tests: Vec<(String, Instant)>,
 
     pub(crate) slowest_test: Option<(Duration, String)>,
     pub(crate) most_memory_used: Option<(u64, String)>,
 }
 
+/// A test that failed, with its error kept in memory or in a temporary file
+pub(crate) struct FailedTest {
+    pub(crate) test_name: String,
+    pub(crate) verdict: Verdict,
+    pub(crate) metrics: ExecutionMetrics,
+    error: StoredError,
+}
+
+enum StoredError {
+    InMemory(TestError),
+    /// The error is serialized in `file` at `offset`
+    Spilled { file: Arc<File>, offset: u64, length: usize },
+}
+
+impl FailedTest {
+    /// Returns the error of the test, reading it back from the temporary file if it was moved there
+    pub(crate) fn error(&self) -> Cow<'_, TestError> {
+        match &self.error {
+            StoredError::InMemory(error) => Cow::Borrowed(error),
+            StoredError::Spilled { file, offset, length } => {
+                let mut file = file.as_ref();
+                let mut serialized = vec![0; *length];
+                file.seek(SeekFrom::Start(*offset)).expect("Failed to seek the file with the errors");
+                file.read_exact(&mut serialized).expect("Failed to read the file with the errors");
+                Cow::Owned(serde_json::from_slice(&serialized).expect("Failed to deserialize the error"))
+            }
+        }
+    }
+}
+
+/// The verdict and metrics of a single finished test
+pub(crate) struct TestResult {
+    pub(crate) test_name: String,
+    pub(crate) verdict: Verdict,
+    pub(crate) time: Option<Duration>,
+    pub(crate) cpu_time: Option<Duration>,
+    pub(crate) memory_kibibytes: Option<u64>,
+}
+
+pub(crate) struct RuntimeStatistics {
+    pub(crate) mean: Duration,
+    pub(crate) median: Duration,
+    pub(crate) percentile_95: Duration,
+    /// The sum of the wall times
+    pub(crate) total: Duration,
+    /// The sum of the CPU times, if the executor measures them
+    pub(crate) total_cpu_time: Option<Duration>,
+}
+
 struct CountPart<'a> {
     display_empty: bool,
     count: usize,
@@ -76,55 +144,150 @@ impl TestSummary {
             total: total_count,
             processed: 0,
             incorrect: 0,
+            presentation_error: 0,
             timed_out: 0,
             invalid_output: 0,
             memory_limit_exceeded: 0,
             runtime_error: 0,
             sio2jail_error: 0,
+            executor_error: 0,
             checker_error: 0,
             no_output_file: 0,
+            invalid_test_file: 0,
             success: 0,
 
             test_errors: vec![],
+            errors_in_memory_size: 0,
+            spilled_errors: None,
+            test_results: vec![],
+            running_tests: vec![],
 
             slowest_test: None,
             most_memory_used: None,
         }
     }
 
+    pub(crate) fn mark_running(&mut self, test_name: &str) {
+        self.running_tests.push((test_name.to_string(), Instant::now()));
+    }
+
+    pub(crate) fn mark_finished(&mut self, test_name: &str) {
+        self.running_tests.retain(|(running_name, _)| running_name != test_name);
+    }
+
+    /// Formats the names of the longest running tests and how long they've been running for
+    pub(crate) fn format_running(&self, max_count: usize) -> String {
+        let mut running: Vec<&(String, Instant)> = self.running_tests.iter().collect();
+        running.sort_by_key(|(_, start_time)| *start_time);
+
+        let mut result = running.iter()
+            .take(max_count)
+            .map(|(test_name, start_time)| format!("{} ({:.1}s)", test_name, start_time.elapsed().as_secs_f64()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        if running.len() > max_count {
+            result.push_str(&format!(" and {} more", running.len() - max_count));
+        }
+        result
+    }
+
+    /// Formats the names and verdicts of the most recently finished tests, starting with the newest one
+    pub(crate) fn format_finished(&self, max_count: usize) -> String {
+        self.test_results.iter()
+            .rev()
+            .take(max_count)
+            .map(|result| {
+                let text = format!("{} ({})", result.test_name, result.verdict);
+                if result.verdict.is_success() { text.green().to_string() } else { text.red().to_string() }
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
     pub(crate) fn add_success(&mut self, metrics: &ExecutionMetrics, test_name: &str) {
         self.processed += 1;
         self.success += 1;
+        self.test_results.push(TestResult {
+            test_name: test_name.to_string(),
+            verdict: if self.generate_mode { Verdict::Successful } else { Verdict::Correct },
+            time: metrics.time,
+            cpu_time: metrics.cpu_time,
+            memory_kibibytes: metrics.memory_kibibytes,
+        });
         self.add_metrics(metrics, test_name);
     }
 
-    pub(crate) fn add_test_error(&mut self, error: TestError, test_name: String) {
+    pub(crate) fn add_test_error(&mut self, error: TestError, test_name: String, metrics: &ExecutionMetrics) {
         match &error {
             Incorrect { .. } => { self.incorrect += 1 }
+            PresentationError { .. } => { self.presentation_error += 1 }
             ProgramError { error: ExecutionError::TimedOut, .. } => { self.timed_out += 1 }
             ProgramError { error: ExecutionError::MemoryLimitExceeded, .. } => { self.memory_limit_exceeded += 1 }
             ProgramError { error: ExecutionError::RuntimeError(_), .. } => { self.runtime_error += 1 }
             ProgramError { error: ExecutionError::Sio2jailError(_), .. } => { self.sio2jail_error += 1 }
+            ProgramError { error: ExecutionError::ExecutorError(_), .. } => { self.executor_error += 1 }
             ProgramError { error: ExecutionError::IncorrectCheckerFormat(_), .. } => { self.checker_error += 1 }
             ProgramError { error: ExecutionError::PipeError } => { self.invalid_output += 1 }
             ProgramError { error: ExecutionError::OutputNotUtf8 } => { self.invalid_output += 1 }
             CheckerError { .. } => { self.checker_error += 1 }
-            NoOutputFile { .. } => { self.no_output_file += 1 }
-            Cancelled => return,
+            NoOutputFile => { self.no_output_file += 1 }
+            InvalidTestFile { .. } => { self.invalid_test_file += 1 }
+            Cancelled | ProgramError { error: ExecutionError::Interrupted } => return,
         }
         self.processed += 1;
-        self.test_errors.push((test_name, error));
+        self.test_results.push(TestResult {
+            test_name: test_name.clone(),
+            verdict: error.verdict(),
+            time: metrics.time,
+            cpu_time: metrics.cpu_time,
+            memory_kibibytes: metrics.memory_kibibytes,
+        });
+        let verdict = error.verdict();
+        let error = self.store_error(error);
+        self.test_errors.push(FailedTest { test_name, verdict, metrics: *metrics, error });
+    }
+
+    /// Keeps the error in memory, or moves it to a temporary file if the errors already take up too much memory
+    fn store_error(&mut self, error: TestError) -> StoredError {
+        let size = match &error {
+            Incorrect { error } | PresentationError { error } => error.size(),
+            _ => 0,
+        };
+        if self.errors_in_memory_size + size <= MAX_ERRORS_IN_MEMORY {
+            self.errors_in_memory_size += size;
+            return StoredError::InMemory(error);
+        }
+
+        let file = self.spilled_errors.get_or_insert_with(|| {
+            Arc::new(create_temp_file().expect("Failed to create the file with the errors"))
+        });
+        let mut writer = file.as_ref();
+        let offset = writer.seek(SeekFrom::End(0)).expect("Failed to seek the file with the errors");
+        let serialized = serde_json::to_vec(&error).expect("Failed to serialize the error");
+        writer.write_all(&serialized).expect("Failed to write the file with the errors");
+        StoredError::Spilled { file: file.clone(), offset, length: serialized.len() }
+    }
+
+    /// Whether a test with `value` should replace `current` as the extreme one.
+    /// Ties go to the test that comes first in natural order, so that the summary doesn't depend on the order in which the tests finished
+    fn replaces<T: Ord>(current: &Option<(T, String)>, value: &T, test_name: &str) -> bool {
+        match current {
+            None => true,
+            Some((current_value, current_name)) => value.cmp(current_value)
+                .then_with(|| human_sort::compare(current_name, test_name))
+                .is_gt(),
+        }
     }
 
     fn add_metrics(&mut self, metrics: &ExecutionMetrics, test_name: &str) {
         if let Some(new_time) = &metrics.time {
-            if self.slowest_test.as_ref().is_none_or(|(time, _)| new_time > time) {
+            if Self::replaces(&self.slowest_test, new_time, test_name) {
                 self.slowest_test = Some((*new_time, test_name.to_string()));
             }
         }
 
         if let Some(new_memory) = &metrics.memory_kibibytes {
-            if self.most_memory_used.as_ref().is_none_or(|(memory, _)| new_memory > memory) {
+            if Self::replaces(&self.most_memory_used, new_memory, test_name) {
                 self.most_memory_used = Some((*new_memory, test_name.to_string()));
             }
         }
@@ -134,12 +297,15 @@ impl TestSummary {
         [
             CountPart::new(self.success, if self.generate_mode { "successful" } else { "correct" }).display_empty().with_color(Green),
             CountPart::new(self.incorrect, "wrong answer").with_plural("wrong answers"),
+            CountPart::new(self.presentation_error, "presentation error").with_plural("presentation errors"),
             CountPart::new(self.timed_out, "timed out"),
             CountPart::new(self.invalid_output, "invalid output").with_plural("invalid outputs"),
             CountPart::new(self.memory_limit_exceeded, "out of memory"),
             CountPart::new(self.runtime_error, "runtime error").with_plural("runtime errors"),
             CountPart::new(self.no_output_file, "without output file"),
+            CountPart::new(self.invalid_test_file, "invalid test file").with_plural("invalid test files"),
             CountPart::new(self.sio2jail_error, "sio2jail error").with_plural("sio2jail errors"),
+            CountPart::new(self.executor_error, "executor error").with_plural("executor errors"),
             CountPart::new(self.checker_error, "checker error").with_plural("checker errors").with_color(Blue),
             CountPart::new(if show_not_finished { self.total - self.processed } else { 0 }, "not finished").with_color(Yellow),
         ]
@@ -152,9 +318,39 @@ impl TestSummary {
             .join(", ")
     }
 
-    pub(crate) fn get_errors(&mut self) -> &Vec<(String, TestError)> {
+    /// Returns the times of the successful tests
+    pub(crate) fn successful_test_times(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.test_results.iter()
+            .filter(|result| result.verdict.is_success())
+            .filter_map(|result| result.time.map(|time| (result.test_name.as_str(), time)))
+    }
+
+    pub(crate) fn runtime_statistics(&self) -> Option<RuntimeStatistics> {
+        let mut times: Vec<Duration> = self.successful_test_times().map(|(_, time)| time).collect();
+        if times.is_empty() {
+            return None;
+        }
+
+        times.sort();
+        let total: Duration = times.iter().sum();
+        // Nearest-rank percentile
+        let percentile = |percent: usize| times[(times.len() * percent).div_ceil(100).max(1) - 1];
+
+        Some(RuntimeStatistics {
+            mean: total / times.len() as u32,
+            median: percentile(50),
+            percentile_95: percentile(95),
+            total,
+            total_cpu_time: self.test_results.iter()
+                .filter(|result| result.verdict.is_success() && result.time.is_some())
+                .map(|result| result.cpu_time)
+                .sum(),
+        })
+    }
+
+    pub(crate) fn get_errors(&mut self) -> &Vec<FailedTest> {
         self.test_errors.sort_by(|a, b| -> Ordering {
-            human_sort::compare(&a.0, &b.0)
+            human_sort::compare(&a.test_name, &b.test_name)
         });
         &self.test_errors
     }
@@ -1,76 +1,493 @@
 use std::cmp::max;
 use std::fs;
-use std::io::{Read, read_to_string};
-use std::path::Path;
-use comfy_table::{Attribute, Cell, Color, Table};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use comfy_table::{Attribute, Cell, Color};
+use colored::Colorize;
 use comfy_table::ContentArrangement::Dynamic;
 use terminal_size::{Height, Width};
-use crate::test_errors::TestError;
-use crate::test_errors::TestError::{Incorrect, NoOutputFile};
+use crate::comparator::{same_tokens, Comparator};
+use crate::compression::{compressed_paths, open_decompressed};
+use crate::diff_tool::DiffTool;
+use crate::file_contents::FileContents;
+use crate::output::create_table;
+use crate::test_errors::{OutputDifference, TestError};
+use crate::test_errors::TestError::{Incorrect, InvalidTestFile, NoOutputFile, PresentationError};
 
-pub(crate) fn compare_output(expected_output_path: &Path, actual_output: impl Read) -> Result<(), TestError> {
-	if !expected_output_path.is_file() {
+pub(crate) const BYTE_ORDER_MARK: char = '\u{feff}';
+
+/// Output files larger than this are compared by hashing instead of being loaded into memory
+pub(crate) const HASH_COMPARISON_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// The extension of files containing the SHA-256 hash of the expected output, which can be used instead of the output file
+pub(crate) const HASH_FILE_EXT: &str = ".sha256";
+
+/// Whether the differences are described without showing the contents of the output files, set with --redact-diff
+static DIFFS_REDACTED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn redact_diffs() {
+	DIFFS_REDACTED.store(true, Relaxed);
+}
+
+pub(crate) fn diffs_redacted() -> bool {
+	DIFFS_REDACTED.load(Relaxed)
+}
+
+/// How line endings and byte order marks are treated when comparing outputs
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum LineEndings {
+	/// Carriage returns at the ends of lines are ignored
+	Lenient,
+	/// CRLF line endings and byte order marks are removed from the input files, the output files and the program's output
+	Normalize,
+	/// Carriage returns and byte order marks are compared like any other character
+	Strict,
+}
+
+/// How strictly whitespace is compared by the lines comparator
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct WhitespaceRules {
+	/// Whitespace at the ends of lines and empty lines at the end count as differences
+	pub(crate) strict: bool,
+	/// The program's output has to end with a newline
+	pub(crate) require_trailing_newline: bool,
+}
+
+fn find_output_files_with_ext(output_directory: &Path, test_name: &str, output_ext: &str) -> Vec<PathBuf> {
+	let main_output = output_directory.join(format!("{}{}", test_name, output_ext));
+	let hash_file = output_directory.join(format!("{}{}{}", test_name, output_ext, HASH_FILE_EXT));
+	let main_output = if main_output.is_file() {
+		main_output
+	} else {
+		let replacement = [hash_file].into_iter()
+			.chain(compressed_paths(&main_output))
+			.find(|path| path.is_file());
+		replacement.unwrap_or(main_output)
+	};
+	let mut result = vec![main_output];
+
+	for i in 2.. {
+		let alternative_output = output_directory.join(format!("{}{}.{}", test_name, output_ext, i));
+		if !alternative_output.is_file() {
+			break;
+		}
+		result.push(alternative_output);
+	}
+
+	result
+}
+
+/// Returns the paths of the output files accepted for a test.
+///
+/// The first one is always the main output file (e.g. `test1.out`), followed by
+/// the existing alternative outputs numbered from 2 (`test1.out.2`, `test1.out.3`, ...).
+/// The alternatives are only searched for until the first missing number.
+/// If the main output file doesn't exist, but a file with its SHA-256 hash (e.g. `test1.out.sha256`)
+/// or a compressed version of it (e.g. `test1.out.gz`) does, that file is returned in its place.
+///
+/// If there are no output files with `output_ext`, the fallback extensions are tried in order
+/// and the files for the first one that has any are returned instead.
+pub(crate) fn find_output_files(output_directory: &Path, test_name: &str, output_ext: &str, fallback_exts: &[String]) -> Vec<PathBuf> {
+	let main_files = find_output_files_with_ext(output_directory, test_name, output_ext);
+	if main_files.iter().any(|path| path.is_file()) {
+		return main_files;
+	}
+
+	fallback_exts.iter()
+		.map(|ext| find_output_files_with_ext(output_directory, test_name, ext))
+		.find(|files| files.iter().any(|path| path.is_file()))
+		.unwrap_or(main_files)
+}
+
+/// Checks if the path is a file with the hash of an output file
+pub(crate) fn is_hash_file(path: &Path) -> bool {
+	path.to_string_lossy().ends_with(HASH_FILE_EXT)
+}
+
+/// How outputs are hashed to compare them without loading them into memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputHashing {
+	/// The whole output is hashed byte for byte
+	Exact,
+	/// Whitespace at the ends of lines and empty lines at the end are skipped and every line ends with a newline,
+	/// like they're compared by the lines comparator. For an output file without trailing whitespace that ends
+	/// with a newline, this is the same as the hash of the whole file
+	Lines,
+}
+
+impl OutputHashing {
+	fn hash(self, output: impl Read) -> String {
+		let mut reader = BufReader::new(output);
+		let mut hasher = Sha256::new();
+		let mut line = vec![];
+		let mut pending_empty_lines = 0;
+		loop {
+			line.clear();
+			if reader.read_until(b'\n', &mut line).expect("Failed to read output") == 0 {
+				break;
+			}
+			if self == OutputHashing::Exact {
+				hasher.update(&line);
+				continue;
+			}
+			let trimmed = line.trim_ascii_end();
+			if trimmed.is_empty() {
+				pending_empty_lines += 1;
+				continue;
+			}
+			for _ in 0..pending_empty_lines {
+				hasher.update(b"\n");
+			}
+			pending_empty_lines = 0;
+			hasher.update(trimmed);
+			hasher.update(b"\n");
+		}
+		hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+	}
+}
+
+/// Returns the hash of an output file that is too large to be loaded into memory,
+/// or the hash given in a hash file, whose first word is the SHA-256 hash of the output file (like in the output of sha256sum)
+fn expected_hash(expected_output_path: &Path, hashing: OutputHashing) -> Result<String, TestError> {
+	if is_hash_file(expected_output_path) {
+		let contents = fs::read_to_string(expected_output_path)
+			.map_err(|error| InvalidTestFile { error: format!("Failed to read {}: {}", expected_output_path.display(), error) })?;
+		Ok(contents.split_whitespace().next().unwrap_or("").to_lowercase())
+	} else {
+		Ok(hashing.hash(open_decompressed(expected_output_path).map_err(|error| InvalidTestFile { error })?))
+	}
+}
+
+/// Compares the program's output with the accepted output files.
+///
+/// The test is correct if the output matches any of them.
+/// If it doesn't, the returned diff is generated against the first one,
+/// using the diff tool if one is provided. If the output has the same tokens
+/// as any of the output files, it's reported as a presentation error instead.
+///
+/// Hash files are compared with the hash of the output, using the hashing of the comparator
+/// or [`OutputHashing::Lines`] if it doesn't have one. Output files larger than [`HASH_COMPARISON_THRESHOLD`]
+/// are compared by hashing both outputs too, but only if the comparator can be replaced by hashing.
+/// No diff is shown for the hashed outputs.
+pub(crate) fn compare_output(test_name: &str, expected_output_paths: &[PathBuf], mut actual_output: File, diff_tool: Option<&DiffTool>, comparator: &dyn Comparator) -> Result<(), TestError> {
+	let existing_paths: Vec<&PathBuf> = expected_output_paths.iter().filter(|path| path.is_file()).collect();
+	if existing_paths.is_empty() {
 		return Err(NoOutputFile);
 	}
-	let expected_output = fs::read_to_string(expected_output_path).expect("Failed to read output file");
-	let actual_output = read_to_string(actual_output).expect("Failed to read actual input");
+	let comparator_hashing = comparator.hashing();
+	let (hashed_paths, existing_paths): (Vec<&PathBuf>, Vec<&PathBuf>) = existing_paths.into_iter().partition(|path| {
+		is_hash_file(path) || (comparator_hashing.is_some() && fs::metadata(path).is_ok_and(|metadata| metadata.len() > HASH_COMPARISON_THRESHOLD))
+	});
 
-	let expected_output = split_trim_end(&expected_output);
-	let actual_output = split_trim_end(&actual_output);
+	let mut first_diff = None;
+	if !hashed_paths.is_empty() {
+		let hashing = comparator_hashing.unwrap_or(OutputHashing::Lines);
+		actual_output.rewind().expect("Failed to seek output");
+		let actual_hash = hashing.hash(&actual_output);
+		for expected_output_path in hashed_paths {
+			if expected_hash(expected_output_path, hashing)? == actual_hash {
+				return Ok(());
+			}
+			first_diff.get_or_insert(format!("The hash of the output differs from the hash of {}", expected_output_path.display()).red().to_string().into());
+		}
+	}
+	if existing_paths.is_empty() {
+		return Err(Incorrect { error: first_diff.expect("At least one output file should have been compared") });
+	}
+
+	let actual_output = FileContents::read(actual_output).expect("Failed to read actual input");
+	let mut presentation_error = false;
+	for expected_output_path in existing_paths {
+		let expected_output = open_decompressed(expected_output_path)
+			.and_then(|file| FileContents::read(file).map_err(|error| format!("Failed to read {}: {}", expected_output_path.display(), error)))
+			.map_err(|error| InvalidTestFile { error })?;
+		let Err(builtin_diff) = comparator.compare(expected_output_path, &expected_output, &actual_output) else {
+			return Ok(());
+		};
+		presentation_error |= same_tokens(&expected_output, &actual_output);
+		if first_diff.is_none() {
+			first_diff = Some(match diff_tool {
+				Some(diff_tool) => diff_tool.diff(test_name, expected_output_path, &actual_output, || builtin_diff),
+				None => builtin_diff,
+			});
+		}
+	}
 
-	if actual_output != expected_output {
-		return Err(Incorrect { error: generate_diff(&expected_output, &actual_output) });
+	let error = first_diff.expect("At least one output file should have been compared");
+	if presentation_error {
+		Err(PresentationError { error })
+	} else {
+		Err(Incorrect { error })
 	}
-	Ok(())
 }
 
-fn split_trim_end(to_split: &str) -> Vec<&str> {
+fn split_trim_end(to_split: &str, line_endings: LineEndings, strict_whitespace: bool) -> Vec<&str> {
+	let to_split = match line_endings {
+		LineEndings::Normalize => to_split.strip_prefix(BYTE_ORDER_MARK).unwrap_or(to_split),
+		LineEndings::Lenient | LineEndings::Strict => to_split,
+	};
 	let mut res = to_split
 		.split('\n')
-		.map(|line| line.trim_end())
+		.map(|line| match (line_endings, strict_whitespace) {
+			(LineEndings::Strict, false) => line.trim_end_matches(|char: char| char.is_whitespace() && char != '\r'),
+			(LineEndings::Strict, true) => line,
+			(LineEndings::Lenient | LineEndings::Normalize, false) => line.trim_end(),
+			(LineEndings::Lenient | LineEndings::Normalize, true) => line.strip_suffix('\r').unwrap_or(line),
+		})
 		.collect::<Vec<&str>>();
 
-	while res.last().is_some_and(|last| last.trim().is_empty()) {
-		res.pop();
+	if strict_whitespace {
+		// Only the newline ending the last line is ignored
+		if res.last().is_some_and(|last| last.is_empty()) {
+			res.pop();
+		}
+	} else {
+		while res.last().is_some_and(|last| last.is_empty()) {
+			res.pop();
+		}
 	}
 
 	res
 }
 
-fn generate_diff(expected_split: &[&str], actual_split: &[&str]) -> String {
-	let (Width(w), Height(_)) = terminal_size::terminal_size().unwrap_or((Width(40), Height(0)));
-	let mut table = Table::new();
-	table.set_content_arrangement(Dynamic).set_width(w).set_header(vec![
-		Cell::new("Line").add_attribute(Attribute::Bold),
-		Cell::new("Output file").add_attribute(Attribute::Bold).fg(Color::Green),
-		Cell::new("Your program's output").add_attribute(Attribute::Bold).fg(Color::Red)
-	]);
+/// Splits a line into alternating whitespace and non-whitespace parts
+fn split_tokens(line: &str) -> Vec<&str> {
+	let mut tokens = vec![];
+	let mut token_start = 0;
+	let mut previous_whitespace = None;
+	for (index, char) in line.char_indices() {
+		let whitespace = char.is_whitespace();
+		if previous_whitespace.is_some_and(|previous| previous != whitespace) {
+			tokens.push(&line[token_start..index]);
+			token_start = index;
+		}
+		previous_whitespace = Some(whitespace);
+	}
+	if token_start < line.len() {
+		tokens.push(&line[token_start..]);
+	}
+	tokens
+}
 
-	let mut row_count = 0;
-	for i in 0..max(expected_split.len(), actual_split.len()) {
-		let expected_line = expected_split.get(i).unwrap_or(&"");
-		let actual_line = actual_split.get(i).unwrap_or(&"");
+/// Returns which of the words in `words` differ from the ones in `other`.
+///
+/// If both lines have the same number of words they are compared position by position,
+/// otherwise everything between the common prefix and the common suffix is marked as different
+fn differing_words(words: &[&str], other: &[&str]) -> Vec<bool> {
+	if words.len() == other.len() {
+		return words.iter().zip(other).map(|(word, other_word)| word != other_word).collect();
+	}
 
-		if expected_line != actual_line {
-			table.add_row(vec![
-				Cell::new(i + 1),
-				Cell::new(expected_line).fg(Color::Green),
-				Cell::new(actual_line).fg(Color::Red)
-			]);
+	let common_prefix = words.iter().zip(other).take_while(|(word, other_word)| word == other_word).count();
+	let common_suffix = words.iter().rev().zip(other.iter().rev())
+		.take_while(|(word, other_word)| word == other_word)
+		.count()
+		.min(words.len().min(other.len()) - common_prefix);
+	(0..words.len()).map(|index| index >= common_prefix && index < words.len() - common_suffix).collect()
+}
 
-			row_count += 1;
+/// Colors `line` and additionally highlights the words that differ from the ones in `other`
+fn highlight_differences(line: &str, other: &str, color: colored::Color) -> String {
+	let tokens = split_tokens(line);
+	let words: Vec<&str> = tokens.iter().copied().filter(|token| !token.starts_with(char::is_whitespace)).collect();
+	let other_words: Vec<&str> = split_tokens(other).into_iter().filter(|token| !token.starts_with(char::is_whitespace)).collect();
+	let mut differing = differing_words(&words, &other_words).into_iter();
+
+	tokens.into_iter().map(|token| {
+		if token.starts_with(char::is_whitespace) {
+			return token.color(color).to_string();
+		}
+		if differing.next().unwrap_or(true) {
+			token.color(color).bold().underline().to_string()
+		} else {
+			token.color(color).to_string()
 		}
+	}).collect()
+}
 
-		if row_count >= 99 {
+/// Compares the outputs line by line, ignoring whitespace at the ends of lines and empty lines at the end
+/// unless the whitespace rules are strict, returning a diff with the given column headers if they differ
+pub(crate) fn compare_lines(expected_output: &str, actual_output: &str, line_endings: LineEndings, whitespace: WhitespaceRules, headers: (&str, &str)) -> Result<(), OutputDifference> {
+	if whitespace.require_trailing_newline && !actual_output.is_empty() && !actual_output.ends_with('\n') {
+		return Err("The output doesn't end with a newline".red().to_string().into());
+	}
+	let expected_split = split_trim_end(expected_output, line_endings, whitespace.strict);
+	let actual_split = split_trim_end(actual_output, line_endings, whitespace.strict);
+	if expected_split == actual_split {
+		return Ok(());
+	}
+	Err(OutputDifference::Lines(LineDiff::new(&expected_split, &actual_split, headers)))
+}
+
+/// The lines that differ between two outputs, kept so that the diff table can be rendered only when it's shown
+#[derive(Clone, Hash, Serialize, Deserialize)]
+pub struct LineDiff {
+	headers: (String, String),
+	/// The numbers of the differing lines, along with their expected and actual contents
+	lines: Vec<(usize, String, String)>,
+	/// Whether more lines differ than the ones that were kept
+	truncated: bool,
+}
+
+impl LineDiff {
+	/// At most this many differing lines are shown
+	const MAX_LINES: usize = 99;
+
+	fn new(expected_split: &[&str], actual_split: &[&str], (expected_header, actual_header): (&str, &str)) -> Self {
+		let mut differing = (0..max(expected_split.len(), actual_split.len()))
+			.map(|i| (i + 1, *expected_split.get(i).unwrap_or(&""), *actual_split.get(i).unwrap_or(&"")))
+			.filter(|(_, expected_line, actual_line)| expected_line != actual_line);
+		let lines = differing.by_ref()
+			.take(Self::MAX_LINES)
+			.map(|(line, expected_line, actual_line)| (line, expected_line.to_string(), actual_line.to_string()))
+			.collect();
+		LineDiff {
+			headers: (expected_header.to_string(), actual_header.to_string()),
+			lines,
+			truncated: differing.next().is_some(),
+		}
+	}
+
+	pub(crate) fn size(&self) -> usize {
+		self.lines.iter().map(|(_, expected_line, actual_line)| expected_line.len() + actual_line.len()).sum()
+	}
+
+	pub(crate) fn render(&self) -> String {
+		if diffs_redacted() {
+			return self.render_redacted();
+		}
+
+		let (Width(w), Height(_)) = terminal_size::terminal_size().unwrap_or((Width(40), Height(0)));
+		let mut table = create_table();
+		table.set_content_arrangement(Dynamic).set_width(w).set_header(vec![
+			Cell::new("Line").add_attribute(Attribute::Bold),
+			Cell::new(&self.headers.0).add_attribute(Attribute::Bold).fg(Color::Green),
+			Cell::new(&self.headers.1).add_attribute(Attribute::Bold).fg(Color::Red)
+		]);
+
+		for (line, expected_line, actual_line) in &self.lines {
+			table.add_row(vec![
+				Cell::new(line),
+				Cell::new(highlight_differences(expected_line, actual_line, colored::Color::Green)),
+				Cell::new(highlight_differences(actual_line, expected_line, colored::Color::Red)),
+			]);
+		}
+		if self.truncated {
 			table.add_row(vec![
 				Cell::new("..."),
 				Cell::new("..."),
 				Cell::new("...")
 			]);
+		}
 
+		table.to_string().replace('\r', "")
+	}
+
+	/// Renders only the numbers of the differing lines and the positions of the differing characters in them
+	fn render_redacted(&self) -> String {
+		let mut table = create_table();
+		table.set_header(vec![
+			Cell::new("Line").add_attribute(Attribute::Bold),
+			Cell::new("Differing characters").add_attribute(Attribute::Bold).fg(Color::Red),
+		]);
+
+		for (line, expected_line, actual_line) in &self.lines {
+			table.add_row(vec![
+				Cell::new(line),
+				Cell::new(format_positions(&differing_positions(expected_line, actual_line))),
+			]);
+		}
+		if self.truncated {
+			table.add_row(vec![
+				Cell::new("..."),
+				Cell::new("...")
+			]);
+		}
+
+		table.to_string()
+	}
+}
+
+/// Returns the ranges of the (1-based) positions of the characters that differ between the lines,
+/// including the ones past the end of the shorter line
+fn differing_positions(line: &str, other: &str) -> Vec<(usize, usize)> {
+	let mut chars = line.chars();
+	let mut other_chars = other.chars();
+	let mut ranges: Vec<(usize, usize)> = vec![];
+	for position in 1.. {
+		let (char, other_char) = (chars.next(), other_chars.next());
+		if char.is_none() && other_char.is_none() {
 			break;
 		}
+		if char == other_char {
+			continue;
+		}
+		match ranges.last_mut() {
+			Some((_, end)) if *end == position - 1 => *end = position,
+			_ => ranges.push((position, position)),
+		}
 	}
+	ranges
+}
+
+fn format_positions(ranges: &[(usize, usize)]) -> String {
+	/// At most this many ranges of positions are listed for a line
+	const MAX_RANGES: usize = 10;
 
-	table.to_string().replace('\r', "")
+	let mut result = ranges.iter()
+		.take(MAX_RANGES)
+		.map(|(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+		.collect::<Vec<String>>()
+		.join(", ");
+	if ranges.len() > MAX_RANGES {
+		result.push_str(&format!(" and {} more", ranges.len() - MAX_RANGES));
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sha256(data: &str) -> String {
+		Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+	}
+
+	#[test]
+	fn hashes_outputs_like_the_lines_comparator() {
+		let expected = OutputHashing::Lines.hash("1 2\n\n3\n".as_bytes());
+		assert_eq!(expected, sha256("1 2\n\n3\n"));
+		assert_eq!(OutputHashing::Lines.hash("1 2  \r\n\n3".as_bytes()), expected);
+		assert_eq!(OutputHashing::Lines.hash("1 2\n\n3\n\n\n".as_bytes()), expected);
+		assert_ne!(OutputHashing::Lines.hash("1 2\n3\n".as_bytes()), expected);
+	}
+
+	#[test]
+	fn hashes_outputs_exactly() {
+		assert_eq!(OutputHashing::Exact.hash("1 2 \n3".as_bytes()), sha256("1 2 \n3"));
+		assert_ne!(OutputHashing::Exact.hash("1 2 \n3".as_bytes()), OutputHashing::Exact.hash("1 2\n3\n".as_bytes()));
+	}
+
+	#[test]
+	fn finds_the_differing_positions() {
+		assert_eq!(differing_positions("1 2 3", "1 2 3"), vec![]);
+		assert_eq!(differing_positions("1 2 3", "1 5 3"), vec![(3, 3)]);
+		assert_eq!(differing_positions("123 456", "129 406"), vec![(3, 3), (6, 6)]);
+		assert_eq!(differing_positions("12345", "19995"), vec![(2, 4)]);
+		assert_eq!(differing_positions("1 2", "1 2 3"), vec![(4, 5)]);
+		assert_eq!(differing_positions("", "ab"), vec![(1, 2)]);
+		assert_eq!(differing_positions("żółw", "żólw"), vec![(3, 3)]);
+	}
+
+	#[test]
+	fn formats_the_positions() {
+		assert_eq!(format_positions(&[(3, 3)]), "3");
+		assert_eq!(format_positions(&[(2, 4), (7, 7)]), "2-4, 7");
+		let ranges = (1..=12).map(|position| (position * 2, position * 2)).collect::<Vec<(usize, usize)>>();
+		assert_eq!(format_positions(&ranges), "2, 4, 6, 8, 10, 12, 14, 16, 18, 20 and 2 more");
+	}
 }
@@ -0,0 +1,111 @@
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+#[cfg(target_os = "linux")]
+use crate::cpu_affinity::pin_current_thread;
+
+/// The threads checking the outputs of the tests, or `None` if the testing threads check them themselves
+static CHECKING_POOL: OnceLock<Option<ThreadPool>> = OnceLock::new();
+
+/// How many outputs can wait to be checked for every checking thread
+const QUEUED_CHECKS_PER_THREAD: usize = 2;
+
+/// Splits the CPUs between the threads running the tests and the ones checking their outputs, returning their numbers.
+///
+/// Checking is usually much faster than running the tests, so it gets a quarter of the CPUs.
+/// With fewer CPUs than that needs (and when testing sequentially, so that the results are printed in order)
+/// the testing threads check every output before running the next test, so that checkers never take a CPU from a tested program
+fn thread_counts(cpus: usize, sequential: bool) -> (usize, usize) {
+    if sequential {
+        return (1, 0);
+    }
+    let checking_threads = cpus / 4;
+    (cpus - checking_threads, checking_threads)
+}
+
+/// Creates the global thread pool running the tests and the pool checking their outputs.
+///
+/// If `pinned_cpus` are given, the threads of both pools are only run on them, every thread on its own CPU
+pub(crate) fn build_thread_pools(sequential: bool, pinned_cpus: Option<Vec<usize>>) -> Result<(), String> {
+    let cpus = pinned_cpus.as_ref().map_or_else(|| thread::available_parallelism().map_or(1, |cpus| cpus.get()), Vec::len);
+    let (testing_threads, checking_threads) = thread_counts(cpus, sequential);
+    let testing_pool = ThreadPoolBuilder::new().num_threads(testing_threads);
+    let checking_pool = ThreadPoolBuilder::new().num_threads(checking_threads);
+    #[cfg(target_os = "linux")]
+    let (testing_pool, checking_pool) = match &pinned_cpus {
+        Some(cpus) => {
+            let (testing_cpus, checking_cpus) = (cpus[..testing_threads].to_vec(), cpus[testing_threads..].to_vec());
+            (
+                testing_pool.start_handler(move |index| pin_current_thread(testing_cpus[index])),
+                checking_pool.start_handler(move |index| pin_current_thread(checking_cpus[index])),
+            )
+        }
+        None => (testing_pool, checking_pool),
+    };
+
+    // Without pinning, running with the default thread pool is fine if it was already created
+    if let Err(error) = testing_pool.build_global() {
+        if pinned_cpus.is_some() {
+            return Err(format!("Failed to create the testing threads: {}", error));
+        }
+    }
+    let checking_pool = (checking_threads > 0)
+        .then(|| checking_pool.build())
+        .transpose()
+        .map_err(|error| format!("Failed to create the checking threads: {}", error))?;
+    let _ = CHECKING_POOL.set(checking_pool);
+    Ok(())
+}
+
+/// The pool checking the outputs of the tests, if they aren't checked by the testing threads
+pub(crate) fn checking_pool() -> Option<&'static ThreadPool> {
+    CHECKING_POOL.get().and_then(Option::as_ref)
+}
+
+/// Limits how many outputs can wait to be checked, so that the testing threads don't get far ahead
+/// of the checking ones and keep the outputs of many tests around
+pub(crate) struct CheckingQueue {
+    queued: Mutex<usize>,
+    dequeued: Condvar,
+    limit: usize,
+}
+
+impl CheckingQueue {
+    pub(crate) fn new(pool: &ThreadPool) -> Self {
+        CheckingQueue { queued: Mutex::new(0), dequeued: Condvar::new(), limit: pool.current_num_threads() * QUEUED_CHECKS_PER_THREAD }
+    }
+
+    /// Blocks until there's room for another output in the queue, which is freed when the returned guard is dropped
+    pub(crate) fn enqueue(&self) -> QueuedCheck<'_> {
+        let queued = self.queued.lock().expect("Failed to lock the checking queue");
+        let mut queued = self.dequeued.wait_while(queued, |queued| *queued >= self.limit).expect("Failed to lock the checking queue");
+        *queued += 1;
+        QueuedCheck { queue: self }
+    }
+}
+
+/// An output waiting to be checked, see [`CheckingQueue::enqueue`]
+pub(crate) struct QueuedCheck<'a> {
+    queue: &'a CheckingQueue,
+}
+
+impl Drop for QueuedCheck<'_> {
+    fn drop(&mut self) {
+        *self.queue.queued.lock().expect("Failed to lock the checking queue") -= 1;
+        self.queue.dequeued.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::thread_counts;
+
+    #[test]
+    fn splits_the_cpus() {
+        assert_eq!(thread_counts(1, false), (1, 0));
+        assert_eq!(thread_counts(3, false), (3, 0));
+        assert_eq!(thread_counts(4, false), (3, 1));
+        assert_eq!(thread_counts(16, false), (12, 4));
+        assert_eq!(thread_counts(16, true), (1, 0));
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+/// Splits a value like `1.5s` into its numeric part and its unit
+fn split_unit(value: &str) -> (&str, &str) {
+    let value = value.trim();
+    let unit_start = value
+        .find(|char: char| !(char.is_ascii_digit() || char == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    (number, unit.trim())
+}
+
+/// Parses a duration such as `5`, `1.5s`, `750ms` or `2m`.
+///
+/// Values without a unit are treated as seconds
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = split_unit(value);
+    let number: f64 = number.parse()
+        .map_err(|_| format!("\"{}\" is not a valid duration", value))?;
+    let multiplier = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("\"{}\" is not a valid duration unit, use one of: ms, s, m, h", unit)),
+    };
+
+    Duration::try_from_secs_f64(number * multiplier)
+        .map_err(|_| format!("\"{}\" is not a valid duration", value))
+}
+
+/// Parses a memory amount such as `262144`, `256M` or `1GiB` into KiB.
+///
+/// Values without a unit are treated as KiB. All units are binary (1M = 1024K)
+pub(crate) fn parse_memory(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value);
+    let number: f64 = number.parse()
+        .map_err(|_| format!("\"{}\" is not a valid amount of memory", value))?;
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" | "k" | "kib" => 1.0,
+        "m" | "mib" => 1024.0,
+        "g" | "gib" => 1024.0 * 1024.0,
+        _ => return Err(format!("\"{}\" is not a valid memory unit, use one of: K, M, G", unit)),
+    };
+
+    let kibibytes = (number * multiplier).ceil();
+    if kibibytes < 1.0 || kibibytes > u64::MAX as f64 {
+        return Err(format!("\"{}\" is not a valid amount of memory", value));
+    }
+    Ok(kibibytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{parse_duration, parse_memory};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("750ms"), Ok(Duration::from_millis(750)));
+        assert_eq!(parse_duration(" 2m "), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1.2.3s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+
+    #[test]
+    fn parses_memory() {
+        assert_eq!(parse_memory("262144"), Ok(262144));
+        assert_eq!(parse_memory("256M"), Ok(256 * 1024));
+        assert_eq!(parse_memory("1GiB"), Ok(1024 * 1024));
+        assert_eq!(parse_memory("1.5k"), Ok(2));
+    }
+
+    #[test]
+    fn rejects_invalid_memory() {
+        assert!(parse_memory("0").is_err());
+        assert!(parse_memory("12T").is_err());
+        assert!(parse_memory("M").is_err());
+    }
+}
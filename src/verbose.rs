@@ -0,0 +1,23 @@
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+use colored::Colorize;
+
+/// How much toster prints about what it's doing, set with -v (1) or -vv (2)
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub(crate) fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Relaxed);
+}
+
+pub(crate) fn verbosity() -> u8 {
+    VERBOSITY.load(Relaxed)
+}
+
+/// Prints the message to stderr if the verbosity is at least `level`.
+///
+/// The message is only created when it's printed
+pub(crate) fn log(level: u8, message: impl FnOnce() -> String) {
+    if verbosity() >= level {
+        eprintln!("{}", message().bright_black());
+    }
+}
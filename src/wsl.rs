@@ -0,0 +1,45 @@
+use crate::compiler::Executable;
+
+/// The command running a command line inside of the default WSL distribution
+const WSL: [&str; 2] = ["wsl", "-e"];
+
+/// Translates a Windows path into the path it has inside of WSL, e.g. "C:\tests\a.cpp" becomes "/mnt/c/tests/a.cpp".
+/// Relative paths stay relative, since WSL starts in the translated current directory
+pub(crate) fn to_wsl_path(path: &str) -> String {
+    let path = path.strip_prefix(r"\\?\").unwrap_or(path).replace('\\', "/");
+    match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => {
+            format!("/mnt/{}{}", drive.to_ascii_lowercase() as char, &path[2..])
+        }
+        _ => path,
+    }
+}
+
+/// Makes the command run inside of WSL
+pub(crate) fn wrap_command(command: Vec<String>) -> Vec<String> {
+    WSL.iter().map(|part| part.to_string()).chain(command).collect()
+}
+
+/// Makes the executable (or script, with its interpreter) run inside of WSL.
+///
+/// Its path is translated with wslpath when it's run, so that the Windows path can still be used everywhere else
+pub(crate) fn bridge_executable(executable: Executable) -> Executable {
+    let interpreter = executable.interpreter.iter().flatten()
+        .map(|part| shlex::try_quote(part).expect("The interpreter command is invalid").to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+    let script = format!(r#"exec {} "$(wslpath -u "$0")" "$@""#, interpreter);
+    Executable {
+        path: executable.path,
+        interpreter: Some(wrap_command(vec!["sh".to_string(), "-c".to_string(), script])),
+    }
+}
+
+/// Makes the executable bridged with [`bridge_executable`] run through `wrapper`, a command inside of WSL
+/// that starts the program with the rest of its arguments
+pub(crate) fn run_through(executable: Executable, wrapper: Vec<String>) -> Executable {
+    let mut interpreter = executable.interpreter.expect("The executable doesn't run inside of WSL");
+    debug_assert!(interpreter.starts_with(&WSL.map(String::from)));
+    interpreter.splice(WSL.len()..WSL.len(), wrapper);
+    Executable { path: executable.path, interpreter: Some(interpreter) }
+}